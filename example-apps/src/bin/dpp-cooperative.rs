@@ -0,0 +1,50 @@
+//! Dining Philosophers Problem on the cooperative kernel
+
+use example_apps::dpp::{
+    philosopher_subscription, table_subscription, DppEvent, PhilosopherData, PhilosopherId, TableData,
+    PHILOSOPHER_STATES, TABLE_STATES,
+};
+use log::{self, info};
+use qlrl::{fsm::FiniteStateMachine, observer::LogObserver, MachineId};
+use std::{ops::ControlFlow, thread, time::Duration};
+
+use cooperative::{HostTicks, Kernel};
+
+/// Kernel time after which the example stops
+const RUN_TIME_MS: u64 = 10_000;
+
+fn main() {
+    env_logger::init();
+    info!("Start state machine runtime context using the cooperative kernel");
+
+    let mut philosophers = [PhilosopherId::Plato, PhilosopherId::Sokrates, PhilosopherId::Aristoteles].map(|id| {
+        FiniteStateMachine::try_new(&PHILOSOPHER_STATES, PhilosopherData::new(id))
+            .expect("Invalid philosopher states")
+            .cancel_timers_on_exit()
+            .with_observer(LogObserver::new(format!("{:?}", id)))
+    });
+    // the philosophers are added first, i.e. with the ids 0 to 2
+    let mut table = FiniteStateMachine::try_new(&TABLE_STATES, TableData::new([0, 1, 2].map(MachineId)))
+        .expect("Invalid table states")
+        .with_observer(LogObserver::new("Table"));
+
+    let mut kernel = Kernel::<DppEvent, 4, 16, 8>::new();
+    for philosopher in philosophers.iter_mut() {
+        kernel.add_subscribed(philosopher, 1, philosopher_subscription);
+    }
+    kernel.add_subscribed(&mut table, 2, table_subscription);
+
+    let mut elapsed_ms = 0;
+    kernel.run(&mut HostTicks::new(), |timeout| {
+        // sleep until the next timer expires, the host has no interrupts
+        let sleep_ms = timeout.unwrap_or(10).min(10);
+        thread::sleep(Duration::from_millis(sleep_ms));
+        elapsed_ms += sleep_ms;
+        if elapsed_ms < RUN_TIME_MS {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(())
+        }
+    });
+    info!("Stopped after {} ms", RUN_TIME_MS);
+}
@@ -0,0 +1,28 @@
+//! Dining Philosophers Problem state diagrams
+//!
+//! Prints the state lists as Mermaid (default), PlantUML (`plantuml`) or
+//! Graphviz DOT (`dot`) diagram.
+
+use std::{env, fmt::{self, Debug}};
+
+use example_apps::dpp::{PHILOSOPHER_STATES, TABLE_STATES};
+use qlrl::{diagram, State};
+
+fn render<D, E, S: PartialEq + Debug>(format: &str, state_list: &[State<D, E, S>], out: &mut String) -> fmt::Result {
+    match format {
+        "plantuml" => diagram::plantuml(state_list, out),
+        "dot" => diagram::dot(state_list, out),
+        _ => diagram::mermaid(state_list, out),
+    }
+}
+
+fn main() -> fmt::Result {
+    let format = env::args().nth(1).unwrap_or_default();
+    let mut out = String::new();
+    out.push_str("Philosopher:\n");
+    render(&format, &PHILOSOPHER_STATES, &mut out)?;
+    out.push_str("\nTable:\n");
+    render(&format, &TABLE_STATES, &mut out)?;
+    print!("{}", out);
+    Ok(())
+}
@@ -1,35 +1,31 @@
 //! Dining Philosophers Problem
 
-use example_apps::dpp::{DppEvent, PhilosopherData, PhilosopherId, TableData, PHILOSOPHER_STATES, TABLE_STATES};
+use example_apps::dpp::{
+    DppEvent, PhilosopherData, PhilosopherId, TableData, PHILOSOPHER_STATES, TABLE_SIGNALS, TABLE_STATES,
+};
 use log::{self, info};
-use qlrl::{fsm::FiniteStateMachine, StateMachine};
+use qlrl::{fsm::FiniteStateMachine, observer::LogObserver};
 
-use threads_on_host::ThreadedContext;
+use threads_on_host::{ShutdownError, ThreadedContext};
 
-fn main() {
+fn main() -> Result<(), ShutdownError> {
     env_logger::init();
-    info!("Start state machine runtime context using threads, channels and busses");
+    info!("Start state machine runtime context using threads and channels");
 
     let mut context = ThreadedContext::<DppEvent>::new();
-    context.add(Box::new(FiniteStateMachine::new(
-        &PHILOSOPHER_STATES,
-        PhilosopherData::new(PhilosopherId::Aristoteles),
-    )) as Box<dyn StateMachine<DppEvent> + Send + 'static>);
-
-    context.add(Box::new(FiniteStateMachine::new(
-        &PHILOSOPHER_STATES,
-        PhilosopherData::new(PhilosopherId::Plato),
-    )) as Box<dyn StateMachine<DppEvent> + Send + 'static>);
-
-    context.add(Box::new(FiniteStateMachine::new(
-        &PHILOSOPHER_STATES,
-        PhilosopherData::new(PhilosopherId::Sokrates),
-    )) as Box<dyn StateMachine<DppEvent> + Send + 'static>);
-
-    context.add(
-        Box::new(FiniteStateMachine::new(&TABLE_STATES, TableData::new()))
-            as Box<dyn StateMachine<DppEvent> + Send + 'static>,
-    );
-
-    context.run();
+    let philosophers = [PhilosopherId::Plato, PhilosopherId::Sokrates, PhilosopherId::Aristoteles].map(|id| {
+        let philosopher = FiniteStateMachine::try_new(&PHILOSOPHER_STATES, PhilosopherData::new(id))
+            .expect("Invalid philosopher states")
+            .cancel_timers_on_exit()
+            .with_observer(LogObserver::new(format!("{:?}", id)));
+        // philosophers receive the events sent to them only
+        context.add_subscribed_to(Box::new(philosopher), &[])
+    });
+
+    let table = FiniteStateMachine::try_new(&TABLE_STATES, TableData::new(philosophers))
+        .expect("Invalid table states")
+        .with_observer(LogObserver::new("Table"));
+    context.add_subscribed_to(Box::new(table), &TABLE_SIGNALS);
+
+    context.run()
 }
@@ -0,0 +1,32 @@
+//! Dining Philosophers Problem on tokio tasks
+
+use example_apps::dpp::{
+    philosopher_subscription, table_subscription, DppEvent, PhilosopherData, PhilosopherId, TableData,
+    PHILOSOPHER_STATES, TABLE_STATES,
+};
+use log::{self, info};
+use qlrl::{fsm::FiniteStateMachine, observer::LogObserver};
+
+use tokio_on_host::{ShutdownError, TokioContext};
+
+#[tokio::main]
+async fn main() -> Result<(), ShutdownError> {
+    env_logger::init();
+    info!("Start state machine runtime context using tokio tasks and channels");
+
+    let mut context = TokioContext::<DppEvent>::new();
+    let philosophers = [PhilosopherId::Plato, PhilosopherId::Sokrates, PhilosopherId::Aristoteles].map(|id| {
+        let philosopher = FiniteStateMachine::try_new(&PHILOSOPHER_STATES, PhilosopherData::new(id))
+            .expect("Invalid philosopher states")
+            .cancel_timers_on_exit()
+            .with_observer(LogObserver::new(format!("{:?}", id)));
+        context.add_subscribed(Box::new(philosopher), philosopher_subscription)
+    });
+
+    let table = FiniteStateMachine::try_new(&TABLE_STATES, TableData::new(philosophers))
+        .expect("Invalid table states")
+        .with_observer(LogObserver::new("Table"));
+    context.add_subscribed(Box::new(table), table_subscription);
+
+    context.run().await
+}
@@ -3,10 +3,11 @@
 //! Implementation example for Quantum Leaps Rust Like
 //!
 use log::{debug, info};
-use qlrl::{ProcessingResult, State, StateMachineContext};
+use qlrl::{state_machine, MachineId, ProcessingResult, Signal, StateMachineContext};
 
 //----------------------------------------------------------------------------
-// Type definitions for events, states, and state machine private data
+// Type definitions for events and state machine private data
+// (the state enums are generated along with the state lists below)
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PhilosopherId {
@@ -26,6 +27,34 @@ pub enum DppEvent {
     GrantRightFork(PhilosopherId),
 }
 
+/// Kinds of events, i.e. the events without the philosopher
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DppSignal {
+    RequestLeftFork,
+    RequestRightFork,
+    FinishEating,
+    ReleaseLeftFork,
+    ReleaseRightFork,
+    GrantLeftFork,
+    GrantRightFork,
+}
+
+impl Signal for DppEvent {
+    type Kind = DppSignal;
+
+    fn signal(&self) -> DppSignal {
+        match self {
+            DppEvent::RequestLeftFork(_) => DppSignal::RequestLeftFork,
+            DppEvent::RequestRightFork(_) => DppSignal::RequestRightFork,
+            DppEvent::FinishEating(_) => DppSignal::FinishEating,
+            DppEvent::ReleaseLeftFork(_) => DppSignal::ReleaseLeftFork,
+            DppEvent::ReleaseRightFork(_) => DppSignal::ReleaseRightFork,
+            DppEvent::GrantLeftFork(_) => DppSignal::GrantLeftFork,
+            DppEvent::GrantRightFork(_) => DppSignal::GrantRightFork,
+        }
+    }
+}
+
 #[derive(PartialEq, Debug)]
 pub struct PhilosopherData {
     id: PhilosopherId,
@@ -37,29 +66,39 @@ impl PhilosopherData {
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub enum PhilosopherState {
-    Think,
-    Hungry,
-    Eat,
-}
-
 #[derive(PartialEq, Debug)]
 pub struct TableData {
     forks_available: [bool; 3],
+    philosophers: [MachineId; 3], // indexed by PhilosopherId
 }
 
 impl TableData {
-    pub fn new() -> Self {
+    pub fn new(philosophers: [MachineId; 3]) -> Self {
         TableData {
             forks_available: [true; 3],
+            philosophers,
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub enum TableState {
-    Operational,
+//----------------------------------------------------------------------------
+// subscriptions i.e. the published events a state machine receives
+
+/// Kinds of published events the table handles
+pub const TABLE_SIGNALS: [DppSignal; 4] = [
+    DppSignal::RequestLeftFork,
+    DppSignal::RequestRightFork,
+    DppSignal::ReleaseLeftFork,
+    DppSignal::ReleaseRightFork,
+];
+
+/// Philosophers receive the events sent to them only
+pub fn philosopher_subscription(_event: &DppEvent) -> bool {
+    false
+}
+
+pub fn table_subscription(event: &DppEvent) -> bool {
+    TABLE_SIGNALS.contains(&event.signal())
 }
 
 //----------------------------------------------------------------------------
@@ -68,7 +107,7 @@ pub enum TableState {
 fn philosopher_think_entry<'a>(
     data: &'a mut PhilosopherData,
     context: &mut (dyn StateMachineContext<DppEvent> + 'a),
-) -> () {
+) {
     info!("Think: {:?}", data.id);
     debug!(
         "Publish {:?} after {} ms",
@@ -79,18 +118,12 @@ fn philosopher_think_entry<'a>(
 }
 
 fn philosopher_think_dispatch<'a>(
-    data: &'a mut PhilosopherData,
+    _data: &'a mut PhilosopherData,
     _context: &mut (dyn StateMachineContext<DppEvent> + 'a),
-    event: DppEvent,
-) -> ProcessingResult<PhilosopherState> {
+    event: &DppEvent,
+) -> ProcessingResult<PhilosopherData, DppEvent, PhilosopherState> {
     match event {
-        DppEvent::GrantLeftFork(philosopher) => {
-            if philosopher == data.id {
-                ProcessingResult::Transition(PhilosopherState::Hungry)
-            } else {
-                ProcessingResult::Ignored
-            }
-        }
+        DppEvent::GrantLeftFork(_) => ProcessingResult::Transition(PhilosopherState::Hungry),
         _ => ProcessingResult::Ignored,
     }
 }
@@ -98,25 +131,19 @@ fn philosopher_think_dispatch<'a>(
 fn philosopher_hungry_entry<'a>(
     data: &'a mut PhilosopherData,
     context: &mut (dyn StateMachineContext<DppEvent> + 'a),
-) -> () {
+) {
     info!("Hungry: {:?}", data.id);
     debug!("Publish {:?}", DppEvent::RequestRightFork(data.id),);
     context.publish_event(DppEvent::RequestRightFork(data.id));
 }
 
 fn philosopher_hungry_dispatch<'a>(
-    data: &'a mut PhilosopherData,
+    _data: &'a mut PhilosopherData,
     _context: &mut (dyn StateMachineContext<DppEvent> + 'a),
-    event: DppEvent,
-) -> ProcessingResult<PhilosopherState> {
+    event: &DppEvent,
+) -> ProcessingResult<PhilosopherData, DppEvent, PhilosopherState> {
     match event {
-        DppEvent::GrantRightFork(philosopher) => {
-            if philosopher == data.id {
-                ProcessingResult::Transition(PhilosopherState::Eat)
-            } else {
-                ProcessingResult::Ignored
-            }
-        }
+        DppEvent::GrantRightFork(_) => ProcessingResult::Transition(PhilosopherState::Eat),
         _ => ProcessingResult::Ignored,
     }
 }
@@ -124,19 +151,20 @@ fn philosopher_hungry_dispatch<'a>(
 fn philosopher_eat_entry<'a>(
     data: &'a mut PhilosopherData,
     context: &mut (dyn StateMachineContext<DppEvent> + 'a),
-) -> () {
+) {
     info!("Eat: {:?}", data.id);
     debug!(
-        "Publish {:?} after 1000 ms",
+        "Arm timer for {:?} after 100 ms",
         DppEvent::FinishEating(data.id),
     );
-    context.publish_delayed_event(100, DppEvent::FinishEating(data.id));
+    // owned by the Eat state, i.e. cancelled when Eat is left early
+    context.arm_timer(100, DppEvent::FinishEating(data.id));
 }
 
 fn philosopher_eat_exit<'a>(
     data: &'a mut PhilosopherData,
     context: &mut (dyn StateMachineContext<DppEvent> + 'a),
-) -> () {
+) {
     debug!(
         "Publish {:?} and {:?} ",
         DppEvent::ReleaseRightFork(data.id),
@@ -147,92 +175,106 @@ fn philosopher_eat_exit<'a>(
 }
 
 fn philosopher_eat_dispatch<'a>(
-    data: &'a mut PhilosopherData,
+    _data: &'a mut PhilosopherData,
     _context: &mut (dyn StateMachineContext<DppEvent> + 'a),
-    event: DppEvent,
-) -> ProcessingResult<PhilosopherState> {
+    event: &DppEvent,
+) -> ProcessingResult<PhilosopherData, DppEvent, PhilosopherState> {
     match event {
-        DppEvent::FinishEating(philosopher) => {
-            if philosopher == data.id {
-                ProcessingResult::Transition(PhilosopherState::Think)
-            } else {
-                ProcessingResult::Ignored
-            }
-        }
+        DppEvent::FinishEating(_) => ProcessingResult::Transition(PhilosopherState::Think),
         _ => ProcessingResult::Ignored,
     }
 }
 
+/// Forks of a philosopher: the left fork has the index of the philosopher,
+/// the right fork is shared with the next philosopher
+fn left_fork(philosopher: PhilosopherId) -> usize {
+    philosopher as usize
+}
+
+fn right_fork(philosopher: PhilosopherId) -> usize {
+    (philosopher as usize + 1) % 3
+}
+
+fn table_operational_entry<'a>(_data: &'a mut TableData, context: &mut (dyn StateMachineContext<DppEvent> + 'a)) {
+    // offer the requests for forks that were not available again
+    while context.recall() {}
+}
+
 fn table_operational_dispatch<'a>(
     data: &'a mut TableData,
     context: &mut (dyn StateMachineContext<DppEvent> + 'a),
-    event: DppEvent,
-) -> ProcessingResult<TableState> {
+    event: &DppEvent,
+) -> ProcessingResult<TableData, DppEvent, TableState> {
+    let free_forks = data.forks_available.iter().filter(|available| **available).count();
     match event {
-        DppEvent::RequestLeftFork(philosopher) => {
-            context.publish_event(DppEvent::GrantLeftFork(philosopher));
-            data.forks_available[0] = false;
+        // keep a fork for the right hand of someone else, otherwise all
+        // philosophers could hold their left fork and wait forever
+        DppEvent::RequestLeftFork(philosopher) if data.forks_available[left_fork(*philosopher)] && free_forks > 1 => {
+            data.forks_available[left_fork(*philosopher)] = false;
+            context.send_to(data.philosophers[*philosopher as usize], DppEvent::GrantLeftFork(*philosopher));
             ProcessingResult::Handled
         }
-        DppEvent::RequestRightFork(philosopher) => {
-            context.publish_event(DppEvent::GrantRightFork(philosopher));
+        DppEvent::RequestRightFork(philosopher) if data.forks_available[right_fork(*philosopher)] => {
+            data.forks_available[right_fork(*philosopher)] = false;
+            context.send_to(data.philosophers[*philosopher as usize], DppEvent::GrantRightFork(*philosopher));
             ProcessingResult::Handled
         }
+        DppEvent::RequestLeftFork(_) | DppEvent::RequestRightFork(_) => {
+            debug!("Defer {:?}", event);
+            ProcessingResult::Deferred
+        }
+        DppEvent::ReleaseLeftFork(philosopher) => {
+            data.forks_available[left_fork(*philosopher)] = true;
+            ProcessingResult::Transition(TableState::Operational)
+        }
+        DppEvent::ReleaseRightFork(philosopher) => {
+            data.forks_available[right_fork(*philosopher)] = true;
+            ProcessingResult::Transition(TableState::Operational)
+        }
         _ => ProcessingResult::Ignored,
     }
 }
 
 //----------------------------------------------------------------------------
-// default states
-
-fn init<D, E, S: PartialEq>() -> Option<State<D, E, S>> {
-    None
-}
+// state enums and static data structures
+//
+// Note:
+//    The state_machine! macro lets the compiler detect duplicate states and
+//    unknown transition targets
 
-fn entry<'a, D, E>(_data: &'a mut D, _context: &mut (dyn StateMachineContext<E> + 'a)) -> () {}
+state_machine! {
+    #[derive(Debug, PartialEq)]
+    pub enum PhilosopherState;
 
-fn exit<'a, D, E>(_data: &'a mut D, _context: &mut (dyn StateMachineContext<E> + 'a)) -> () {}
+    pub const PHILOSOPHER_STATES: [State<PhilosopherData, DppEvent>] = [
+        Think {
+            entry: philosopher_think_entry,
+            dispatch: philosopher_think_dispatch,
+            transitions: [GrantLeftFork => Hungry],
+        },
+        Hungry {
+            entry: philosopher_hungry_entry,
+            dispatch: philosopher_hungry_dispatch,
+            transitions: [GrantRightFork => Eat],
+        },
+        Eat {
+            entry: philosopher_eat_entry,
+            exit: philosopher_eat_exit,
+            dispatch: philosopher_eat_dispatch,
+            transitions: [FinishEating => Think],
+        },
+    ];
+}
 
-//----------------------------------------------------------------------------
-// static data structures
-//
-// Note:
-//    These data structures require careful reviews since the compiler is not
-//    able to find any inconsistencies
-
-pub const PHILOSOPHER_STATES: [State<PhilosopherData, DppEvent, PhilosopherState>; 3] = [
-    State::<PhilosopherData, DppEvent, PhilosopherState> {
-        state: PhilosopherState::Think,
-        super_state: None,
-        entry: philosopher_think_entry,
-        exit,
-        init,
-        dispatch: philosopher_think_dispatch,
-    },
-    State::<PhilosopherData, DppEvent, PhilosopherState> {
-        state: PhilosopherState::Hungry,
-        super_state: None,
-        entry: philosopher_hungry_entry,
-        exit,
-        init,
-        dispatch: philosopher_hungry_dispatch,
-    },
-    State::<PhilosopherData, DppEvent, PhilosopherState> {
-        state: PhilosopherState::Eat,
-        super_state: None,
-        entry: philosopher_eat_entry,
-        exit: philosopher_eat_exit,
-        init,
-        dispatch: philosopher_eat_dispatch,
-    },
-];
+state_machine! {
+    #[derive(Debug, PartialEq)]
+    pub enum TableState;
 
-pub const TABLE_STATES: [State<TableData, DppEvent, TableState>; 1] =
-    [State::<TableData, DppEvent, TableState> {
-        state: TableState::Operational,
-        super_state: None,
-        entry,
-        exit,
-        init,
-        dispatch: table_operational_dispatch,
-    }];
+    pub const TABLE_STATES: [State<TableData, DppEvent>] = [
+        Operational {
+            entry: table_operational_entry,
+            dispatch: table_operational_dispatch,
+            transitions: [ReleaseLeftFork => Operational, ReleaseRightFork => Operational],
+        },
+    ];
+}
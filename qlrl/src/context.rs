@@ -0,0 +1,231 @@
+//! Context wrapper used by the state machine processors
+//!
+//! The processors hand this wrapper instead of the runtime context to the
+//! state handler functions. It forwards everything to the runtime context and
+//! keeps track of the timers armed by each state, queues the internal events
+//! a state machine posts to itself and recalls deferred events.
+//!
+use super::ring::Ring;
+use super::{MachineId, PublishError, Signal, StateMachineContext, TimerHandle};
+
+/// Maximum number of timers a state machine tracks for cancellation on exit
+///
+/// If more timers are armed, the oldest record is dropped; that timer is not
+/// cancelled automatically anymore.
+pub const MAX_OWNED_TIMERS: usize = 8;
+
+/// Capacity of the queue of internal events of a state machine
+///
+/// Posting more internal events while processing a single event fails with
+/// `PublishError::QueueFull`.
+pub const MAX_INTERNAL_EVENTS: usize = 8;
+
+/// Capacity of the queue of deferred events of a state machine
+///
+/// More deferred events are dropped and reported to the observer via
+/// `StateMachineObserver::event_dropped`.
+pub const MAX_DEFERRED_EVENTS: usize = 8;
+
+/// Events kept inside a state machine processor
+pub(crate) struct EventQueues<E> {
+    internal: Ring<E, MAX_INTERNAL_EVENTS>, // posted by the state machine to itself
+    deferred: Ring<E, MAX_DEFERRED_EVENTS>,
+}
+
+impl<E> EventQueues<E> {
+    pub(crate) fn new() -> Self {
+        EventQueues {
+            internal: Ring::new(),
+            deferred: Ring::new(),
+        }
+    }
+
+    fn post(&mut self, e: E) -> Result<(), PublishError> {
+        if self.internal.is_full() {
+            return Err(PublishError::QueueFull);
+        }
+        self.internal.push(e);
+        Ok(())
+    }
+
+    /// Next internal event to dispatch
+    pub(crate) fn next(&mut self) -> Option<E> {
+        self.internal.pop()
+    }
+
+    /// Keep an event for `recall`, hands it back if the queue is full
+    pub(crate) fn defer(&mut self, e: E) -> Result<(), E> {
+        if self.deferred.is_full() {
+            return Err(e);
+        }
+        self.deferred.push(e);
+        Ok(())
+    }
+
+    /// Move the oldest deferred event to the internal events unless their
+    /// queue is full
+    fn recall(&mut self) -> bool {
+        if self.internal.is_full() {
+            return false;
+        }
+        match self.deferred.pop() {
+            Some(e) => self.post(e).is_ok(),
+            None => false,
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.internal.clear();
+        self.deferred.clear();
+    }
+}
+
+/// Timers armed by the states of a state machine, i.e. by their entry actions
+/// and dispatch functions
+pub(crate) struct OwnedTimers {
+    enabled: bool,
+    timers: [Option<(usize, TimerHandle)>; MAX_OWNED_TIMERS],
+    next: usize, // slot to overwrite if all slots are used
+}
+
+impl OwnedTimers {
+    pub(crate) const fn new() -> Self {
+        OwnedTimers {
+            enabled: false,
+            timers: [None; MAX_OWNED_TIMERS],
+            next: 0,
+        }
+    }
+
+    pub(crate) fn enable(&mut self) {
+        self.enabled = true;
+    }
+
+    fn record(&mut self, owner: usize, handle: TimerHandle) {
+        if !self.enabled {
+            return;
+        }
+        let slot = match self.timers.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None => {
+                let slot = self.next;
+                self.next = (self.next + 1) % MAX_OWNED_TIMERS;
+                slot
+            }
+        };
+        self.timers[slot] = Some((owner, handle));
+    }
+
+    fn forget(&mut self, handle: TimerHandle) {
+        for slot in self.timers.iter_mut() {
+            if matches!(slot, Some((_, h)) if *h == handle) {
+                *slot = None;
+            }
+        }
+    }
+
+    /// Cancel all timers armed by the state at index `owner`
+    pub(crate) fn cancel_owned_by<'a, E>(
+        &mut self,
+        context: &mut (dyn StateMachineContext<E> + 'a),
+        owner: usize,
+    ) {
+        for slot in self.timers.iter_mut() {
+            if let Some((o, handle)) = *slot {
+                if o == owner {
+                    context.cancel_timer(handle);
+                    *slot = None;
+                }
+            }
+        }
+    }
+}
+
+/// Runtime context as seen by the state handler functions
+pub(crate) struct MachineContext<'c, 'a, E> {
+    inner: &'c mut (dyn StateMachineContext<E> + 'a),
+    timers: &'c mut OwnedTimers,
+    events: &'c mut EventQueues<E>,
+    owner: Option<usize>, // the state that owns armed timers
+}
+
+impl<'c, 'a, E> MachineContext<'c, 'a, E> {
+    pub(crate) fn new(
+        inner: &'c mut (dyn StateMachineContext<E> + 'a),
+        timers: &'c mut OwnedTimers,
+        events: &'c mut EventQueues<E>,
+        owner: Option<usize>,
+    ) -> Self {
+        MachineContext {
+            inner,
+            timers,
+            events,
+            owner,
+        }
+    }
+}
+
+impl<'c, 'a, E> StateMachineContext<E> for MachineContext<'c, 'a, E> {
+    fn publish_event(&mut self, e: E) {
+        self.inner.publish_event(e);
+    }
+
+    fn try_publish_event(&mut self, e: E) -> Result<(), PublishError> {
+        self.inner.try_publish_event(e)
+    }
+
+    fn send_to(&mut self, target: MachineId, e: E) {
+        self.inner.send_to(target, e);
+    }
+
+    fn post_internal(&mut self, e: E) -> Result<(), PublishError> {
+        self.events.post(e)
+    }
+
+    fn recall(&mut self) -> bool {
+        self.events.recall()
+    }
+
+    fn publish_delayed_event(&mut self, delay_in_ms: u64, e: E) {
+        // fire and forget, not owned by the state
+        self.inner.publish_delayed_event(delay_in_ms, e);
+    }
+
+    fn arm_timer(&mut self, delay_in_ms: u64, e: E) -> TimerHandle {
+        let handle = self.inner.arm_timer(delay_in_ms, e);
+        if let Some(owner) = self.owner {
+            self.timers.record(owner, handle);
+        }
+        handle
+    }
+
+    fn arm_periodic_timer(&mut self, period_in_ms: u64, e: E) -> TimerHandle
+    where
+        E: Clone,
+    {
+        let handle = self.inner.arm_periodic_timer(period_in_ms, e);
+        if let Some(owner) = self.owner {
+            self.timers.record(owner, handle);
+        }
+        handle
+    }
+
+    fn cancel_timer(&mut self, handle: TimerHandle) -> bool {
+        self.timers.forget(handle);
+        self.inner.cancel_timer(handle)
+    }
+
+    fn subscribe(&mut self, kind: E::Kind)
+    where
+        E: Signal,
+    {
+        self.inner.subscribe(kind);
+    }
+
+    fn unsubscribe(&mut self, kind: E::Kind)
+    where
+        E: Signal,
+    {
+        self.inner.unsubscribe(kind);
+    }
+}
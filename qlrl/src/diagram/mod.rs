@@ -0,0 +1,266 @@
+//! Rendering of state lists as diagrams
+//!
+//! - Mermaid `stateDiagram-v2`, PlantUML and Graphviz DOT
+//! - states are named by their `Debug` representation, reduced to the
+//!   characters valid in identifiers
+//! - nesting is taken from `super_state`, initial sub states from `init`
+//!   and transitions from `transitions`, labelled `Event [guard] / action`;
+//!   completion transitions have no event
+//! - choice pseudo states are rendered as UML choices or DOT diamonds
+//! - the regions of an orthogonal state are separated by `--` in UML and
+//!   rendered as dashed clusters in DOT
+//! - the first state of the state list is the initial state
+//!
+//! The state list should be valid (see `validate`); states in a
+//! `super_state` cycle are not rendered.
+//!
+//! ```
+//! # use qlrl::{State, TransitionSpec};
+//! #[derive(Debug, PartialEq)]
+//! enum Switch { Off, On }
+//!
+//! const SWITCH_STATES: [State<(), (), Switch>; 2] = [
+//!     State { transitions: &[TransitionSpec::new("Toggle", Switch::On)], ..State::new(Switch::Off) },
+//!     State { transitions: &[TransitionSpec::new("Toggle", Switch::Off)], ..State::new(Switch::On) },
+//! ];
+//!
+//! let mut diagram = String::new();
+//! qlrl::diagram::mermaid(&SWITCH_STATES, &mut diagram).unwrap();
+//! assert!(diagram.contains("Off --> On : Toggle"));
+//! ```
+//!
+use core::fmt::{self, Debug, Write};
+
+use super::{super_index, State, StateKind, TransitionSpec};
+
+/// Writer dropping all characters not valid in identifiers
+struct Identifier<'w>(&'w mut dyn Write);
+
+impl Write for Identifier<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars().filter(|c| c.is_alphanumeric() || *c == '_') {
+            self.0.write_char(c)?;
+        }
+        Ok(())
+    }
+}
+
+fn name<S: Debug>(out: &mut dyn Write, state: &S) -> fmt::Result {
+    write!(Identifier(out), "{:?}", state)
+}
+
+fn indent(out: &mut dyn Write, depth: usize) -> fmt::Result {
+    for _ in 0..depth {
+        out.write_str("    ")?;
+    }
+    Ok(())
+}
+
+/// Transition label `Event [guard] / action`, empty for unguarded
+/// completion transitions without action
+fn label<D, E, S>(out: &mut dyn Write, transition: &TransitionSpec<D, E, S>) -> fmt::Result {
+    let mut separator = "";
+    if !transition.is_completion() {
+        out.write_str(transition.event)?;
+        separator = " ";
+    }
+    if let Some(guard) = transition.guard_name() {
+        write!(out, "{}[{}]", separator, guard)?;
+        separator = " ";
+    }
+    if let Some(action) = transition.action_name() {
+        write!(out, "{}/ {}", separator, action)?;
+    }
+    Ok(())
+}
+
+fn is_labelled<D, E, S>(transition: &TransitionSpec<D, E, S>) -> bool {
+    !transition.is_completion() || transition.guard.is_some() || transition.action.is_some()
+}
+
+/// Indices of the states directly nested in `parent` (top level if `None`)
+fn children<'s, D, E, S: PartialEq>(
+    state_list: &'s [State<D, E, S>],
+    parent: Option<usize>,
+) -> impl Iterator<Item = usize> + 's {
+    (0..state_list.len()).filter(move |&i| super_index(state_list, i) == parent)
+}
+
+/// The two UML flavors differ in the header and the declaration of simple states only
+#[derive(Clone, Copy, PartialEq)]
+enum Uml {
+    Mermaid,
+    PlantUml,
+}
+
+fn uml_states<D, E, S: PartialEq + Debug>(
+    state_list: &[State<D, E, S>],
+    parent: Option<usize>,
+    depth: usize,
+    uml: Uml,
+    out: &mut dyn Write,
+) -> fmt::Result {
+    let orthogonal = parent.is_some_and(|p| state_list[p].kind == StateKind::Orthogonal);
+    for (n, i) in children(state_list, parent).enumerate() {
+        let state = &state_list[i];
+        if orthogonal && n > 0 {
+            indent(out, depth)?;
+            out.write_str("--\n")?;
+        }
+        indent(out, depth)?;
+        if state.kind == StateKind::Choice {
+            out.write_str("state ")?;
+            name(out, &state.state)?;
+            out.write_str(" <<choice>>\n")?;
+            continue;
+        }
+        if children(state_list, Some(i)).next().is_none() {
+            if uml == Uml::PlantUml {
+                out.write_str("state ")?;
+            }
+            name(out, &state.state)?;
+            out.write_str("\n")?;
+            continue;
+        }
+        out.write_str("state ")?;
+        name(out, &state.state)?;
+        out.write_str(" {\n")?;
+        if let Some(init) = (state.init)() {
+            indent(out, depth + 1)?;
+            out.write_str("[*] --> ")?;
+            name(out, &init)?;
+            out.write_str("\n")?;
+        }
+        uml_states(state_list, Some(i), depth + 1, uml, out)?;
+        indent(out, depth)?;
+        out.write_str("}\n")?;
+    }
+    Ok(())
+}
+
+fn uml<D, E, S: PartialEq + Debug>(state_list: &[State<D, E, S>], uml: Uml, out: &mut dyn Write) -> fmt::Result {
+    out.write_str(match uml {
+        Uml::Mermaid => "stateDiagram-v2\n",
+        Uml::PlantUml => "@startuml\n",
+    })?;
+    if let Some(first) = state_list.first() {
+        indent(out, 1)?;
+        out.write_str("[*] --> ")?;
+        name(out, &first.state)?;
+        out.write_str("\n")?;
+    }
+    uml_states(state_list, None, 1, uml, out)?;
+    for state in state_list {
+        for transition in state.transitions {
+            indent(out, 1)?;
+            name(out, &state.state)?;
+            out.write_str(" --> ")?;
+            name(out, &transition.target)?;
+            if is_labelled(transition) {
+                out.write_str(" : ")?;
+                label(out, transition)?;
+            }
+            out.write_str("\n")?;
+        }
+    }
+    if uml == Uml::PlantUml {
+        out.write_str("@enduml\n")?;
+    }
+    Ok(())
+}
+
+/// Render a state list as Mermaid `stateDiagram-v2`
+pub fn mermaid<D, E, S: PartialEq + Debug>(state_list: &[State<D, E, S>], out: &mut impl Write) -> fmt::Result {
+    uml(state_list, Uml::Mermaid, out)
+}
+
+/// Render a state list as PlantUML state diagram
+pub fn plantuml<D, E, S: PartialEq + Debug>(state_list: &[State<D, E, S>], out: &mut impl Write) -> fmt::Result {
+    uml(state_list, Uml::PlantUml, out)
+}
+
+fn dot_states<D, E, S: PartialEq + Debug>(
+    state_list: &[State<D, E, S>],
+    parent: Option<usize>,
+    depth: usize,
+    out: &mut dyn Write,
+) -> fmt::Result {
+    for i in children(state_list, parent) {
+        let state = &state_list[i];
+        indent(out, depth)?;
+        if state.kind == StateKind::Choice {
+            name(out, &state.state)?;
+            out.write_str(" [shape = diamond, label = \"\"];\n")?;
+            continue;
+        }
+        if children(state_list, Some(i)).next().is_none() {
+            name(out, &state.state)?;
+            out.write_str(";\n")?;
+            continue;
+        }
+        // a composite state is a cluster; its node is the initial pseudo state
+        out.write_str("subgraph cluster_")?;
+        name(out, &state.state)?;
+        out.write_str(" {\n")?;
+        indent(out, depth + 1)?;
+        out.write_str("label = \"")?;
+        name(out, &state.state)?;
+        out.write_str("\";\n")?;
+        if parent.is_some_and(|p| state_list[p].kind == StateKind::Orthogonal) {
+            indent(out, depth + 1)?;
+            out.write_str("style = dashed;\n")?;
+        }
+        indent(out, depth + 1)?;
+        name(out, &state.state)?;
+        out.write_str(" [shape = point];\n")?;
+        if let Some(init) = (state.init)() {
+            indent(out, depth + 1)?;
+            name(out, &state.state)?;
+            out.write_str(" -> ")?;
+            name(out, &init)?;
+            out.write_str(";\n")?;
+        }
+        dot_states(state_list, Some(i), depth + 1, out)?;
+        indent(out, depth)?;
+        out.write_str("}\n")?;
+    }
+    Ok(())
+}
+
+/// Render a state list as Graphviz DOT digraph
+///
+/// Composite states are rendered as clusters; transitions from and to a
+/// composite state start and end at its initial pseudo state.
+pub fn dot<D, E, S: PartialEq + Debug>(state_list: &[State<D, E, S>], out: &mut impl Write) -> fmt::Result {
+    let out: &mut dyn Write = out;
+    out.write_str("digraph {\n")?;
+    indent(out, 1)?;
+    out.write_str("node [shape = box, style = rounded];\n")?;
+    if let Some(first) = state_list.first() {
+        indent(out, 1)?;
+        out.write_str("__start [shape = point];\n")?;
+        indent(out, 1)?;
+        out.write_str("__start -> ")?;
+        name(out, &first.state)?;
+        out.write_str(";\n")?;
+    }
+    dot_states(state_list, None, 1, out)?;
+    for state in state_list {
+        for transition in state.transitions {
+            indent(out, 1)?;
+            name(out, &state.state)?;
+            out.write_str(" -> ")?;
+            name(out, &transition.target)?;
+            if is_labelled(transition) {
+                out.write_str(" [label = \"")?;
+                label(out, transition)?;
+                out.write_str("\"]")?;
+            }
+            out.write_str(";\n")?;
+        }
+    }
+    out.write_str("}\n")
+}
+
+#[cfg(test)]
+mod tests;
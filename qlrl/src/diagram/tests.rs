@@ -0,0 +1,138 @@
+extern crate std;
+use std::string::String;
+
+use super::*;
+
+struct Data;
+struct Event;
+
+fn leaf() -> Option<char> {
+    None
+}
+
+fn init_x() -> Option<char> {
+    Some('x')
+}
+
+// T{x, y} and a top level state z; `Debug` of char is quoted e.g. 'x'
+const STATES: [State<Data, Event, char>; 4] = [
+    State { transitions: &[crate::TransitionSpec::new("Go", 'z')], init: init_x, ..State::new('T') },
+    State { super_state: Some('T'), transitions: &[crate::TransitionSpec::new("Next", 'y')], ..State::new('x') },
+    State { super_state: Some('T'), init: leaf, ..State::new('y') },
+    State::new('z'),
+];
+
+type Renderer = fn(&[State<Data, Event, char>], &mut String) -> fmt::Result;
+
+fn render(renderer: Renderer) -> String {
+    let mut out = String::new();
+    renderer(&STATES, &mut out).unwrap();
+    out
+}
+
+#[test]
+fn mermaid_nests_states_and_lists_transitions() {
+    assert_eq!(
+        "stateDiagram-v2\n    [*] --> T\n    state T {\n        [*] --> x\n        x\n        y\n    }\n    z\n    T --> z : Go\n    x --> y : Next\n",
+        render(mermaid)
+    );
+}
+
+#[test]
+fn plantuml_declares_simple_states() {
+    assert_eq!(
+        "@startuml\n    [*] --> T\n    state T {\n        [*] --> x\n        state x\n        state y\n    }\n    state z\n    T --> z : Go\n    x --> y : Next\n@enduml\n",
+        render(plantuml)
+    );
+}
+
+#[test]
+fn dot_renders_composite_states_as_clusters() {
+    assert_eq!(
+        concat!(
+            "digraph {\n",
+            "    node [shape = box, style = rounded];\n",
+            "    __start [shape = point];\n",
+            "    __start -> T;\n",
+            "    subgraph cluster_T {\n",
+            "        label = \"T\";\n",
+            "        T [shape = point];\n",
+            "        T -> x;\n",
+            "        x;\n",
+            "        y;\n",
+            "    }\n",
+            "    z;\n",
+            "    T -> z [label = \"Go\"];\n",
+            "    x -> y [label = \"Next\"];\n",
+            "}\n",
+        ),
+        render(dot)
+    );
+}
+
+#[test]
+fn empty_state_list_renders_empty_diagram() {
+    let mut out = String::new();
+    mermaid::<Data, Event, char>(&[], &mut out).unwrap();
+    assert_eq!("stateDiagram-v2\n", out);
+}
+
+fn door_closed(_data: &Data, _event: &Event) -> bool {
+    true
+}
+
+fn lock<'a>(_data: &'a mut Data, _context: &mut (dyn crate::StateMachineContext<Event> + 'a), _event: &Event) {}
+
+fn door_open(_data: &Data) -> bool {
+    false
+}
+
+#[test]
+fn labels_show_guard_and_action() {
+    static TRANSITIONS: [crate::TransitionSpec<Data, Event, char>; 1] = [crate::TransitionSpec {
+        guard: Some(crate::Guard { name: "door_closed", check: crate::Check::Event(door_closed) }),
+        action: Some(crate::Action { name: "lock", run: crate::Effect::Event(lock) }),
+        ..crate::TransitionSpec::new("Leave", 'b')
+    }];
+    static GUARDED: [State<Data, Event, char>; 2] = [State { transitions: &TRANSITIONS, ..State::new('a') }, State::new('b')];
+    let mut out = String::new();
+    mermaid(&GUARDED, &mut out).unwrap();
+    assert!(out.ends_with("a --> b : Leave [door_closed] / lock\n"));
+}
+
+#[test]
+fn choices_and_completion_transitions() {
+    static BRANCHES: [crate::TransitionSpec<Data, Event, char>; 2] = [
+        crate::TransitionSpec { guard: Some(crate::Guard { name: "door_open", check: crate::Check::Completion(door_open) }), ..crate::TransitionSpec::new("", 'a') },
+        crate::TransitionSpec::new("", 'b'),
+    ];
+    static CHOICE: [State<Data, Event, char>; 3] = [
+        State::new('a'),
+        State::new('b'),
+        State { kind: crate::StateKind::Choice, transitions: &BRANCHES, ..State::new('c') },
+    ];
+    let mut out = String::new();
+    plantuml(&CHOICE, &mut out).unwrap();
+    assert!(out.contains("    state c <<choice>>\n    c --> a : [door_open]\n    c --> b\n"));
+    out.clear();
+    dot(&CHOICE, &mut out).unwrap();
+    assert!(out.contains("    c [shape = diamond, label = \"\"];\n"));
+    assert!(out.contains("    c -> b;\n"));
+}
+
+#[test]
+fn regions_are_separated() {
+    static REGIONS: [State<Data, Event, char>; 5] = [
+        State { kind: crate::StateKind::Orthogonal, ..State::new('D') },
+        State { super_state: Some('D'), init: init_x, ..State::new('N') },
+        State { super_state: Some('N'), ..State::new('x') },
+        State { super_state: Some('D'), ..State::new('W') },
+        State { super_state: Some('W'), ..State::new('y') },
+    ];
+    let mut out = String::new();
+    mermaid(&REGIONS, &mut out).unwrap();
+    assert!(out.contains("    state D {\n        state N {\n            [*] --> x\n            x\n        }\n        --\n        state W {\n"));
+    out.clear();
+    dot(&REGIONS, &mut out).unwrap();
+    assert!(out.contains("        subgraph cluster_W {\n            label = \"W\";\n            style = dashed;\n"));
+}
@@ -1,43 +1,224 @@
 //! Finite State Machine processor
 //!
+//! Internal events posted via `post_internal` are dispatched right after the
+//! event (or start) that posted them, before the next external event.
+//! Events a state returns `ProcessingResult::Deferred` for are kept until
+//! `recall` queues them as internal events again.
+//!
+//! An event is offered to the transitions of the current state it triggers
+//! first; the dispatch function sees the event only if none of them is
+//! enabled.
+//!
+//! After entering a state its completion transitions are evaluated; a
+//! transition to a choice pseudo state continues along the first enabled
+//! branch of the choice.
 //!
 use core::cmp::PartialEq;
 
-use super::{find_state_index, ProcessingResult, State, StateMachine, StateMachineContext};
+use super::context::{EventQueues, MachineContext, OwnedTimers};
+use super::{
+    index_of, select_completion, select_triggered, validate, Action, Introspect, NoObserver, ProcessingResult,
+    State, StateKind, StateMachine, StateMachineContext, StateMachineObserver, ValidationError,
+    MAX_TRANSITION_CHAIN,
+};
 
-pub struct FiniteStateMachine<D: 'static, E: 'static, S: PartialEq + 'static> {
+pub struct FiniteStateMachine<D: 'static, E: 'static, S: PartialEq + 'static, O = NoObserver> {
     index: usize,
     state_list: &'static [State<D, E, S>],
     data: D,
+    timers: OwnedTimers,
+    events: EventQueues<E>,
+    observer: O,
 }
 
 impl<D, E, S: PartialEq> FiniteStateMachine<D, E, S> {
+    /// Create a finite state machine without validating the state list
+    ///
+    /// An invalid state list panics on processing, e.g. a completion chain
+    /// longer than `MAX_TRANSITION_CHAIN`; prefer `try_new`.
     pub fn new(state_list: &'static [State<D, E, S>], data: D) -> Self {
         FiniteStateMachine {
             state_list,
             index: 0,
             data, // data is moved
+            timers: OwnedTimers::new(),
+            events: EventQueues::new(),
+            observer: NoObserver,
         }
     }
+
+    /// Create a finite state machine from a validated state list
+    pub fn try_new(state_list: &'static [State<D, E, S>], data: D) -> Result<Self, ValidationError> {
+        validate(state_list)?;
+        Ok(Self::new(state_list, data))
+    }
+}
+
+impl<D, E, S: PartialEq, O> FiniteStateMachine<D, E, S, O> {
+    /// Report the event processing to the given observer
+    pub fn with_observer<P: StateMachineObserver<E, S>>(self, observer: P) -> FiniteStateMachine<D, E, S, P> {
+        FiniteStateMachine {
+            index: self.index,
+            state_list: self.state_list,
+            data: self.data,
+            timers: self.timers,
+            events: self.events,
+            observer,
+        }
+    }
+
+    /// Cancel the timers armed by the entry action or dispatch function of
+    /// a state when the state is left
+    pub fn cancel_timers_on_exit(mut self) -> Self {
+        self.timers.enable();
+        self
+    }
+
+    /// State machine private data
+    pub fn data(&self) -> &D {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut D {
+        &mut self.data
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
 }
 
-impl<D, E, S> StateMachine<E> for FiniteStateMachine<D, E, S>
+impl<D, E, S: PartialEq, O: StateMachineObserver<E, S>> FiniteStateMachine<D, E, S, O> {
+    fn enter<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a)) {
+        let state = &self.state_list[self.index];
+        self.observer.entered(&state.state);
+        let mut entry_context = MachineContext::new(context, &mut self.timers, &mut self.events, Some(self.index));
+        (state.entry)(&mut self.data, &mut entry_context);
+    }
+
+    fn exit<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a)) {
+        let state = &self.state_list[self.index];
+        self.observer.exited(&state.state);
+        let mut exit_context = MachineContext::new(context, &mut self.timers, &mut self.events, None);
+        (state.exit)(&mut self.data, &mut exit_context);
+        self.timers.cancel_owned_by(context, self.index);
+    }
+
+    fn act<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a), action: &Action<D, E>, event: Option<&E>) {
+        let mut action_context = MachineContext::new(context, &mut self.timers, &mut self.events, None);
+        action.execute(&mut self.data, &mut action_context, event);
+    }
+
+    /// Complete a transition after the source is exited: execute the action,
+    /// pass choices and enter the target, then follow completion transitions
+    ///
+    /// The triggering event (if any) is handed to the first action only.
+    fn take<'a>(
+        &mut self,
+        context: &mut (dyn StateMachineContext<E> + 'a),
+        action: Option<&Action<D, E>>,
+        event: Option<&E>,
+        target: usize,
+    ) {
+        let (mut action, mut event, mut target) = (action, event, target);
+        let mut steps = 0;
+        loop {
+            if let Some(action) = action {
+                self.act(context, action, event);
+            }
+            let state = &self.state_list[target];
+            let next = if state.kind == StateKind::Choice {
+                Some(select_completion(state, &self.data, &mut self.observer).expect("Choice without else branch"))
+            } else {
+                self.index = target;
+                self.enter(context);
+                select_completion(state, &self.data, &mut self.observer)
+            };
+            let Some(next) = next else { break };
+            steps += 1;
+            assert!(steps <= MAX_TRANSITION_CHAIN, "Transition chain too long");
+            let next_index = index_of(self.state_list, &next.target).expect("State specification not found ");
+            self.observer.transition(&state.state, &self.state_list[next_index].state);
+            if state.kind != StateKind::Choice {
+                self.exit(context);
+            }
+            (action, event, target) = (next.action.as_ref(), None, next_index);
+        }
+    }
+
+    /// Take a transition returned by the dispatch function of the active state
+    fn returned<'a>(
+        &mut self,
+        context: &mut (dyn StateMachineContext<E> + 'a),
+        target: &S,
+        action: Option<&Action<D, E>>,
+        event: &E,
+    ) {
+        let index = index_of(self.state_list, target).expect("State specification not found ");
+        self.observer.transition(&self.state_list[self.index].state, &self.state_list[index].state);
+        self.exit(context);
+        self.take(context, action, Some(event), index);
+    }
+
+    /// Dispatch a single event
+    fn process<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a), event: E) {
+        let state = &self.state_list[self.index];
+        self.observer.event_received(&state.state, &event);
+        if let Some(triggered) = select_triggered(state, &self.data, &event, &mut self.observer) {
+            let index = index_of(self.state_list, &triggered.target).expect("State specification not found ");
+            self.observer.transition(&state.state, &self.state_list[index].state);
+            self.exit(context);
+            self.take(context, triggered.action.as_ref(), Some(&event), index);
+            return;
+        }
+        let mut dispatch_context = MachineContext::new(context, &mut self.timers, &mut self.events, Some(self.index));
+        match (state.dispatch)(&mut self.data, &mut dispatch_context, &event) {
+            ProcessingResult::Handled => self.observer.event_handled(&state.state, &event),
+            ProcessingResult::Transition(new_state) => self.returned(context, &new_state, None, &event),
+            ProcessingResult::TransitionWith(new_state, run) => {
+                self.returned(context, &new_state, Some(&Action::returned(run)), &event)
+            }
+            ProcessingResult::Ignored => self.observer.event_ignored(&state.state, &event),
+            ProcessingResult::SuperState(_current_state) => self.observer.event_ignored(&state.state, &event), // relevant only for hierarchical state machines
+            ProcessingResult::Top => self.observer.event_ignored(&state.state, &event), // relevant only for hierarchical state machines
+            ProcessingResult::Deferred => {
+                self.observer.event_deferred(&state.state, &event);
+                if let Err(event) = self.events.defer(event) {
+                    self.observer.event_dropped(&state.state, &event);
+                }
+            }
+        }
+    }
+
+    /// Dispatch the queued internal events (run to completion)
+    fn complete<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a)) {
+        while let Some(event) = self.events.next() {
+            self.process(context, event);
+        }
+    }
+}
+
+impl<D, E, S: PartialEq, O> Introspect<S> for FiniteStateMachine<D, E, S, O> {
+    fn current_state(&self) -> &S {
+        &self.state_list[self.index].state
+    }
+
+    /// Visit the current state; super states are not evaluated
+    fn active_states(&self, visit: &mut dyn FnMut(&S)) {
+        visit(self.current_state());
+    }
+}
+
+impl<D, E, S, O> StateMachine<E> for FiniteStateMachine<D, E, S, O>
 where
     S: PartialEq,
     E: Send,
+    O: StateMachineObserver<E, S>,
 {
-    /// Dispatch an event
+    /// Dispatch an event and the internal events posted while processing it
     fn dispatch<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a), event: E) {
-        match (self.state_list[self.index].dispatch)(&mut self.data, context, event) {
-            ProcessingResult::Ignored | ProcessingResult::Handled => (),
-            ProcessingResult::Transition(new_state) => {
-                (self.state_list[self.index].exit)(&mut self.data, context);
-                self.index = find_state_index(self.state_list, new_state).expect("State specification not found ");
-                (self.state_list[self.index].entry)(&mut self.data, context);
-            }
-            ProcessingResult::SuperState(_current_state) => (), // relevant only for hierarchical state machines
-            ProcessingResult::Top => (), // relevant only for hierarchical state machines
-        }
+        self.process(context, event);
+        self.complete(context);
     }
 
     /// Start the state machine i.e. let the state machine perform its
@@ -47,7 +228,15 @@ where
     /// [*] --> FirstState
     /// ```
     fn start<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a)) {
-        (self.state_list[self.index].entry)(&mut self.data, context);
+        self.take(context, None, None, 0);
+        self.complete(context);
+    }
+
+    /// Stop the state machine i.e. exit the active state; pending internal
+    /// and deferred events are dropped
+    fn stop<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a)) {
+        self.exit(context);
+        self.events.clear();
     }
 }
 
@@ -1,7 +1,835 @@
+extern crate std;
+use std::{format, string::String, vec, vec::Vec};
+
 use super::*;
+use crate::testing::SimulatedContext;
+use crate::{Introspect, MachineId, PublishError, Signal, StateMachineObserver, TimerHandle, MAX_DEFERRED_EVENTS, MAX_INTERNAL_EVENTS};
+
+//----------------------------------------------------------------------------
+// door closing automatically after a while, run in a simulated context
+
+#[derive(Clone, Debug, PartialEq)]
+enum DoorEvent {
+    Open,
+    Close,
+    Opened,
+}
+
+#[derive(Default)]
+struct Door {
+    trace: String,
+}
+
+fn closed_dispatch<'a>(
+    _data: &'a mut Door,
+    _context: &mut (dyn StateMachineContext<DoorEvent> + 'a),
+    event: &DoorEvent,
+) -> ProcessingResult<Door, DoorEvent, DoorState> {
+    match event {
+        DoorEvent::Open => ProcessingResult::Transition(DoorState::Open),
+        _ => ProcessingResult::Ignored,
+    }
+}
+
+fn open_entry<'a>(data: &'a mut Door, context: &mut (dyn StateMachineContext<DoorEvent> + 'a)) {
+    data.trace.push_str("+open");
+    context.publish_event(DoorEvent::Opened);
+    context.arm_timer(1000, DoorEvent::Close);
+}
+
+fn open_exit<'a>(data: &'a mut Door, _context: &mut (dyn StateMachineContext<DoorEvent> + 'a)) {
+    data.trace.push_str("-open");
+}
+
+fn open_dispatch<'a>(
+    _data: &'a mut Door,
+    _context: &mut (dyn StateMachineContext<DoorEvent> + 'a),
+    event: &DoorEvent,
+) -> ProcessingResult<Door, DoorEvent, DoorState> {
+    match event {
+        DoorEvent::Close => ProcessingResult::Transition(DoorState::Closed),
+        _ => ProcessingResult::Ignored,
+    }
+}
+
+crate::state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum DoorState;
+
+    const DOOR_STATES: [State<Door, DoorEvent>] = [
+        Closed { dispatch: closed_dispatch, transitions: [Open => Open] },
+        Open { entry: open_entry, exit: open_exit, dispatch: open_dispatch, transitions: [Close => Closed] },
+    ];
+}
+
+fn started_door() -> (FiniteStateMachine<Door, DoorEvent, DoorState>, SimulatedContext<DoorEvent>) {
+    let mut door = FiniteStateMachine::new(&DOOR_STATES, Door::default()).cancel_timers_on_exit();
+    let mut context = SimulatedContext::new();
+    context.start(&mut [&mut door]);
+    (door, context)
+}
+
+#[test]
+fn start_enters_first_state() {
+    let (door, context) = started_door();
+    assert_eq!(0, door.index);
+    assert_eq!("", door.data.trace);
+    assert_eq!(0, context.published().count());
+}
+
+#[test]
+fn event_triggers_transition_with_entry_action() {
+    let (mut door, mut context) = started_door();
+    context.publish_event(DoorEvent::Open);
+    context.run(&mut [&mut door]);
+    assert_eq!(1, door.index);
+    assert_eq!("+open", door.data.trace);
+    assert!(context.published().eq(&[DoorEvent::Open, DoorEvent::Opened]));
+}
+
+#[test]
+fn introspection_reports_current_state_and_data() {
+    let (mut door, mut context) = started_door();
+    assert_eq!(&DoorState::Closed, door.current_state());
+    context.publish_event(DoorEvent::Open);
+    context.run(&mut [&mut door]);
+    assert!(door.is_in(&DoorState::Open));
+    assert!(!door.is_in(&DoorState::Closed));
+    let mut active = vec![];
+    door.active_states(&mut |s| active.push(*s == DoorState::Open));
+    assert_eq!(vec![true], active);
+    door.data_mut().trace.clear();
+    assert_eq!("", door.data().trace);
+}
+
+/// Observer recording the processing steps
+#[derive(Default)]
+struct Recorder {
+    steps: Vec<String>,
+}
+
+impl StateMachineObserver<DoorEvent, DoorState> for Recorder {
+    fn event_received(&mut self, state: &DoorState, event: &DoorEvent) {
+        self.steps.push(format!("{:?} receives {:?}", state, event));
+    }
+    fn event_handled(&mut self, state: &DoorState, event: &DoorEvent) {
+        self.steps.push(format!("{:?} handles {:?}", state, event));
+    }
+    fn event_ignored(&mut self, state: &DoorState, event: &DoorEvent) {
+        self.steps.push(format!("{:?} ignores {:?}", state, event));
+    }
+    fn transition(&mut self, from: &DoorState, to: &DoorState) {
+        self.steps.push(format!("{:?} -> {:?}", from, to));
+    }
+    fn entered(&mut self, state: &DoorState) {
+        self.steps.push(format!("enter {:?}", state));
+    }
+    fn exited(&mut self, state: &DoorState) {
+        self.steps.push(format!("exit {:?}", state));
+    }
+}
+
+#[test]
+fn observer_sees_every_processing_step() {
+    let mut door = FiniteStateMachine::new(&DOOR_STATES, Door::default()).with_observer(Recorder::default());
+    let mut context = SimulatedContext::<DoorEvent>::new();
+    context.start(&mut [&mut door]);
+    context.publish_event(DoorEvent::Open);
+    context.run(&mut [&mut door]);
+    assert_eq!(
+        vec![
+            "enter Closed",
+            "Closed receives Open",
+            "Closed -> Open",
+            "exit Closed",
+            "enter Open",
+            "Open receives Opened",
+            "Open ignores Opened",
+        ],
+        door.observer().steps
+    );
+}
+
+#[test]
+fn ignored_event_keeps_state() {
+    let (mut door, mut context) = started_door();
+    context.publish_event(DoorEvent::Close);
+    context.run(&mut [&mut door]);
+    assert_eq!(0, door.index);
+    assert_eq!("", door.data.trace);
+}
+
+#[test]
+fn timer_closes_door_after_delay() {
+    let (mut door, mut context) = started_door();
+    context.publish_event(DoorEvent::Open);
+    context.advance(999, &mut [&mut door]);
+    assert_eq!(1, door.index);
+    context.advance(1, &mut [&mut door]);
+    assert_eq!(0, door.index);
+    assert_eq!("+open-open", door.data.trace);
+}
+
+#[test]
+fn closing_early_cancels_timer_of_open_state() {
+    let (mut door, mut context) = started_door();
+    context.publish_event(DoorEvent::Open);
+    context.advance(500, &mut [&mut door]);
+    context.publish_event(DoorEvent::Close);
+    context.publish_event(DoorEvent::Open);
+    context.advance(999, &mut [&mut door]);
+    // still open: the first timer was cancelled, the second one is not due yet
+    assert_eq!(1, door.index);
+    assert_eq!("+open-open+open", door.data.trace);
+    assert_eq!(1, context.pending_timers());
+}
+
+//----------------------------------------------------------------------------
+// internal events run to completion before the next external event
+
+#[derive(Clone, Debug, PartialEq)]
+enum StepEvent {
+    Step(u8),
+    External,
+    Flood,
+}
+
+#[derive(Default)]
+struct Steps {
+    trace: String,
+}
+
+fn stepping_entry<'a>(_data: &'a mut Steps, context: &mut (dyn StateMachineContext<StepEvent> + 'a)) {
+    context.publish_event(StepEvent::External);
+    context.post_internal(StepEvent::Step(1)).unwrap();
+    context.post_internal(StepEvent::Step(2)).unwrap();
+}
+
+fn stepping_dispatch<'a>(
+    data: &'a mut Steps,
+    context: &mut (dyn StateMachineContext<StepEvent> + 'a),
+    event: &StepEvent,
+) -> ProcessingResult<Steps, StepEvent, Stepping> {
+    match event {
+        StepEvent::Step(n) => {
+            data.trace.push_str(&format!("{}", n));
+            if *n == 1 {
+                context.post_internal(StepEvent::Step(3)).unwrap();
+            }
+            ProcessingResult::Handled
+        }
+        StepEvent::External => {
+            data.trace.push('x');
+            ProcessingResult::Handled
+        }
+        StepEvent::Flood => {
+            for _ in 0..MAX_INTERNAL_EVENTS {
+                context.post_internal(StepEvent::Step(0)).unwrap();
+            }
+            assert_eq!(Err(PublishError::QueueFull), context.post_internal(StepEvent::Step(9)));
+            data.trace.push('!');
+            ProcessingResult::Handled
+        }
+    }
+}
+
+crate::state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum Stepping;
+
+    const STEPPING_STATES: [State<Steps, StepEvent>] = [
+        Busy { entry: stepping_entry, dispatch: stepping_dispatch },
+    ];
+}
+
+#[test]
+fn internal_events_are_processed_before_external_events() {
+    let mut sm = FiniteStateMachine::new(&STEPPING_STATES, Steps::default());
+    let mut context = SimulatedContext::<StepEvent>::new();
+    context.start(&mut [&mut sm]);
+    assert_eq!("123x", sm.data().trace);
+    // internal events never reach the runtime
+    assert!(context.published().eq(&[StepEvent::External]));
+}
+
+#[test]
+fn posting_to_full_internal_queue_fails() {
+    let mut sm = FiniteStateMachine::new(&STEPPING_STATES, Steps::default());
+    let mut context = SimulatedContext::<StepEvent>::new();
+    context.start(&mut [&mut sm]);
+    sm.data_mut().trace.clear();
+    context.publish_event(StepEvent::Flood);
+    context.run(&mut [&mut sm]);
+    assert_eq!("!00000000", sm.data().trace);
+}
+
+//----------------------------------------------------------------------------
+// jobs arriving while busy are deferred and recalled when idle again
+
+#[derive(Clone, Debug, PartialEq)]
+enum JobEvent {
+    Job(u8),
+    Ready,
+}
+
+#[derive(Default)]
+struct Jobs {
+    done: Vec<u8>,
+}
+
+fn busy_dispatch<'a>(
+    _data: &'a mut Jobs,
+    _context: &mut (dyn StateMachineContext<JobEvent> + 'a),
+    event: &JobEvent,
+) -> ProcessingResult<Jobs, JobEvent, Worker> {
+    match event {
+        JobEvent::Job(_) => ProcessingResult::Deferred,
+        JobEvent::Ready => ProcessingResult::Transition(Worker::Idle),
+    }
+}
+
+fn idle_entry<'a>(_data: &'a mut Jobs, context: &mut (dyn StateMachineContext<JobEvent> + 'a)) {
+    context.recall();
+}
+
+fn idle_dispatch<'a>(
+    data: &'a mut Jobs,
+    _context: &mut (dyn StateMachineContext<JobEvent> + 'a),
+    event: &JobEvent,
+) -> ProcessingResult<Jobs, JobEvent, Worker> {
+    match event {
+        JobEvent::Job(n) => {
+            data.done.push(*n);
+            ProcessingResult::Transition(Worker::Busy)
+        }
+        JobEvent::Ready => ProcessingResult::Ignored,
+    }
+}
+
+crate::state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum Worker;
+
+    const WORKER_STATES: [State<Jobs, JobEvent>] = [
+        Busy { dispatch: busy_dispatch, transitions: [Ready => Idle] },
+        Idle { entry: idle_entry, dispatch: idle_dispatch, transitions: [Job => Busy] },
+    ];
+}
+
+#[test]
+fn deferred_events_are_recalled_in_order() {
+    let mut worker = FiniteStateMachine::new(&WORKER_STATES, Jobs::default());
+    let mut context = SimulatedContext::<JobEvent>::new();
+    context.start(&mut [&mut worker]);
+    context.publish_event(JobEvent::Job(1));
+    context.publish_event(JobEvent::Job(2));
+    context.publish_event(JobEvent::Ready);
+    context.run(&mut [&mut worker]);
+    assert_eq!(vec![1], worker.data().done);
+    assert_eq!(&Worker::Busy, worker.current_state());
+    context.publish_event(JobEvent::Ready);
+    context.run(&mut [&mut worker]);
+    assert_eq!(vec![1, 2], worker.data().done);
+    context.publish_event(JobEvent::Ready);
+    context.run(&mut [&mut worker]);
+    assert_eq!(&Worker::Idle, worker.current_state());
+}
+
+/// Observer recording dropped events
+#[derive(Default)]
+struct DropRecorder {
+    dropped: Vec<JobEvent>,
+}
+
+impl StateMachineObserver<JobEvent, Worker> for DropRecorder {
+    fn event_dropped(&mut self, _state: &Worker, event: &JobEvent) {
+        self.dropped.push(event.clone());
+    }
+}
+
+#[test]
+fn deferring_to_full_queue_drops_event() {
+    let mut worker = FiniteStateMachine::new(&WORKER_STATES, Jobs::default()).with_observer(DropRecorder::default());
+    let mut context = SimulatedContext::<JobEvent>::new();
+    context.start(&mut [&mut worker]);
+    for n in 0..=MAX_DEFERRED_EVENTS as u8 {
+        context.publish_event(JobEvent::Job(n));
+    }
+    context.run(&mut [&mut worker]);
+    assert_eq!(vec![JobEvent::Job(MAX_DEFERRED_EVENTS as u8)], worker.observer().dropped);
+    context.publish_event(JobEvent::Ready);
+    context.run(&mut [&mut worker]);
+    assert_eq!(vec![0], worker.data().done);
+}
+
+//----------------------------------------------------------------------------
+// turnstile unlocking on enough credit; guard and action are declared
+
+#[derive(Clone, Debug, PartialEq)]
+enum TurnstileEvent {
+    Coin(u8),
+    Ticket,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TurnstileSignal {
+    Coin,
+    Ticket,
+}
+
+impl Signal for TurnstileEvent {
+    type Kind = TurnstileSignal;
+    fn signal(&self) -> TurnstileSignal {
+        match self {
+            TurnstileEvent::Coin(_) => TurnstileSignal::Coin,
+            TurnstileEvent::Ticket => TurnstileSignal::Ticket,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Turnstile {
+    credit: u8,
+    trace: String,
+}
+
+fn locked_exit<'a>(data: &'a mut Turnstile, _context: &mut (dyn StateMachineContext<TurnstileEvent> + 'a)) {
+    data.trace.push_str("-locked");
+}
+
+fn locked_dispatch<'a>(
+    data: &'a mut Turnstile,
+    _context: &mut (dyn StateMachineContext<TurnstileEvent> + 'a),
+    event: &TurnstileEvent,
+) -> ProcessingResult<Turnstile, TurnstileEvent, Gate> {
+    match event {
+        TurnstileEvent::Coin(amount) => {
+            data.credit += amount;
+            ProcessingResult::Handled
+        }
+        TurnstileEvent::Ticket => ProcessingResult::Ignored,
+    }
+}
+
+fn enough_credit(data: &Turnstile, event: &TurnstileEvent) -> bool {
+    matches!(event, TurnstileEvent::Coin(amount) if data.credit + amount >= 2)
+}
+
+fn thank<'a>(
+    data: &'a mut Turnstile,
+    _context: &mut (dyn StateMachineContext<TurnstileEvent> + 'a),
+    event: &TurnstileEvent,
+) {
+    data.trace.push_str(&format!("/thanks for {:?}", event));
+}
+
+fn stamp<'a>(
+    data: &'a mut Turnstile,
+    _context: &mut (dyn StateMachineContext<TurnstileEvent> + 'a),
+    event: &TurnstileEvent,
+) {
+    data.trace.push_str(&format!("/stamp {:?}", event));
+}
+
+fn unlocked_entry<'a>(data: &'a mut Turnstile, _context: &mut (dyn StateMachineContext<TurnstileEvent> + 'a)) {
+    data.trace.push_str("+unlocked");
+}
+
+crate::state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum Gate;
+
+    const GATE_STATES: [State<Turnstile, TurnstileEvent: Signal>] = [
+        Locked {
+            exit: locked_exit,
+            dispatch: locked_dispatch,
+            transitions: [Coin [enough_credit] => Unlocked / thank, Ticket => Unlocked / stamp],
+        },
+        Unlocked { entry: unlocked_entry },
+    ];
+}
+
+#[test]
+fn guard_blocks_transition_and_action_runs_between_exit_and_entry() {
+    let mut gate = FiniteStateMachine::new(&GATE_STATES, Turnstile::default());
+    let mut context = SimulatedContext::<TurnstileEvent>::new();
+    context.start(&mut [&mut gate]);
+    context.publish_event(TurnstileEvent::Coin(1));
+    context.run(&mut [&mut gate]);
+    assert_eq!(&Gate::Locked, gate.current_state());
+    assert_eq!("", gate.data().trace);
+    context.publish_event(TurnstileEvent::Coin(1));
+    context.run(&mut [&mut gate]);
+    assert_eq!(&Gate::Unlocked, gate.current_state());
+    assert_eq!("-locked/thanks for Coin(1)+unlocked", gate.data().trace);
+    assert_eq!(1, gate.data().credit);
+}
+
+//----------------------------------------------------------------------------
+// lamp dimmed by the action of a transition returned by the dispatch function
+
+#[derive(Default)]
+struct Lamp {
+    trace: String,
+}
+
+fn off_exit<'a>(data: &'a mut Lamp, _context: &mut (dyn StateMachineContext<u8> + 'a)) {
+    data.trace.push_str("-off");
+}
+
+fn off_dispatch<'a>(
+    _data: &'a mut Lamp,
+    _context: &mut (dyn StateMachineContext<u8> + 'a),
+    event: &u8,
+) -> ProcessingResult<Lamp, u8, Light> {
+    match event {
+        0 => ProcessingResult::Transition(Light::On),
+        _ => ProcessingResult::TransitionWith(Light::On, dim),
+    }
+}
+
+fn dim<'a>(data: &'a mut Lamp, _context: &mut (dyn StateMachineContext<u8> + 'a), event: &u8) {
+    data.trace.push_str(&format!("/dim {}", event));
+}
+
+fn on_entry<'a>(data: &'a mut Lamp, _context: &mut (dyn StateMachineContext<u8> + 'a)) {
+    data.trace.push_str("+on");
+}
+
+crate::state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum Light;
+
+    const LIGHT_STATES: [State<Lamp, u8>] = [
+        Off { exit: off_exit, dispatch: off_dispatch, transitions: [Switch => On, Dim => On / dim] },
+        On { entry: on_entry },
+    ];
+}
+
+#[test]
+fn returned_action_runs_between_exit_and_entry() {
+    let mut lamp = FiniteStateMachine::try_new(&LIGHT_STATES, Lamp::default()).unwrap();
+    let mut context = SimulatedContext::<u8>::new();
+    context.start(&mut [&mut lamp]);
+    context.publish_event(30);
+    context.run(&mut [&mut lamp]);
+    assert_eq!(&Light::On, lamp.current_state());
+    assert_eq!("-off/dim 30+on", lamp.data().trace);
+}
+
+#[test]
+fn transition_is_selected_by_event() {
+    let mut gate = FiniteStateMachine::new(&GATE_STATES, Turnstile::default());
+    let mut context = SimulatedContext::<TurnstileEvent>::new();
+    context.start(&mut [&mut gate]);
+    context.publish_event(TurnstileEvent::Ticket);
+    context.run(&mut [&mut gate]);
+    assert_eq!(&Gate::Unlocked, gate.current_state());
+    assert_eq!("-locked/stamp Ticket+unlocked", gate.data().trace);
+}
+
+/// Observer recording guard outcomes and transitions
+#[derive(Default)]
+struct GuardRecorder {
+    steps: Vec<String>,
+}
+
+impl StateMachineObserver<TurnstileEvent, Gate> for GuardRecorder {
+    fn event_handled(&mut self, state: &Gate, event: &TurnstileEvent) {
+        self.steps.push(format!("{:?} handles {:?}", state, event));
+    }
+    fn guard_evaluated(&mut self, state: &Gate, guard: &'static str, holds: bool) {
+        self.steps.push(format!("{:?} [{}] {}", state, guard, holds));
+    }
+    fn transition(&mut self, from: &Gate, to: &Gate) {
+        self.steps.push(format!("{:?} -> {:?}", from, to));
+    }
+}
+
+#[test]
+fn observer_sees_guard_outcomes() {
+    let mut gate = FiniteStateMachine::new(&GATE_STATES, Turnstile::default()).with_observer(GuardRecorder::default());
+    let mut context = SimulatedContext::<TurnstileEvent>::new();
+    context.start(&mut [&mut gate]);
+    context.publish_event(TurnstileEvent::Coin(1));
+    context.publish_event(TurnstileEvent::Coin(1));
+    context.run(&mut [&mut gate]);
+    assert_eq!(
+        vec![
+            "Locked [enough_credit] false",
+            "Locked handles Coin(1)",
+            "Locked [enough_credit] true",
+            "Locked -> Unlocked",
+        ],
+        gate.observer().steps
+    );
+}
+
+//----------------------------------------------------------------------------
+// thermostat branching through a choice and cooling until a completion
+// transition is enabled
+
+#[derive(Clone, Debug, PartialEq)]
+enum Reading {
+    Measure(i8),
+}
+
+#[derive(Default)]
+struct Thermostat {
+    temperature: i8,
+    trace: String,
+}
+
+fn idle_entry_trace<'a>(data: &'a mut Thermostat, _context: &mut (dyn StateMachineContext<Reading> + 'a)) {
+    data.trace.push_str("+idle");
+}
+
+fn idle_exit_trace<'a>(data: &'a mut Thermostat, _context: &mut (dyn StateMachineContext<Reading> + 'a)) {
+    data.trace.push_str("-idle");
+}
+
+fn cooling_entry<'a>(data: &'a mut Thermostat, _context: &mut (dyn StateMachineContext<Reading> + 'a)) {
+    data.trace.push_str("+cooling");
+}
+
+fn cooling_exit<'a>(data: &'a mut Thermostat, _context: &mut (dyn StateMachineContext<Reading> + 'a)) {
+    data.trace.push_str("-cooling");
+}
+
+fn start_fan<'a>(data: &'a mut Thermostat, _context: &mut (dyn StateMachineContext<Reading> + 'a)) {
+    data.trace.push_str("/fan");
+}
+
+fn too_hot(data: &Thermostat) -> bool {
+    data.temperature > 25
+}
+
+fn cool(data: &Thermostat) -> bool {
+    data.temperature <= 20
+}
+
+fn record(data: &mut Thermostat, event: &Reading) {
+    let Reading::Measure(temperature) = event;
+    data.temperature = *temperature;
+}
+
+fn climate_idle_dispatch<'a>(
+    data: &'a mut Thermostat,
+    _context: &mut (dyn StateMachineContext<Reading> + 'a),
+    event: &Reading,
+) -> ProcessingResult<Thermostat, Reading, Climate> {
+    record(data, event);
+    ProcessingResult::Transition(Climate::Check)
+}
+
+fn climate_cooling_dispatch<'a>(
+    data: &'a mut Thermostat,
+    _context: &mut (dyn StateMachineContext<Reading> + 'a),
+    event: &Reading,
+) -> ProcessingResult<Thermostat, Reading, Climate> {
+    record(data, event);
+    ProcessingResult::Transition(Climate::Cooling)
+}
+
+crate::state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum Climate;
+
+    const CLIMATE_STATES: [State<Thermostat, Reading>] = [
+        Idle { entry: idle_entry_trace, exit: idle_exit_trace, dispatch: climate_idle_dispatch, transitions: [Measure => Check] },
+        Check { kind: Choice, transitions: [[too_hot] => Cooling / start_fan, => Idle] },
+        Cooling {
+            entry: cooling_entry,
+            exit: cooling_exit,
+            dispatch: climate_cooling_dispatch,
+            transitions: [Measure => Cooling, [cool] => Idle],
+        },
+    ];
+}
+
+#[test]
+fn choice_branches_and_completion_transition_fires_after_entry() {
+    assert_eq!(Ok(()), crate::validate(&CLIMATE_STATES));
+    let mut thermostat = FiniteStateMachine::new(&CLIMATE_STATES, Thermostat::default());
+    let mut context = SimulatedContext::<Reading>::new();
+    context.start(&mut [&mut thermostat]);
+    let mut measure = |temperature, thermostat: &mut FiniteStateMachine<Thermostat, Reading, Climate>| {
+        thermostat.data_mut().trace.clear();
+        context.publish_event(Reading::Measure(temperature));
+        context.run(&mut [thermostat]);
+        String::from(thermostat.data().trace.as_str())
+    };
+    assert_eq!("-idle+idle", measure(10, &mut thermostat));
+    assert_eq!("-idle/fan+cooling", measure(30, &mut thermostat));
+    assert_eq!("-cooling+cooling", measure(22, &mut thermostat));
+    assert_eq!("-cooling+cooling-cooling+idle", measure(18, &mut thermostat));
+    assert_eq!(&Climate::Idle, thermostat.current_state());
+}
+
+crate::state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum PingPong;
+
+    const PING_PONG_STATES: [State<(), Reading>] = [
+        Ping { transitions: [=> Pong] },
+        Pong { transitions: [=> Ping] },
+    ];
+}
+
+#[test]
+#[should_panic(expected = "Transition chain too long")]
+fn endless_completion_transitions_are_detected() {
+    assert_eq!(Err(ValidationError::CompletionCycle { index: 0 }), FiniteStateMachine::try_new(&PING_PONG_STATES, ()).map(|_| ()));
+    let mut sm = FiniteStateMachine::new(&PING_PONG_STATES, ());
+    sm.start(&mut SimulatedContext::<Reading>::new());
+}
+
+struct Data;
+
+#[derive(Clone)]
+enum Event {
+    Next,
+}
+
+/// Context numbering armed timers and recording cancelled timers
+#[derive(Default)]
+struct TimerContext {
+    armed: u64,
+    cancelled: Vec<TimerHandle>,
+}
+
+impl StateMachineContext<Event> for TimerContext {
+    fn publish_event(&mut self, _e: Event) {}
+    fn send_to(&mut self, _target: MachineId, _e: Event) {}
+    fn arm_timer(&mut self, _delay_in_ms: u64, _e: Event) -> TimerHandle {
+        self.armed += 1;
+        TimerHandle(self.armed)
+    }
+    fn arm_periodic_timer(&mut self, period_in_ms: u64, e: Event) -> TimerHandle {
+        self.arm_timer(period_in_ms, e)
+    }
+    fn cancel_timer(&mut self, handle: TimerHandle) -> bool {
+        self.cancelled.push(handle);
+        true
+    }
+}
+
+fn arm_entry<'a>(_data: &'a mut Data, context: &mut (dyn StateMachineContext<Event> + 'a)) {
+    context.arm_timer(100, Event::Next);
+    context.arm_periodic_timer(10, Event::Next);
+    context.publish_delayed_event(100, Event::Next);
+}
+
+fn arm_exit<'a>(_data: &'a mut Data, context: &mut (dyn StateMachineContext<Event> + 'a)) {
+    context.arm_timer(100, Event::Next);
+}
+
+fn first_dispatch<'a>(
+    _data: &'a mut Data,
+    _context: &mut (dyn StateMachineContext<Event> + 'a),
+    _event: &Event,
+) -> ProcessingResult<Data, Event, Timed> {
+    ProcessingResult::Transition(Timed::Second)
+}
+
+crate::state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum Timed;
+
+    const TIMED_STATES: [State<Data, Event>] = [
+        First { entry: arm_entry, exit: arm_exit, dispatch: first_dispatch, transitions: [Next => Second] },
+        Second {},
+    ];
+}
+
+#[test]
+fn timers_armed_by_state_are_cancelled_on_exit() {
+    let mut context = TimerContext::default();
+    let mut sm = FiniteStateMachine::new(&TIMED_STATES, Data).cancel_timers_on_exit();
+    sm.start(&mut context);
+    assert_eq!(3, context.armed);
+    sm.dispatch(&mut context, Event::Next);
+    // the fire and forget delayed event and the timer armed on exit are kept
+    assert_eq!(vec![TimerHandle(1), TimerHandle(2)], context.cancelled);
+}
+
+#[test]
+fn timers_are_kept_by_default() {
+    let mut context = TimerContext::default();
+    let mut sm = FiniteStateMachine::new(&TIMED_STATES, Data);
+    sm.start(&mut context);
+    sm.dispatch(&mut context, Event::Next);
+    assert!(context.cancelled.is_empty());
+}
+
+#[test]
+fn stop_exits_active_state() {
+    let mut context = TimerContext::default();
+    let mut sm = FiniteStateMachine::new(&TIMED_STATES, Data).cancel_timers_on_exit();
+    sm.start(&mut context);
+    sm.stop(&mut context);
+    // the exit action of First arms the fourth timer
+    assert_eq!(4, context.armed);
+    assert_eq!(vec![TimerHandle(1), TimerHandle(2)], context.cancelled);
+}
+
+//----------------------------------------------------------------------------
+// subscriptions are handed through to the runtime context
+
+/// Context recording subscribed and unsubscribed kinds
+#[derive(Default)]
+struct SubscriptionContext {
+    changes: Vec<(bool, u8)>,
+}
+
+impl StateMachineContext<u8> for SubscriptionContext {
+    fn publish_event(&mut self, _e: u8) {}
+    fn send_to(&mut self, _target: MachineId, _e: u8) {}
+    fn arm_timer(&mut self, _delay_in_ms: u64, _e: u8) -> TimerHandle {
+        TimerHandle(0)
+    }
+    fn arm_periodic_timer(&mut self, _period_in_ms: u64, _e: u8) -> TimerHandle {
+        TimerHandle(0)
+    }
+    fn cancel_timer(&mut self, _handle: TimerHandle) -> bool {
+        false
+    }
+    fn subscribe(&mut self, kind: u8) {
+        self.changes.push((true, kind));
+    }
+    fn unsubscribe(&mut self, kind: u8) {
+        self.changes.push((false, kind));
+    }
+}
+
+fn subscribing_entry<'a>(_data: &'a mut Data, context: &mut (dyn StateMachineContext<u8> + 'a)) {
+    context.subscribe(1);
+    context.subscribe(2);
+}
+
+fn unsubscribing_dispatch<'a>(
+    _data: &'a mut Data,
+    context: &mut (dyn StateMachineContext<u8> + 'a),
+    event: &u8,
+) -> ProcessingResult<Data, u8, Listening> {
+    context.unsubscribe(*event);
+    ProcessingResult::Handled
+}
+
+crate::state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum Listening;
+
+    const LISTENING_STATES: [State<Data, u8>] = [
+        Subscribed { entry: subscribing_entry, dispatch: unsubscribing_dispatch },
+    ];
+}
 
 #[test]
-fn it_works() {
-    let result = 2 + 2;
-    assert_eq!(result, 4);
-}
\ No newline at end of file
+fn subscriptions_are_handed_to_the_runtime_context() {
+    let mut context = SubscriptionContext::default();
+    let mut sm = FiniteStateMachine::new(&LISTENING_STATES, Data);
+    sm.start(&mut context);
+    sm.dispatch(&mut context, 1);
+    assert_eq!(vec![(true, 1), (true, 2), (false, 1)], context.changes);
+}
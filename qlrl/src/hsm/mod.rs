@@ -0,0 +1,689 @@
+//! Hierarchical State Machine processor
+//!
+//! States are nested via the `super_state` field of the state table.
+//!
+//! - An event not handled by the active state (`ProcessingResult::Ignored`)
+//!   bubbles up the `super_state` chain until some state handles it; each
+//!   state offers the event to the transitions it triggers before its
+//!   dispatch function sees it
+//! - A transition exits all states up to the least common ancestor of
+//!   source and target (innermost first) and enters all states down to the
+//!   target (outermost first)
+//! - After entering the target the `init` functions are followed into the
+//!   nested initial sub states; a composite state with `history` resumes its
+//!   last active sub state instead once it has been left
+//! - Internal events posted via `post_internal` are dispatched right after
+//!   the event (or start) that posted them, before the next external event
+//! - An event deferred by any state of the active configuration is kept until
+//!   `recall` queues it as internal event again
+//! - After a transition the completion transitions of the innermost states
+//!   it entered are evaluated; a transition to a choice pseudo state
+//!   continues along the first enabled branch without entering the choice
+//! - The direct sub states of an orthogonal state are regions that are
+//!   entered together, in the order of the state list, and exited together,
+//!   the last region first; a region entered by a transition to a state
+//!   inside one of its siblings follows its history or `init`
+//! - An event is offered to the active state of each region in the order of
+//!   the state list and bubbles up within the region; it reaches the
+//!   orthogonal state only if no region stopped it. An event deferred by one
+//!   region but handled by another is not deferred
+//! - A transition to a state in a sibling region exits and re-enters the
+//!   orthogonal state; deep history above an orthogonal state resumes the
+//!   first region, the other regions follow their history or `init`
+//!
+use core::cmp::PartialEq;
+
+use super::context::{EventQueues, MachineContext, OwnedTimers};
+use super::{
+    index_of, select_completion, select_triggered, super_index, validate, Action, History, Introspect,
+    NoObserver, ProcessingResult, State, StateKind, StateMachine, StateMachineContext, StateMachineObserver,
+    ValidationError, MAX_TRANSITION_CHAIN,
+};
+
+/// Maximum number of nesting levels supported by the processor
+pub const MAX_NESTING_DEPTH: usize = 8;
+
+/// Maximum number of composite states with history supported by the processor
+pub const MAX_HISTORY_STATES: usize = 8;
+
+/// Maximum number of regions that can be active at the same time
+pub const MAX_ACTIVE_REGIONS: usize = 8;
+
+/// Last active innermost state of each composite state with history
+struct HistoryRecord {
+    entries: [Option<(usize, usize)>; MAX_HISTORY_STATES], // (composite, innermost)
+}
+
+impl HistoryRecord {
+    const fn new() -> Self {
+        HistoryRecord {
+            entries: [None; MAX_HISTORY_STATES],
+        }
+    }
+
+    fn record(&mut self, composite: usize, innermost: usize) {
+        let slot = self
+            .entries
+            .iter()
+            .position(|entry| matches!(entry, Some((c, _)) if *c == composite))
+            .or_else(|| self.entries.iter().position(Option::is_none));
+        // `try_new` ensures a slot for each state with history
+        if let Some(slot) = slot {
+            self.entries[slot] = Some((composite, innermost));
+        }
+    }
+
+    fn last(&self, composite: usize) -> Option<usize> {
+        self.entries.iter().flatten().find(|(c, _)| *c == composite).map(|(_, i)| *i)
+    }
+}
+
+/// Active innermost state of each active region, in the order of the state list
+///
+/// While states are exited an innermost state is replaced by its super state
+/// until the last region of an orthogonal state is left.
+struct Configuration {
+    leaves: [usize; MAX_ACTIVE_REGIONS],
+    origins: [usize; MAX_ACTIVE_REGIONS], // innermost state before exiting up
+    entered: [bool; MAX_ACTIVE_REGIONS],  // completion transitions not evaluated yet
+    len: usize,
+    cursor: usize, // position of the next entered innermost state
+}
+
+impl Configuration {
+    const fn new() -> Self {
+        Configuration {
+            leaves: [0; MAX_ACTIVE_REGIONS],
+            origins: [0; MAX_ACTIVE_REGIONS],
+            entered: [false; MAX_ACTIVE_REGIONS],
+            len: 0,
+            cursor: 0,
+        }
+    }
+
+    fn leaves(&self) -> &[usize] {
+        &self.leaves[..self.len]
+    }
+
+    fn position(&self, index: usize) -> Option<usize> {
+        self.leaves().iter().position(|&leaf| leaf == index)
+    }
+
+    fn insert(&mut self, index: usize) {
+        assert!(self.len < MAX_ACTIVE_REGIONS, "Too many active regions");
+        self.leaves.copy_within(self.cursor..self.len, self.cursor + 1);
+        self.origins.copy_within(self.cursor..self.len, self.cursor + 1);
+        self.entered.copy_within(self.cursor..self.len, self.cursor + 1);
+        self.leaves[self.cursor] = index;
+        self.origins[self.cursor] = index;
+        self.entered[self.cursor] = true;
+        self.len += 1;
+        self.cursor += 1;
+    }
+
+    fn remove(&mut self, position: usize) {
+        self.leaves.copy_within(position + 1..self.len, position);
+        self.origins.copy_within(position + 1..self.len, position);
+        self.entered.copy_within(position + 1..self.len, position);
+        self.len -= 1;
+    }
+
+    /// The first innermost state entered since it was last taken
+    fn take_entered(&mut self) -> Option<usize> {
+        let position = self.entered[..self.len].iter().position(|&entered| entered)?;
+        self.entered[position] = false;
+        Some(self.leaves[position])
+    }
+
+    /// Insert the next entered states at `position`, replacing `placeholder`
+    fn reopen(&mut self, position: usize, placeholder: Option<usize>) {
+        if placeholder.is_some() && self.leaves().get(position) == placeholder.as_ref() {
+            self.remove(position);
+        }
+        self.cursor = position;
+    }
+}
+
+pub struct HierarchicalStateMachine<D: 'static, E: 'static, S: PartialEq + 'static, O = NoObserver> {
+    active: Configuration,
+    state_list: &'static [State<D, E, S>],
+    data: D,
+    timers: OwnedTimers,
+    events: EventQueues<E>,
+    history: HistoryRecord,
+    observer: O,
+}
+
+impl<D, E, S: PartialEq> HierarchicalStateMachine<D, E, S> {
+    /// Create a hierarchical state machine
+    ///
+    /// The first state of the state list is the initial state. The state list
+    /// is not validated, an invalid one panics on processing, e.g. a
+    /// completion chain longer than `MAX_TRANSITION_CHAIN`; prefer `try_new`.
+    pub fn new(state_list: &'static [State<D, E, S>], data: D) -> Self {
+        HierarchicalStateMachine {
+            state_list,
+            active: Configuration::new(),
+            data, // data is moved
+            timers: OwnedTimers::new(),
+            events: EventQueues::new(),
+            history: HistoryRecord::new(),
+            observer: NoObserver,
+        }
+    }
+
+    /// Create a hierarchical state machine from a validated state list
+    ///
+    /// Besides `validate` this checks that no state is nested deeper than
+    /// `MAX_NESTING_DEPTH`, that at most `MAX_HISTORY_STATES` states have
+    /// a history and that at most `MAX_ACTIVE_REGIONS` regions can be active.
+    pub fn try_new(state_list: &'static [State<D, E, S>], data: D) -> Result<Self, ValidationError> {
+        validate(state_list)?;
+        if state_list.iter().filter(|state| state.history.is_some()).count() > MAX_HISTORY_STATES {
+            return Err(ValidationError::TooManyHistoryStates);
+        }
+        for index in 0..state_list.len() {
+            let mut depth = 1;
+            let mut current = super_index(state_list, index);
+            while let Some(i) = current {
+                depth += 1;
+                current = super_index(state_list, i);
+            }
+            if depth > MAX_NESTING_DEPTH {
+                return Err(ValidationError::NestingTooDeep { index });
+            }
+        }
+        let regions = (0..state_list.len())
+            .filter(|&i| super_index(state_list, i).is_none())
+            .map(|i| max_regions(state_list, i))
+            .max()
+            .unwrap_or(0);
+        if regions > MAX_ACTIVE_REGIONS {
+            return Err(ValidationError::TooManyRegions);
+        }
+        Ok(Self::new(state_list, data))
+    }
+}
+
+/// Number of regions that can be active at the same time inside the state
+/// at `index`
+fn max_regions<D, E, S: PartialEq>(state_list: &[State<D, E, S>], index: usize) -> usize {
+    let sub_states = (0..state_list.len()).filter(|&i| super_index(state_list, i) == Some(index));
+    if state_list[index].kind == StateKind::Orthogonal {
+        sub_states.map(|i| max_regions(state_list, i)).sum()
+    } else {
+        sub_states.map(|i| max_regions(state_list, i)).max().unwrap_or(1)
+    }
+}
+
+impl<D, E, S: PartialEq, O> HierarchicalStateMachine<D, E, S, O> {
+    /// Report the event processing to the given observer
+    pub fn with_observer<P: StateMachineObserver<E, S>>(self, observer: P) -> HierarchicalStateMachine<D, E, S, P> {
+        HierarchicalStateMachine {
+            active: self.active,
+            state_list: self.state_list,
+            data: self.data,
+            timers: self.timers,
+            events: self.events,
+            history: self.history,
+            observer,
+        }
+    }
+
+    /// Cancel the timers armed by the entry action or dispatch function of
+    /// a state when the state is exited
+    pub fn cancel_timers_on_exit(mut self) -> Self {
+        self.timers.enable();
+        self
+    }
+
+    /// State machine private data
+    pub fn data(&self) -> &D {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut D {
+        &mut self.data
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// The active innermost state of the first region
+    fn index(&self) -> usize {
+        self.active.leaves().first().copied().unwrap_or(0)
+    }
+
+    fn find(&self, state: &S) -> usize {
+        index_of(self.state_list, state).expect("State specification not found ")
+    }
+
+    fn super_index(&self, index: usize) -> Option<usize> {
+        self.state_list[index].super_state.as_ref().map(|s| self.find(s))
+    }
+
+    /// Check whether the state at `index` is nested inside `ancestor`, any
+    /// state is nested inside the top (`None`)
+    fn is_below(&self, index: usize, ancestor: Option<usize>) -> bool {
+        let mut current = self.super_index(index);
+        while current.is_some() && current != ancestor {
+            current = current.and_then(|i| self.super_index(i));
+        }
+        current == ancestor
+    }
+
+    /// Collect the given state and all its super states, innermost first
+    fn path(&self, index: usize, path: &mut [usize; MAX_NESTING_DEPTH]) -> usize {
+        let mut len = 0;
+        let mut next = Some(index);
+        while let Some(i) = next {
+            assert!(len < MAX_NESTING_DEPTH, "State nesting too deep");
+            path[len] = i;
+            len += 1;
+            next = self.super_index(i);
+        }
+        len
+    }
+
+    /// Least common ancestor of `from` (or one of its super states) and `target`
+    ///
+    /// An orthogonal state is not common to two of its regions.
+    fn common_ancestor(&self, from: Option<usize>, target: usize) -> Option<usize> {
+        let mut target_path = [0; MAX_NESTING_DEPTH];
+        let len = self.path(target, &mut target_path);
+        let mut current = from;
+        while let Some(i) = current {
+            let orthogonal = self.state_list[i].kind == StateKind::Orthogonal && i != target;
+            if target_path[..len].contains(&i) && !orthogonal {
+                break;
+            }
+            current = self.super_index(i);
+        }
+        current
+    }
+
+    /// Sub state to resume when entering the composite state at `index`
+    fn resume(&self, index: usize) -> Option<usize> {
+        let innermost = self.history.last(index)?;
+        match self.state_list[index].history? {
+            History::Deep => Some(innermost),
+            History::Shallow => {
+                let mut current = innermost;
+                loop {
+                    match self.super_index(current) {
+                        Some(super_index) if super_index == index => break Some(current),
+                        Some(super_index) => current = super_index,
+                        None => break None,
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<D, E, S: PartialEq, O: StateMachineObserver<E, S>> HierarchicalStateMachine<D, E, S, O> {
+    fn enter<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a), index: usize) {
+        self.observer.entered(&self.state_list[index].state);
+        let mut entry_context = MachineContext::new(context, &mut self.timers, &mut self.events, Some(index));
+        (self.state_list[index].entry)(&mut self.data, &mut entry_context);
+    }
+
+    /// Exit the state at `index`, which must not have active sub states
+    fn exit<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a), index: usize) {
+        let position = self.active.position(index).expect("Exited state is not active");
+        if self.state_list[index].history.is_some() {
+            self.history.record(index, self.active.origins[position]);
+        }
+        // the super state becomes innermost unless other regions are still active
+        let super_index = self.super_index(index);
+        let siblings = self
+            .active
+            .leaves()
+            .iter()
+            .any(|&leaf| leaf != index && super_index.is_some() && self.is_below(leaf, super_index));
+        match super_index {
+            Some(super_index) if !siblings => {
+                self.active.leaves[position] = super_index;
+                self.active.entered[position] = false;
+            }
+            _ => self.active.remove(position),
+        }
+        self.observer.exited(&self.state_list[index].state);
+        let mut exit_context = MachineContext::new(context, &mut self.timers, &mut self.events, None);
+        (self.state_list[index].exit)(&mut self.data, &mut exit_context);
+        self.timers.cancel_owned_by(context, index);
+    }
+
+    /// Exit all active states nested inside `root`, innermost first and the
+    /// last region first; `None` exits all active states
+    fn exit_below<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a), root: Option<usize>) {
+        while let Some(&leaf) = self
+            .active
+            .leaves()
+            .iter()
+            .rev()
+            .find(|&&leaf| Some(leaf) != root && self.is_below(leaf, root))
+        {
+            self.exit(context, leaf);
+        }
+    }
+
+    fn act<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a), action: &Action<D, E>, event: Option<&E>) {
+        let mut action_context = MachineContext::new(context, &mut self.timers, &mut self.events, None);
+        action.execute(&mut self.data, &mut action_context, event);
+    }
+
+    /// Enter all states from (excluding) `from` down to (including) `to` and
+    /// follow the history or `init` of `to`
+    ///
+    /// `from` must be a super state of `to` or `None` for the top.
+    fn enter_into<'a>(
+        &mut self,
+        context: &mut (dyn StateMachineContext<E> + 'a),
+        from: Option<usize>,
+        to: usize,
+    ) {
+        let mut path = [0; MAX_NESTING_DEPTH];
+        let len = self.path(to, &mut path);
+        let len = match from {
+            Some(from) => path[..len]
+                .iter()
+                .position(|&i| i == from)
+                .expect("Initial state is not a sub state"),
+            None => len,
+        };
+        path[..len].reverse();
+        if len == 0 {
+            self.drill_into(context, to);
+        } else {
+            self.enter_path(context, &path[..len]);
+        }
+    }
+
+    /// Enter the given states, outermost first, including the regions of the
+    /// orthogonal states on the way
+    fn enter_path<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a), path: &[usize]) {
+        let (&index, rest) = path.split_first().expect("Empty path");
+        self.enter(context, index);
+        if rest.is_empty() {
+            self.drill_into(context, index);
+        } else if self.state_list[index].kind == StateKind::Orthogonal {
+            for region in 0..self.state_list.len() {
+                if region == rest[0] {
+                    self.enter_path(context, rest);
+                } else if self.super_index(region) == Some(index) {
+                    self.enter_path(context, &[region]);
+                }
+            }
+        } else {
+            self.enter_path(context, rest);
+        }
+    }
+
+    /// Follow the history or the `init` functions into the nested sub states
+    /// of the entered state at `index`, or all regions of an orthogonal state
+    fn drill_into<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a), index: usize) {
+        if self.state_list[index].kind == StateKind::Orthogonal {
+            for region in 0..self.state_list.len() {
+                if self.super_index(region) == Some(index) {
+                    self.enter_path(context, &[region]);
+                }
+            }
+            return;
+        }
+        let sub_index = match self.resume(index) {
+            Some(sub_index) => sub_index,
+            None => match (self.state_list[index].init)() {
+                Some(sub_state) => self.find(&sub_state),
+                None => return self.active.insert(index),
+            },
+        };
+        self.enter_into(context, Some(index), sub_index);
+    }
+
+    /// Perform a transition triggered by the handler of state `source`
+    ///
+    /// `source` is an active state. The action is executed after exiting up
+    /// to the least common ancestor, it receives the triggering event (if
+    /// any); a choice target is passed along its first enabled branch.
+    fn transition<'a>(
+        &mut self,
+        context: &mut (dyn StateMachineContext<E> + 'a),
+        source: usize,
+        target: usize,
+        action: Option<&Action<D, E>>,
+        event: Option<&E>,
+    ) {
+        if source == target {
+            // self transition: exit and re-enter the source
+            self.exit_below(context, Some(source));
+            let position = self.active.position(source).expect("Source is not active");
+            self.exit(context, source);
+            let super_index = self.super_index(source);
+            self.active.reopen(position, super_index);
+            if let Some(action) = action {
+                self.act(context, action, event);
+            }
+            self.enter_into(context, super_index, target);
+            return;
+        }
+
+        // exit up to the least common ancestor, then enter down to the target
+        let (mut from, mut target, mut action, mut event) = (Some(source), target, action, event);
+        let mut steps = 0;
+        let lca = loop {
+            let lca = self.common_ancestor(from, target);
+            self.exit_below(context, lca);
+            if let Some(action) = action {
+                self.act(context, action, event);
+            }
+            let choice = &self.state_list[target];
+            if choice.kind != StateKind::Choice {
+                break lca;
+            }
+            // the choice is not entered, continue from the common ancestor
+            let branch = select_completion(choice, &self.data, &mut self.observer).expect("Choice without else branch");
+            steps += 1;
+            assert!(steps <= MAX_TRANSITION_CHAIN, "Transition chain too long");
+            let next = self.find(&branch.target);
+            self.observer.transition(&choice.state, &self.state_list[next].state);
+            (from, target, action, event) = (lca, next, branch.action.as_ref(), None);
+        };
+        let position = lca.and_then(|lca| self.active.position(lca)).unwrap_or(0);
+        self.active.reopen(position, lca);
+        self.enter_into(context, lca, target);
+    }
+
+    /// Take a transition returned by the dispatch function of state `handler`
+    fn returned<'a>(
+        &mut self,
+        context: &mut (dyn StateMachineContext<E> + 'a),
+        handler: usize,
+        target: &S,
+        action: Option<&Action<D, E>>,
+        event: &E,
+    ) {
+        let target = self.find(target);
+        self.observer.transition(&self.state_list[handler].state, &self.state_list[target].state);
+        self.transition(context, handler, target, action, Some(event));
+    }
+
+    /// Take the enabled completion transitions of the innermost states
+    /// entered since the last call, including those entered on the way
+    fn follow_completions<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a)) {
+        let mut steps = 0;
+        while let Some(source) = self.active.take_entered() {
+            let Some(next) = select_completion(&self.state_list[source], &self.data, &mut self.observer) else {
+                continue;
+            };
+            steps += 1;
+            assert!(steps <= MAX_TRANSITION_CHAIN, "Transition chain too long");
+            let target = self.find(&next.target);
+            self.observer.transition(&self.state_list[source].state, &self.state_list[target].state);
+            self.transition(context, source, target, next.action.as_ref(), None);
+        }
+    }
+
+    /// Dispatch a single event to the active state of each region
+    fn process<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a), event: E) {
+        let (leaves, len) = (self.active.leaves, self.active.len);
+        let leaves = &leaves[..len];
+        let mut stopped = [false; MAX_ACTIVE_REGIONS]; // per region
+        let (mut consumed, mut transition, mut deferred_by) = (false, false, None);
+        for (position, &leaf) in leaves.iter().enumerate() {
+            if self.active.position(leaf).is_none() {
+                // left by a transition of an earlier region
+                continue;
+            }
+            let mut handler = leaf;
+            self.observer.event_received(&self.state_list[handler].state, &event);
+            let stop = loop {
+                let triggered = select_triggered(&self.state_list[handler], &self.data, &event, &mut self.observer);
+                if let Some(triggered) = triggered {
+                    let target = self.find(&triggered.target);
+                    self.observer.transition(&self.state_list[handler].state, &self.state_list[target].state);
+                    self.transition(context, handler, target, triggered.action.as_ref(), Some(&event));
+                    (consumed, transition) = (true, true);
+                    break true;
+                }
+                let mut dispatch_context =
+                    MachineContext::new(context, &mut self.timers, &mut self.events, Some(handler));
+                match (self.state_list[handler].dispatch)(&mut self.data, &mut dispatch_context, &event) {
+                    ProcessingResult::Handled => {
+                        self.observer.event_handled(&self.state_list[handler].state, &event);
+                        consumed = true;
+                        break true;
+                    }
+                    ProcessingResult::Top => {
+                        self.observer.event_ignored(&self.state_list[handler].state, &event);
+                        break true;
+                    }
+                    ProcessingResult::Deferred => {
+                        deferred_by.get_or_insert(handler);
+                        break true;
+                    }
+                    ProcessingResult::Ignored => match self.bubble_up(handler, &event, position, leaves, &stopped) {
+                        Some(super_index) => handler = super_index,
+                        None => break false,
+                    },
+                    ProcessingResult::SuperState(super_state) => handler = self.find(&super_state),
+                    ProcessingResult::Transition(new_state) => {
+                        self.returned(context, handler, &new_state, None, &event);
+                        (consumed, transition) = (true, true);
+                        break true;
+                    }
+                    ProcessingResult::TransitionWith(new_state, run) => {
+                        self.returned(context, handler, &new_state, Some(&Action::returned(run)), &event);
+                        (consumed, transition) = (true, true);
+                        break true;
+                    }
+                }
+            };
+            stopped[position] = stop;
+        }
+        if transition {
+            self.follow_completions(context);
+        }
+        if let Some(handler) = deferred_by.filter(|_| !consumed) {
+            self.observer.event_deferred(&self.state_list[handler].state, &event);
+            if let Err(event) = self.events.defer(event) {
+                self.observer.event_dropped(&self.state_list[handler].state, &event);
+            }
+        }
+    }
+
+    /// Next handler for an event ignored by the state `handler` in the region
+    /// of `leaves[position]`, reports the event as ignored at the top
+    ///
+    /// The event passes an orthogonal state from its last region that gets
+    /// the event and only if no region stopped it.
+    fn bubble_up(&mut self, handler: usize, event: &E, position: usize, leaves: &[usize], stopped: &[bool]) -> Option<usize> {
+        let super_index = self.super_index(handler);
+        match super_index {
+            None => self.observer.event_ignored(&self.state_list[handler].state, event),
+            Some(orthogonal) if self.state_list[orthogonal].kind == StateKind::Orthogonal => {
+                let inside = |leaf: usize| self.is_below(leaf, super_index);
+                let later = leaves[position + 1..]
+                    .iter()
+                    .any(|&leaf| inside(leaf) && self.active.position(leaf).is_some());
+                let stopped = leaves[..position].iter().zip(stopped).any(|(&leaf, &stopped)| stopped && inside(leaf));
+                if later || stopped {
+                    return None;
+                }
+            }
+            Some(_) => {}
+        }
+        super_index
+    }
+
+    /// Dispatch the queued internal events (run to completion)
+    fn complete<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a)) {
+        while let Some(event) = self.events.next() {
+            self.process(context, event);
+        }
+    }
+}
+
+impl<D, E, S: PartialEq, O> Introspect<S> for HierarchicalStateMachine<D, E, S, O> {
+    /// The active innermost state of the first region
+    fn current_state(&self) -> &S {
+        &self.state_list[self.index()].state
+    }
+
+    /// Visit the active states region by region, innermost first; a super
+    /// state shared by several regions is visited with the first of them
+    fn active_states(&self, visit: &mut dyn FnMut(&S)) {
+        let leaves = self.active.leaves();
+        for (position, &leaf) in leaves.iter().enumerate() {
+            let mut current = Some(leaf);
+            while let Some(i) = current {
+                if leaves[..position].iter().any(|&earlier| self.is_below(earlier, Some(i))) {
+                    break;
+                }
+                visit(&self.state_list[i].state);
+                current = self.super_index(i);
+            }
+        }
+    }
+}
+
+impl<D, E, S, O> StateMachine<E> for HierarchicalStateMachine<D, E, S, O>
+where
+    S: PartialEq,
+    E: Send,
+    O: StateMachineObserver<E, S>,
+{
+    /// Dispatch an event and the internal events posted while processing it
+    ///
+    /// The event is offered to the active state of each region and bubbles
+    /// up the `super_state` chain as long as it is ignored.
+    fn dispatch<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a), event: E) {
+        self.process(context, event);
+        self.complete(context);
+    }
+
+    /// Start the state machine i.e. enter the first state of the state list
+    /// including all its super states and follow its initial sub states
+    ///
+    /// ```mermaid
+    /// [*] --> FirstState
+    /// ```
+    fn start<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a)) {
+        self.active.reopen(0, None);
+        self.enter_into(context, None, 0);
+        self.follow_completions(context);
+        self.complete(context);
+    }
+
+    /// Stop the state machine i.e. exit all active states, innermost first
+    /// and the last region first; pending internal and deferred events are
+    /// dropped
+    fn stop<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a)) {
+        self.exit_below(context, None);
+        self.events.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests;
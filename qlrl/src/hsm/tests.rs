@@ -0,0 +1,636 @@
+extern crate std;
+use std::{format, string::String, vec, vec::Vec};
+
+use super::*;
+use crate::{Action, Check, Effect, Guard, History, Introspect, MachineId, StateKind, StateMachineObserver, TimerHandle, TransitionSpec};
+
+// State hierarchy used by the tests; states are named by a single character
+//
+// T (init -> A)
+// +-- A (init -> x)
+// |   +-- x
+// |   +-- y
+// +-- B (init -> z)
+//     +-- z
+
+#[derive(Default)]
+struct Data {
+    trace: String,
+    done: bool,
+}
+
+enum Event {
+    /// The given state returns a transition to the target state
+    Tran(char, char),
+    /// The given state returns a transition to the target state along with
+    /// an action
+    TranWith(char, char),
+    /// Every state traces the event; the given state stops bubbling via `Top`
+    Cut(char),
+    /// The first state delegates to the second state which handles the event
+    Delegate(char, char),
+    /// The given state arms a timer
+    Arm(char),
+    /// The given state posts an internal transition event from source to target
+    Post(char, char, char),
+    /// The first state defers the event, the second state handles it
+    Defer(char, char),
+    /// Triggers the declared transitions to the given state
+    Go(char),
+}
+
+struct Context;
+
+impl StateMachineContext<Event> for Context {
+    fn publish_event(&mut self, _e: Event) {}
+    fn send_to(&mut self, _target: MachineId, _e: Event) {}
+    fn arm_timer(&mut self, _delay_in_ms: u64, _e: Event) -> TimerHandle {
+        TimerHandle(0)
+    }
+    fn arm_periodic_timer(&mut self, _period_in_ms: u64, _e: Event) -> TimerHandle {
+        TimerHandle(0)
+    }
+    fn cancel_timer(&mut self, _handle: TimerHandle) -> bool {
+        false
+    }
+}
+
+/// Context numbering armed timers and recording cancelled timers
+#[derive(Default)]
+struct TimerContext {
+    armed: u64,
+    cancelled: Vec<TimerHandle>,
+}
+
+impl StateMachineContext<Event> for TimerContext {
+    fn publish_event(&mut self, _e: Event) {}
+    fn send_to(&mut self, _target: MachineId, _e: Event) {}
+    fn arm_timer(&mut self, _delay_in_ms: u64, _e: Event) -> TimerHandle {
+        self.armed += 1;
+        TimerHandle(self.armed)
+    }
+    fn arm_periodic_timer(&mut self, period_in_ms: u64, e: Event) -> TimerHandle {
+        self.arm_timer(period_in_ms, e)
+    }
+    fn cancel_timer(&mut self, handle: TimerHandle) -> bool {
+        self.cancelled.push(handle);
+        true
+    }
+}
+
+fn init<const SUB: char>() -> Option<char> {
+    Some(SUB)
+}
+
+fn leaf() -> Option<char> {
+    None
+}
+
+fn entry<'a, const S: char>(data: &'a mut Data, context: &mut (dyn StateMachineContext<Event> + 'a)) {
+    data.trace.push('+');
+    data.trace.push(S);
+    context.recall();
+}
+
+fn exit<'a, const S: char>(data: &'a mut Data, _context: &mut (dyn StateMachineContext<Event> + 'a)) {
+    data.trace.push('-');
+    data.trace.push(S);
+}
+
+fn dispatch<'a, const S: char>(
+    data: &'a mut Data,
+    context: &mut (dyn StateMachineContext<Event> + 'a),
+    event: &Event,
+) -> ProcessingResult<Data, Event, char> {
+    match *event {
+        Event::Arm(state) if state == S => {
+            context.arm_timer(100, Event::Arm(state));
+            ProcessingResult::Handled
+        }
+        Event::Tran(source, target) if source == S => ProcessingResult::Transition(target),
+        Event::TranWith(source, target) if source == S => ProcessingResult::TransitionWith(target, act),
+        Event::Post(state, source, target) if state == S => {
+            context.post_internal(Event::Tran(source, target)).unwrap();
+            ProcessingResult::Handled
+        }
+        Event::Cut(stop) => {
+            data.trace.push('?');
+            data.trace.push(S);
+            if stop == S {
+                ProcessingResult::Top
+            } else {
+                ProcessingResult::Ignored
+            }
+        }
+        Event::Delegate(from, to) if from == S => ProcessingResult::SuperState(to),
+        Event::Defer(from, _) if from == S => ProcessingResult::Deferred,
+        Event::Delegate(_, to) | Event::Defer(_, to) if to == S => {
+            data.trace.push('!');
+            data.trace.push(S);
+            ProcessingResult::Handled
+        }
+        _ => ProcessingResult::Ignored,
+    }
+}
+
+/// The state list; `x` declares the given transitions
+const fn states(x_transitions: &'static [TransitionSpec<Data, Event, char>]) -> [State<Data, Event, char>; 6] {
+    [
+        State::<Data, Event, char> { state: 'T', super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init: init::<'A'>, entry: entry::<'T'>, exit: exit::<'T'>, dispatch: dispatch::<'T'> },
+        State::<Data, Event, char> { state: 'A', super_state: Some('T'), kind: StateKind::Regular, history: None, transitions: &[], init: init::<'x'>, entry: entry::<'A'>, exit: exit::<'A'>, dispatch: dispatch::<'A'> },
+        State::<Data, Event, char> { state: 'x', super_state: Some('A'), kind: StateKind::Regular, history: None, transitions: x_transitions, init: leaf, entry: entry::<'x'>, exit: exit::<'x'>, dispatch: dispatch::<'x'> },
+        State::<Data, Event, char> { state: 'y', super_state: Some('A'), kind: StateKind::Regular, history: None, transitions: &[], init: leaf, entry: entry::<'y'>, exit: exit::<'y'>, dispatch: dispatch::<'y'> },
+        State::<Data, Event, char> { state: 'B', super_state: Some('T'), kind: StateKind::Regular, history: None, transitions: &[], init: init::<'z'>, entry: entry::<'B'>, exit: exit::<'B'>, dispatch: dispatch::<'B'> },
+        State::<Data, Event, char> { state: 'z', super_state: Some('B'), kind: StateKind::Regular, history: None, transitions: &[], init: leaf, entry: entry::<'z'>, exit: exit::<'z'>, dispatch: dispatch::<'z'> },
+    ]
+}
+
+const STATES: [State<Data, Event, char>; 6] = states(&[]);
+
+/// Start the machine and clear the trace of the initial transition
+fn started() -> HierarchicalStateMachine<Data, Event, char> {
+    let mut sm = HierarchicalStateMachine::new(&STATES, Data::default());
+    sm.start(&mut Context);
+    sm.data.trace.clear();
+    sm
+}
+
+fn trace_of(sm: &mut HierarchicalStateMachine<Data, Event, char>, event: Event) -> String {
+    sm.dispatch(&mut Context, event);
+    core::mem::take(&mut sm.data.trace)
+}
+
+#[test]
+fn start_follows_initial_sub_states() {
+    let mut sm = HierarchicalStateMachine::new(&STATES, Data::default());
+    sm.start(&mut Context);
+    assert_eq!("+T+A+x", sm.data.trace);
+    assert_eq!(2, sm.index());
+}
+
+#[test]
+fn start_enters_super_states_of_nested_first_state() {
+    static NESTED_FIRST: [State<Data, Event, char>; 2] = [
+        State::<Data, Event, char> { state: 'x', super_state: Some('A'), kind: StateKind::Regular, history: None, transitions: &[], init: leaf, entry: entry::<'x'>, exit: exit::<'x'>, dispatch: dispatch::<'x'> },
+        State::<Data, Event, char> { state: 'A', super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init: leaf, entry: entry::<'A'>, exit: exit::<'A'>, dispatch: dispatch::<'A'> },
+    ];
+    let mut sm = HierarchicalStateMachine::new(&NESTED_FIRST, Data::default());
+    sm.start(&mut Context);
+    assert_eq!("+A+x", sm.data.trace);
+}
+
+#[test]
+fn transition_between_siblings() {
+    let mut sm = started();
+    assert_eq!("-x+y", trace_of(&mut sm, Event::Tran('x', 'y')));
+    assert_eq!(3, sm.index());
+}
+
+#[test]
+fn transition_across_composite_states() {
+    let mut sm = started();
+    assert_eq!("-x-A+B+z", trace_of(&mut sm, Event::Tran('x', 'z')));
+    assert_eq!(5, sm.index());
+}
+
+#[test]
+fn transition_handled_by_super_state_follows_init() {
+    let mut sm = started();
+    assert_eq!("-x-A+B+z", trace_of(&mut sm, Event::Tran('A', 'B')));
+    assert_eq!(5, sm.index());
+}
+
+#[test]
+fn self_transition_exits_and_reenters() {
+    let mut sm = started();
+    assert_eq!("-x-A+A+x", trace_of(&mut sm, Event::Tran('A', 'A')));
+}
+
+#[test]
+fn transition_to_super_state_does_not_exit_it() {
+    let mut sm = started();
+    trace_of(&mut sm, Event::Tran('x', 'y'));
+    assert_eq!("-y+x", trace_of(&mut sm, Event::Tran('y', 'A')));
+    assert_eq!(2, sm.index());
+}
+
+#[test]
+fn transition_from_super_state_to_sub_state() {
+    let mut sm = started();
+    trace_of(&mut sm, Event::Tran('x', 'y'));
+    assert_eq!("-y+x", trace_of(&mut sm, Event::Tran('A', 'x')));
+}
+
+#[test]
+fn ignored_event_bubbles_up_to_top() {
+    let mut sm = started();
+    assert_eq!("?x?A?T", trace_of(&mut sm, Event::Cut('-')));
+}
+
+#[test]
+fn top_stops_bubbling() {
+    let mut sm = started();
+    assert_eq!("?x?A", trace_of(&mut sm, Event::Cut('A')));
+}
+
+#[test]
+fn super_state_result_delegates_to_given_state() {
+    let mut sm = started();
+    assert_eq!("!T", trace_of(&mut sm, Event::Delegate('x', 'T')));
+    assert_eq!(2, sm.index());
+}
+
+#[test]
+fn unhandled_event_changes_nothing() {
+    let mut sm = started();
+    assert_eq!("", trace_of(&mut sm, Event::Tran('z', 'x')));
+    assert_eq!(2, sm.index());
+}
+
+#[test]
+fn internal_event_is_processed_before_dispatch_returns() {
+    let mut sm = started();
+    sm.dispatch(&mut Context, Event::Post('A', 'x', 'B'));
+    assert_eq!("-x-A+B+z", sm.data.trace);
+    assert_eq!('z', *sm.current_state());
+}
+
+#[test]
+fn deferred_event_is_recalled_by_entry_action() {
+    let mut sm = started();
+    sm.dispatch(&mut Context, Event::Defer('x', 'B'));
+    assert_eq!("", sm.data.trace);
+    // each entry action recalls one event, the innermost state receives it
+    sm.dispatch(&mut Context, Event::Tran('x', 'B'));
+    assert_eq!("-x-A+B+z!B", sm.data.trace);
+}
+
+fn never(_data: &Data, _event: &Event) -> bool {
+    false
+}
+
+fn act<'a>(data: &'a mut Data, _context: &mut (dyn StateMachineContext<Event> + 'a), _event: &Event) {
+    data.trace.push('*');
+}
+
+fn go<const TARGET: char>(event: &Event) -> bool {
+    matches!(*event, Event::Go(target) if target == TARGET)
+}
+
+static X_TRANSITIONS: [TransitionSpec<Data, Event, char>; 2] = [
+    TransitionSpec {
+        trigger: Some(go::<'y'>),
+        guard: Some(Guard { name: "never", check: Check::Event(never) }),
+        ..TransitionSpec::new("Go", 'y')
+    },
+    TransitionSpec {
+        trigger: Some(go::<'B'>),
+        action: Some(Action { name: "act", run: Effect::Event(act) }),
+        ..TransitionSpec::new("Go", 'B')
+    },
+];
+
+const DECLARED_STATES: [State<Data, Event, char>; 6] = states(&X_TRANSITIONS);
+
+#[test]
+fn transition_action_runs_after_exit_up_to_common_ancestor() {
+    let mut sm = HierarchicalStateMachine::new(&DECLARED_STATES, Data::default());
+    sm.start(&mut Context);
+    sm.data.trace.clear();
+    // the guard fails, the event bubbles up and is ignored
+    sm.dispatch(&mut Context, Event::Go('y'));
+    assert_eq!("", sm.data.trace);
+    sm.dispatch(&mut Context, Event::Go('B'));
+    assert_eq!("-x-A*+B+z", sm.data.trace);
+}
+
+#[test]
+fn transition_returned_with_action_runs_it_after_exit_up_to_common_ancestor() {
+    let mut sm = started();
+    sm.dispatch(&mut Context, Event::TranWith('x', 'B'));
+    assert_eq!("-x-A*+B+z", sm.data.trace);
+    sm.data.trace.clear();
+    sm.dispatch(&mut Context, Event::TranWith('z', 'z'));
+    assert_eq!("-z*+z", sm.data.trace);
+}
+
+fn blocked(_data: &Data) -> bool {
+    false
+}
+
+fn mark<'a>(data: &'a mut Data, _context: &mut (dyn StateMachineContext<Event> + 'a)) {
+    data.trace.push('*');
+}
+
+static CHOICE_BRANCHES: [TransitionSpec<Data, Event, char>; 2] = [
+    TransitionSpec { guard: Some(Guard { name: "blocked", check: Check::Completion(blocked) }), ..TransitionSpec::new("", 'y') },
+    TransitionSpec { action: Some(Action { name: "mark", run: Effect::Completion(mark) }), ..TransitionSpec::new("", 'z') },
+];
+
+static Z_COMPLETION: [TransitionSpec<Data, Event, char>; 1] = [TransitionSpec::new("", 'y')];
+
+// STATES with a choice c in A branching to z, which completes to y
+const CHOICE_STATES: [State<Data, Event, char>; 7] = [
+    State::<Data, Event, char> { state: 'T', super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init: init::<'A'>, entry: entry::<'T'>, exit: exit::<'T'>, dispatch: dispatch::<'T'> },
+    State::<Data, Event, char> { state: 'A', super_state: Some('T'), kind: StateKind::Regular, history: None, transitions: &[], init: init::<'x'>, entry: entry::<'A'>, exit: exit::<'A'>, dispatch: dispatch::<'A'> },
+    State::<Data, Event, char> { state: 'x', super_state: Some('A'), kind: StateKind::Regular, history: None, transitions: &[], init: leaf, entry: entry::<'x'>, exit: exit::<'x'>, dispatch: dispatch::<'x'> },
+    State::<Data, Event, char> { state: 'y', super_state: Some('A'), kind: StateKind::Regular, history: None, transitions: &[], init: leaf, entry: entry::<'y'>, exit: exit::<'y'>, dispatch: dispatch::<'y'> },
+    State::<Data, Event, char> { state: 'c', super_state: Some('A'), kind: StateKind::Choice, transitions: &CHOICE_BRANCHES, ..State::new('c') },
+    State::<Data, Event, char> { state: 'B', super_state: Some('T'), kind: StateKind::Regular, history: None, transitions: &[], init: init::<'z'>, entry: entry::<'B'>, exit: exit::<'B'>, dispatch: dispatch::<'B'> },
+    State::<Data, Event, char> { state: 'z', super_state: Some('B'), kind: StateKind::Regular, history: None, transitions: &Z_COMPLETION, init: leaf, entry: entry::<'z'>, exit: exit::<'z'>, dispatch: dispatch::<'z'> },
+];
+
+#[test]
+fn choice_leaves_composite_state_and_completion_returns() {
+    let mut sm = HierarchicalStateMachine::try_new(&CHOICE_STATES, Data::default()).unwrap();
+    sm.start(&mut Context);
+    sm.data.trace.clear();
+    sm.dispatch(&mut Context, Event::Tran('x', 'c'));
+    assert_eq!("-x-A*+B+z-z-B+A+y", sm.data.trace);
+    assert_eq!('y', *sm.current_state());
+}
+
+/// State list with history in A
+///
+/// T (init -> A)
+/// +-- A (init -> C)
+/// |   +-- C (init -> p)
+/// |   |   +-- p
+/// |   |   +-- q
+/// |   +-- r
+/// +-- P
+const fn history_states(history: Option<History>) -> [State<Data, Event, char>; 7] {
+    [
+        State::<Data, Event, char> { state: 'T', super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init: init::<'A'>, entry: entry::<'T'>, exit: exit::<'T'>, dispatch: dispatch::<'T'> },
+        State::<Data, Event, char> { state: 'A', super_state: Some('T'), kind: StateKind::Regular, history, transitions: &[], init: init::<'C'>, entry: entry::<'A'>, exit: exit::<'A'>, dispatch: dispatch::<'A'> },
+        State::<Data, Event, char> { state: 'C', super_state: Some('A'), kind: StateKind::Regular, history: None, transitions: &[], init: init::<'p'>, entry: entry::<'C'>, exit: exit::<'C'>, dispatch: dispatch::<'C'> },
+        State::<Data, Event, char> { state: 'p', super_state: Some('C'), kind: StateKind::Regular, history: None, transitions: &[], init: leaf, entry: entry::<'p'>, exit: exit::<'p'>, dispatch: dispatch::<'p'> },
+        State::<Data, Event, char> { state: 'q', super_state: Some('C'), kind: StateKind::Regular, history: None, transitions: &[], init: leaf, entry: entry::<'q'>, exit: exit::<'q'>, dispatch: dispatch::<'q'> },
+        State::<Data, Event, char> { state: 'r', super_state: Some('A'), kind: StateKind::Regular, history: None, transitions: &[], init: leaf, entry: entry::<'r'>, exit: exit::<'r'>, dispatch: dispatch::<'r'> },
+        State::<Data, Event, char> { state: 'P', super_state: Some('T'), kind: StateKind::Regular, history: None, transitions: &[], init: leaf, entry: entry::<'P'>, exit: exit::<'P'>, dispatch: dispatch::<'P'> },
+    ]
+}
+
+const NO_HISTORY_STATES: [State<Data, Event, char>; 7] = history_states(None);
+const SHALLOW_HISTORY_STATES: [State<Data, Event, char>; 7] = history_states(Some(History::Shallow));
+const DEEP_HISTORY_STATES: [State<Data, Event, char>; 7] = history_states(Some(History::Deep));
+
+/// Go from p to `last`, leave A and return to A
+fn return_trace(state_list: &'static [State<Data, Event, char>], last: char) -> String {
+    let mut sm = HierarchicalStateMachine::try_new(state_list, Data::default()).unwrap();
+    sm.start(&mut Context);
+    sm.dispatch(&mut Context, Event::Tran('p', last));
+    sm.dispatch(&mut Context, Event::Tran('A', 'P'));
+    sm.data.trace.clear();
+    trace_of(&mut sm, Event::Tran('P', 'A'))
+}
+
+#[test]
+fn without_history_init_is_followed_on_every_entry() {
+    assert_eq!("-P+A+C+p", return_trace(&NO_HISTORY_STATES, 'q'));
+    assert_eq!("-P+A+C+p", return_trace(&NO_HISTORY_STATES, 'r'));
+}
+
+#[test]
+fn shallow_history_resumes_direct_sub_state() {
+    assert_eq!("-P+A+r", return_trace(&SHALLOW_HISTORY_STATES, 'r'));
+    // C follows its own init
+    assert_eq!("-P+A+C+p", return_trace(&SHALLOW_HISTORY_STATES, 'q'));
+}
+
+#[test]
+fn deep_history_resumes_innermost_state() {
+    assert_eq!("-P+A+C+q", return_trace(&DEEP_HISTORY_STATES, 'q'));
+    assert_eq!("-P+A+r", return_trace(&DEEP_HISTORY_STATES, 'r'));
+}
+
+#[test]
+fn history_applies_to_self_transition() {
+    let mut sm = HierarchicalStateMachine::try_new(&DEEP_HISTORY_STATES, Data::default()).unwrap();
+    sm.start(&mut Context);
+    sm.dispatch(&mut Context, Event::Tran('p', 'q'));
+    sm.data.trace.clear();
+    assert_eq!("-q-C-A+A+C+q", trace_of(&mut sm, Event::Tran('A', 'A')));
+}
+
+/// State list with the orthogonal state D
+///
+/// T (init -> D)
+/// +-- D (orthogonal)
+/// |   +-- N (init -> a)
+/// |   |   +-- a
+/// |   |   +-- b
+/// |   +-- W (init -> c)
+/// |       +-- c
+/// |       +-- d
+/// +-- O
+const REGION_STATES: [State<Data, Event, char>; 9] = [
+    State::<Data, Event, char> { state: 'T', super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init: init::<'D'>, entry: entry::<'T'>, exit: exit::<'T'>, dispatch: dispatch::<'T'> },
+    State::<Data, Event, char> { state: 'D', super_state: Some('T'), kind: StateKind::Orthogonal, history: None, transitions: &[], init: leaf, entry: entry::<'D'>, exit: exit::<'D'>, dispatch: dispatch::<'D'> },
+    State::<Data, Event, char> { state: 'N', super_state: Some('D'), kind: StateKind::Regular, history: None, transitions: &[], init: init::<'a'>, entry: entry::<'N'>, exit: exit::<'N'>, dispatch: dispatch::<'N'> },
+    State::<Data, Event, char> { state: 'a', super_state: Some('N'), kind: StateKind::Regular, history: None, transitions: &[], init: leaf, entry: entry::<'a'>, exit: exit::<'a'>, dispatch: dispatch::<'a'> },
+    State::<Data, Event, char> { state: 'b', super_state: Some('N'), kind: StateKind::Regular, history: None, transitions: &[], init: leaf, entry: entry::<'b'>, exit: exit::<'b'>, dispatch: dispatch::<'b'> },
+    State::<Data, Event, char> { state: 'W', super_state: Some('D'), kind: StateKind::Regular, history: None, transitions: &[], init: init::<'c'>, entry: entry::<'W'>, exit: exit::<'W'>, dispatch: dispatch::<'W'> },
+    State::<Data, Event, char> { state: 'c', super_state: Some('W'), kind: StateKind::Regular, history: None, transitions: &[], init: leaf, entry: entry::<'c'>, exit: exit::<'c'>, dispatch: dispatch::<'c'> },
+    State::<Data, Event, char> { state: 'd', super_state: Some('W'), kind: StateKind::Regular, history: None, transitions: &[], init: leaf, entry: entry::<'d'>, exit: exit::<'d'>, dispatch: dispatch::<'d'> },
+    State::<Data, Event, char> { state: 'O', super_state: Some('T'), kind: StateKind::Regular, history: None, transitions: &[], init: leaf, entry: entry::<'O'>, exit: exit::<'O'>, dispatch: dispatch::<'O'> },
+];
+
+fn started_in_regions() -> HierarchicalStateMachine<Data, Event, char> {
+    let mut sm = HierarchicalStateMachine::try_new(&REGION_STATES, Data::default()).unwrap();
+    sm.start(&mut Context);
+    sm.data.trace.clear();
+    sm
+}
+
+fn active_of(sm: &HierarchicalStateMachine<Data, Event, char>) -> String {
+    let mut active = String::new();
+    sm.active_states(&mut |s| active.push(*s));
+    active
+}
+
+#[test]
+fn start_enters_all_regions_in_order() {
+    let mut sm = HierarchicalStateMachine::new(&REGION_STATES, Data::default());
+    sm.start(&mut Context);
+    assert_eq!("+T+D+N+a+W+c", sm.data.trace);
+    assert_eq!("aNDTcW", active_of(&sm));
+    assert_eq!('a', *sm.current_state());
+}
+
+#[test]
+fn event_is_offered_to_every_region() {
+    let mut sm = started_in_regions();
+    // the orthogonal state gets the event after all regions ignored it
+    assert_eq!("?a?N?c?W?D?T", trace_of(&mut sm, Event::Cut('-')));
+    assert_eq!("?a?N?c?W", trace_of(&mut sm, Event::Cut('N')));
+    assert_eq!("?a?N?c?W", trace_of(&mut sm, Event::Cut('W')));
+}
+
+#[test]
+fn transition_inside_region_keeps_other_regions() {
+    let mut sm = started_in_regions();
+    assert_eq!("-c+d", trace_of(&mut sm, Event::Tran('c', 'd')));
+    assert_eq!("-a-N+N+a", trace_of(&mut sm, Event::Tran('N', 'N')));
+    assert_eq!("aNDTdW", active_of(&sm));
+}
+
+fn done(data: &Data) -> bool {
+    data.done
+}
+
+static A_COMPLETION: [TransitionSpec<Data, Event, char>; 1] =
+    [TransitionSpec { guard: Some(Guard { name: "done", check: Check::Completion(done) }), ..TransitionSpec::new("", 'b') }];
+
+// REGION_STATES with a completion transition from a to b
+const COMPLETING_REGION_STATES: [State<Data, Event, char>; 9] = {
+    let mut states = REGION_STATES;
+    states[3].transitions = &A_COMPLETION;
+    states
+};
+
+#[test]
+fn completion_transitions_of_entered_states_only() {
+    let mut sm = HierarchicalStateMachine::try_new(&COMPLETING_REGION_STATES, Data::default()).unwrap();
+    sm.start(&mut Context);
+    sm.data.trace.clear();
+    sm.data.done = true;
+    // a was entered before, a transition in the other region does not complete it
+    assert_eq!("-c+d", trace_of(&mut sm, Event::Tran('c', 'd')));
+    assert_eq!("aNDTdW", active_of(&sm));
+    assert_eq!("-a-N+N+a-a+b", trace_of(&mut sm, Event::Tran('N', 'N')));
+    assert_eq!("bNDTdW", active_of(&sm));
+}
+
+#[test]
+fn transition_out_of_region_exits_all_regions() {
+    let mut sm = started_in_regions();
+    assert_eq!("-c-W-a-N-D+O", trace_of(&mut sm, Event::Tran('a', 'O')));
+    assert_eq!("OT", active_of(&sm));
+    assert_eq!("-O+D+N+a+W+d", trace_of(&mut sm, Event::Tran('O', 'd')));
+    assert_eq!("aNDTdW", active_of(&sm));
+}
+
+#[test]
+fn transition_to_sibling_region_reenters_orthogonal_state() {
+    let mut sm = started_in_regions();
+    assert_eq!("-c-W-a-N-D+D+N+a+W+d", trace_of(&mut sm, Event::Tran('a', 'd')));
+    assert_eq!("-d-W-a-N+N+a+W+c", trace_of(&mut sm, Event::Tran('d', 'D')));
+}
+
+#[test]
+fn event_deferred_by_one_region_and_handled_by_another_is_consumed() {
+    let mut sm = started_in_regions();
+    assert_eq!("!c", trace_of(&mut sm, Event::Defer('a', 'c')));
+    // the entry action of b would recall a deferred event
+    assert_eq!("-a+b", trace_of(&mut sm, Event::Tran('a', 'b')));
+}
+
+#[test]
+fn stop_exits_all_regions() {
+    let mut sm = started_in_regions();
+    sm.stop(&mut Context);
+    assert_eq!("-c-W-a-N-D-T", sm.data.trace);
+}
+
+#[test]
+fn stop_exits_active_state_and_all_super_states() {
+    let mut sm = started();
+    sm.stop(&mut Context);
+    assert_eq!("-x-A-T", sm.data.trace);
+}
+
+#[test]
+fn active_states_lists_configuration_innermost_first() {
+    let mut sm = started();
+    let mut active = String::new();
+    sm.active_states(&mut |s| active.push(*s));
+    assert_eq!("xAT", active);
+    trace_of(&mut sm, Event::Tran('x', 'B'));
+    assert_eq!(&'z', sm.current_state());
+}
+
+#[test]
+fn is_in_honors_super_states() {
+    let sm = started();
+    for state in ['x', 'A', 'T'] {
+        assert!(sm.is_in(&state));
+    }
+    for state in ['y', 'B', 'z'] {
+        assert!(!sm.is_in(&state));
+    }
+}
+
+/// Observer recording the processing steps
+#[derive(Default)]
+struct Recorder {
+    steps: String,
+}
+
+impl StateMachineObserver<Event, char> for Recorder {
+    fn event_received(&mut self, state: &char, _event: &Event) {
+        self.steps.push_str(&format!("<{}", state));
+    }
+    fn event_handled(&mut self, state: &char, _event: &Event) {
+        self.steps.push_str(&format!("!{}", state));
+    }
+    fn event_ignored(&mut self, state: &char, _event: &Event) {
+        self.steps.push_str(&format!("?{}", state));
+    }
+    fn transition(&mut self, from: &char, to: &char) {
+        self.steps.push_str(&format!("{}>{}", from, to));
+    }
+    fn entered(&mut self, state: &char) {
+        self.steps.push_str(&format!("+{}", state));
+    }
+    fn exited(&mut self, state: &char) {
+        self.steps.push_str(&format!("-{}", state));
+    }
+}
+
+#[test]
+fn observer_sees_every_processing_step() {
+    let mut sm = HierarchicalStateMachine::new(&STATES, Data::default()).with_observer(Recorder::default());
+    sm.start(&mut Context);
+    assert_eq!("+T+A+x", sm.observer().steps);
+    sm.observer.steps.clear();
+    sm.dispatch(&mut Context, Event::Tran('A', 'B'));
+    assert_eq!("<xA>B-x-A+B+z", sm.observer().steps);
+    sm.observer.steps.clear();
+    sm.dispatch(&mut Context, Event::Cut('B'));
+    assert_eq!("<z?B", sm.observer().steps);
+    sm.observer.steps.clear();
+    sm.dispatch(&mut Context, Event::Delegate('z', 'T'));
+    assert_eq!("<z!T", sm.observer().steps);
+    sm.observer.steps.clear();
+    sm.dispatch(&mut Context, Event::Tran('q', 'q'));
+    assert_eq!("<z?T", sm.observer().steps);
+}
+
+#[test]
+fn try_new_accepts_valid_state_list() {
+    assert!(HierarchicalStateMachine::try_new(&STATES, Data::default()).is_ok());
+}
+
+#[test]
+fn timers_are_cancelled_when_owning_state_is_exited() {
+    let mut context = TimerContext::default();
+    let mut sm = HierarchicalStateMachine::new(&STATES, Data::default()).cancel_timers_on_exit();
+    sm.start(&mut context);
+    sm.dispatch(&mut context, Event::Arm('A'));
+    sm.dispatch(&mut context, Event::Arm('x'));
+    sm.dispatch(&mut context, Event::Tran('x', 'y'));
+    assert_eq!(vec![TimerHandle(2)], context.cancelled);
+    sm.dispatch(&mut context, Event::Tran('y', 'z'));
+    assert_eq!(vec![TimerHandle(2), TimerHandle(1)], context.cancelled);
+}
+
+#[test]
+fn timers_are_kept_by_default() {
+    let mut context = TimerContext::default();
+    let mut sm = HierarchicalStateMachine::new(&STATES, Data::default());
+    sm.start(&mut context);
+    sm.dispatch(&mut context, Event::Arm('x'));
+    sm.dispatch(&mut context, Event::Tran('x', 'z'));
+    assert!(context.cancelled.is_empty());
+}
@@ -1,14 +1,33 @@
 //! # State machine quantum leaps like
 //!
 #![no_std]
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 use core::cmp::PartialEq;
 
-pub enum ProcessingResult<S> {
+/// Outcome of a state handler (dispatch) function
+///
+/// Hierarchical state machines use `Ignored`, `SuperState` and `Top`
+/// to control how an event bubbles up the `super_state` chain.
+pub enum ProcessingResult<D, E, S> {
+    /// The event was consumed, no state change
     Handled,
+    /// The event was not consumed; a hierarchical state machine offers it
+    /// to the super state next
     Ignored,
+    /// The event was consumed and causes a transition to the given state;
+    /// declared guards and actions do not apply, see `TransitionSpec` for
+    /// transitions triggered by the event itself
     Transition(S),
-    Top,           // only needed for hierarchical state machines
-    SuperState(S), // only needed for hierarchical state machines
+    /// Like `Transition`, the action is executed with the event after the
+    /// exit and before the entry actions (UML order)
+    TransitionWith(S, ActionFn<D, E>),
+    Top,           // only needed for hierarchical state machines: stop bubbling, event is dropped
+    SuperState(S), // only needed for hierarchical state machines: offer the event to the given state
+    /// The event cannot be handled yet; the processor keeps it until it is
+    /// recalled via `StateMachineContext::recall`
+    Deferred,
 }
 
 pub type EntryFn<D, E> =
@@ -17,13 +36,18 @@ pub type EntryFn<D, E> =
 pub type ExitFn<D, E> =
     for<'a> fn(data: &'a mut D, context: &mut (dyn StateMachineContext<E> + 'a)) -> ();
 
-pub type InitFn<D, E, S> = fn() -> Option<State<D, E, S>>;
+/// Name the initial sub state of a composite state (if any)
+pub type InitFn<S> = fn() -> Option<S>;
 
+/// State handler function
+///
+/// The event is passed by reference so a hierarchical state machine can offer
+/// the very same event to the super states if it is not handled.
 pub type DispatchFn<D, E, S> = for<'a> fn(
     data: &'a mut D,
     context: &mut (dyn StateMachineContext<E> + 'a),
-    event: E,
-) -> ProcessingResult<S>;
+    event: &E,
+) -> ProcessingResult<D, E, S>;
 
 /// States of a state machine are arranged as an (const) array of states
 ///
@@ -35,20 +59,292 @@ pub type DispatchFn<D, E, S> = for<'a> fn(
 ///
 /// Note:
 ///     each state must be described in exactly one element of state machine
-///     describing array
+///     describing array; use `validate` to check a state list
 pub struct State<D, E, S>
 where
+    D: 'static,
     E: 'static,
-    S: PartialEq,
+    S: PartialEq + 'static,
 {
     pub state: S,
     pub super_state: Option<S>,
+    pub kind: StateKind,
+    /// History of a composite state; evaluated by hierarchical state machines
+    pub history: Option<History>,
+    /// Transitions triggered by events, completion transitions and the
+    /// transitions the dispatch function may return; see `TransitionSpec`
+    pub transitions: &'static [TransitionSpec<D, E, S>],
     pub entry: EntryFn<D, E>,
     pub exit: ExitFn<D, E>,
-    pub init: InitFn<D, E, S>,
+    pub init: InitFn<S>,
     pub dispatch: DispatchFn<D, E, S>,
 }
 
+/// Check whether an event triggers a declared transition, e.g. by its `Signal`
+pub type TriggerFn<E> = fn(event: &E) -> bool;
+
+/// Condition a declared transition triggered by an event is taken on
+pub type GuardFn<D, E> = fn(data: &D, event: &E) -> bool;
+
+/// Condition a completion transition or a branch of a choice is taken on;
+/// there is no triggering event to look at
+pub type CompletionGuardFn<D> = fn(data: &D) -> bool;
+
+/// Action of a declared transition, executed after the exit actions of the
+/// source and before the entry actions of the target
+pub type ActionFn<D, E> =
+    for<'a> fn(data: &'a mut D, context: &mut (dyn StateMachineContext<E> + 'a), event: &E);
+
+/// Action of a completion transition or a branch of a choice
+pub type CompletionActionFn<D, E> = for<'a> fn(data: &'a mut D, context: &mut (dyn StateMachineContext<E> + 'a));
+
+/// Guard function of a transition triggered by an event or of a completion
+/// transition
+pub enum Check<D, E> {
+    Event(GuardFn<D, E>),
+    Completion(CompletionGuardFn<D>),
+}
+
+/// Action function of a transition triggered by an event or of a completion
+/// transition
+pub enum Effect<D, E> {
+    Event(ActionFn<D, E>),
+    Completion(CompletionActionFn<D, E>),
+}
+
+/// Guard function along with its name for validation and diagrams
+pub struct Guard<D, E> {
+    pub name: &'static str,
+    pub check: Check<D, E>,
+}
+
+impl<D, E> Guard<D, E> {
+    /// Evaluate the guard; a guard of an event transition does not hold
+    /// without an event
+    fn holds(&self, data: &D, event: Option<&E>) -> bool {
+        match (&self.check, event) {
+            (Check::Event(check), Some(event)) => check(data, event),
+            (Check::Event(_), None) => false,
+            (Check::Completion(check), _) => check(data),
+        }
+    }
+}
+
+/// Transition action along with its name for validation and diagrams
+pub struct Action<D, E> {
+    pub name: &'static str,
+    pub run: Effect<D, E>,
+}
+
+impl<D, E> Action<D, E> {
+    /// Action a dispatch function returns along with its transition
+    pub(crate) const fn returned(run: ActionFn<D, E>) -> Self {
+        Action { name: "", run: Effect::Event(run) }
+    }
+
+    /// Execute the action; an action of an event transition is skipped
+    /// without an event
+    pub(crate) fn execute<'a>(&self, data: &'a mut D, context: &mut (dyn StateMachineContext<E> + 'a), event: Option<&E>) {
+        match (&self.run, event) {
+            (Effect::Event(run), Some(event)) => run(data, context, event),
+            (Effect::Event(_), None) => (),
+            (Effect::Completion(run), _) => run(data, context),
+        }
+    }
+
+    fn needs_event(&self) -> bool {
+        matches!(self.run, Effect::Event(_))
+    }
+}
+
+/// Kind of an element of a state list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateKind {
+    /// A state the state machine can be in
+    Regular,
+    /// Choice pseudo state: a transition targeting it continues right away
+    /// along the first of its completion transitions whose guard holds; the
+    /// last one is the unguarded else branch. It is never active and has no
+    /// entry and exit actions
+    Choice,
+    /// Composite state whose direct sub states are orthogonal regions that
+    /// are active at the same time; a finite state machine treats it like a
+    /// regular state
+    Orthogonal,
+}
+
+/// History of a composite state
+///
+/// A composite state with history resumes its last active sub state when it
+/// is entered again instead of following `init`; `init` applies to the first
+/// entry only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum History {
+    /// Resume the last active direct sub state, which follows its own `init`
+    Shallow,
+    /// Resume the last active innermost state
+    Deep,
+}
+
+/// Description of a transition of a state
+///
+/// A transition with a `trigger` is taken by the processor itself: before the
+/// dispatch function sees an event, the processor evaluates the guards of the
+/// transitions triggered by the event and takes the first one whose guard
+/// holds (or that has no guard), executing its action between the exit and
+/// the entry actions. Only if no triggered transition is enabled the event is
+/// passed to the dispatch function.
+///
+/// A transition without `trigger` describes a transition the dispatch
+/// function returns via `ProcessingResult::Transition`, e.g. for diagrams; it
+/// has no guard, its action names the one returned via
+/// `ProcessingResult::TransitionWith`.
+///
+/// A transition with an empty event name is a completion transition: the
+/// processor takes it as soon as the state is entered and its guard holds,
+/// and choice pseudo states branch along them.
+pub struct TransitionSpec<D, E, S> {
+    /// Name of the triggering event, empty for completion transitions
+    pub event: &'static str,
+    pub trigger: Option<TriggerFn<E>>,
+    pub target: S,
+    pub guard: Option<Guard<D, E>>,
+    pub action: Option<Action<D, E>>,
+}
+
+impl<D, E, S> TransitionSpec<D, E, S> {
+    /// Describe a transition without trigger, guard and action
+    pub const fn new(event: &'static str, target: S) -> Self {
+        TransitionSpec {
+            event,
+            trigger: None,
+            target,
+            guard: None,
+            action: None,
+        }
+    }
+
+    pub fn is_completion(&self) -> bool {
+        self.event.is_empty()
+    }
+
+    pub fn guard_name(&self) -> Option<&'static str> {
+        self.guard.as_ref().map(|guard| guard.name)
+    }
+
+    pub fn action_name(&self) -> Option<&'static str> {
+        self.action.as_ref().map(|action| action.name)
+    }
+
+    fn triggered_by(&self, event: &E) -> bool {
+        self.trigger.is_some_and(|trigger| trigger(event))
+    }
+
+    /// Evaluate the guard and report the outcome as guard of `state`
+    fn enabled(&self, state: &S, data: &D, event: Option<&E>, observer: &mut impl StateMachineObserver<E, S>) -> bool {
+        let Some(guard) = &self.guard else {
+            return true;
+        };
+        let holds = guard.holds(data, event);
+        observer.guard_evaluated(state, guard.name, holds);
+        holds
+    }
+}
+
+// guards and actions are compared and printed by name, triggers by their
+// presence; function pointers are not meaningful to compare
+impl<D, E, S: PartialEq> PartialEq for TransitionSpec<D, E, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.event == other.event
+            && self.trigger.is_some() == other.trigger.is_some()
+            && self.target == other.target
+            && self.guard_name() == other.guard_name()
+            && self.action_name() == other.action_name()
+    }
+}
+
+impl<D, E, S: core::fmt::Debug> core::fmt::Debug for TransitionSpec<D, E, S> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TransitionSpec")
+            .field("event", &self.event)
+            .field("triggered", &self.trigger.is_some())
+            .field("target", &self.target)
+            .field("guard", &self.guard_name())
+            .field("action", &self.action_name())
+            .finish()
+    }
+}
+
+/// Select the first transition of `state` triggered by `event` whose guard holds
+pub(crate) fn select_triggered<'t, D, E, S: PartialEq>(
+    state: &'t State<D, E, S>,
+    data: &D,
+    event: &E,
+    observer: &mut impl StateMachineObserver<E, S>,
+) -> Option<&'t TransitionSpec<D, E, S>> {
+    state
+        .transitions
+        .iter()
+        .filter(|t| !t.is_completion() && t.triggered_by(event))
+        .find(|t| t.enabled(&state.state, data, Some(event), observer))
+}
+
+/// Select the first completion transition of `state` whose guard holds
+pub(crate) fn select_completion<'t, D, E, S: PartialEq>(
+    state: &'t State<D, E, S>,
+    data: &D,
+    observer: &mut impl StateMachineObserver<E, S>,
+) -> Option<&'t TransitionSpec<D, E, S>> {
+    state
+        .transitions
+        .iter()
+        .filter(|t| t.is_completion())
+        .find(|t| t.enabled(&state.state, data, None, observer))
+}
+
+/// Maximum number of completion transitions and choice branches followed
+/// while processing a single event
+///
+/// A longer chain is considered a loop and panics. `validate` rejects loops
+/// of unguarded completion transitions; loops depending on guards or passing
+/// initial sub states are only detected at run time.
+pub const MAX_TRANSITION_CHAIN: usize = 16;
+
+fn no_init<S>() -> Option<S> {
+    None
+}
+
+fn no_action<'a, D, E>(_data: &'a mut D, _context: &mut (dyn StateMachineContext<E> + 'a)) {}
+
+fn ignore_all<'a, D, E, S>(
+    _data: &'a mut D,
+    _context: &mut (dyn StateMachineContext<E> + 'a),
+    _event: &E,
+) -> ProcessingResult<D, E, S> {
+    ProcessingResult::Ignored
+}
+
+impl<D, E, S: PartialEq> State<D, E, S> {
+    /// Describe a top level state without sub states, entry and exit actions
+    /// that ignores all events
+    ///
+    /// Use struct update syntax to override the defaults, e.g.
+    /// `State { dispatch: my_dispatch, ..State::new(MyState::Idle) }`
+    pub const fn new(state: S) -> Self {
+        State {
+            state,
+            super_state: None,
+            kind: StateKind::Regular,
+            history: None,
+            transitions: &[],
+            entry: no_action::<D, E>,
+            exit: no_action::<D, E>,
+            init: no_init::<S>,
+            dispatch: ignore_all::<D, E, S>,
+        }
+    }
+}
+
 /// Trait providing an execution context to a state machine
 ///
 /// The context acts as an execution environment. where the state machine
@@ -60,8 +356,176 @@ pub trait StateMachineContext<E> {
     /// Pulbish an event
     fn publish_event(&mut self, e: E);
 
+    /// Publish an event and report if the context could not accept it
+    ///
+    /// Contexts with bounded event queues should override this; the default
+    /// relies on `publish_event` and always succeeds.
+    fn try_publish_event(&mut self, e: E) -> Result<(), PublishError> {
+        self.publish_event(e);
+        Ok(())
+    }
+
+    /// Send an event to a single state machine instead of publishing it to all
+    fn send_to(&mut self, target: MachineId, e: E);
+
+    /// Post an event to the state machine itself
+    ///
+    /// The state machine processors queue internal events and dispatch them
+    /// right after the current event (or `start`), before the next external
+    /// event is taken from the runtime (run to completion). The default
+    /// publishes the event, for contexts used without a processor.
+    ///
+    /// Returns `PublishError::QueueFull` and drops the event if the queue of
+    /// internal events (`MAX_INTERNAL_EVENTS`) is full.
+    fn post_internal(&mut self, e: E) -> Result<(), PublishError> {
+        self.try_publish_event(e)
+    }
+
+    /// Recall the oldest event deferred by the state machine
+    ///
+    /// The recalled event is queued like an internal event, so calling this
+    /// from an entry action offers the event to the newly entered state.
+    /// Returns `false` if no event is deferred or the queue of internal
+    /// events is full; the default has no defer queue and always returns
+    /// `false`.
+    fn recall(&mut self) -> bool {
+        false
+    }
+
     // Publish an event after a certain delay in microseconds
-    fn publish_delayed_event(&mut self, delay_in_ms: u64, e: E);
+    fn publish_delayed_event(&mut self, delay_in_ms: u64, e: E) {
+        self.arm_timer(delay_in_ms, e);
+    }
+
+    /// Arm a one-shot timer publishing the event after a delay in milliseconds
+    ///
+    /// Runtimes that can address state machines deliver the event to the
+    /// state machine that armed the timer only.
+    fn arm_timer(&mut self, delay_in_ms: u64, e: E) -> TimerHandle;
+
+    /// Arm a timer publishing a copy of the event every period in milliseconds
+    fn arm_periodic_timer(&mut self, period_in_ms: u64, e: E) -> TimerHandle
+    where
+        E: Clone;
+
+    /// Cancel an armed timer
+    ///
+    /// Returns `false` if the timer is unknown, already expired or cancelled.
+    fn cancel_timer(&mut self, handle: TimerHandle) -> bool;
+
+    /// Subscribe the state machine to the published events of a kind
+    ///
+    /// Runtimes with a subscriber registry deliver published events of that
+    /// kind from now on; the default ignores subscriptions.
+    fn subscribe(&mut self, _kind: E::Kind)
+    where
+        E: Signal,
+    {
+    }
+
+    /// Unsubscribe the state machine from the published events of a kind
+    ///
+    /// Events of that kind sent to the state machine are still received.
+    fn unsubscribe(&mut self, _kind: E::Kind)
+    where
+        E: Signal,
+    {
+    }
+}
+
+/// Kind of an event, e.g. the variant of an event enum without its data
+///
+/// State machines subscribe to kinds of events rather than to events.
+///
+/// ```
+/// # use qlrl::Signal;
+/// #[derive(Clone, Debug)]
+/// enum Event {
+///     Press(u8),
+///     Release(u8),
+/// }
+///
+/// #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// enum EventKind {
+///     Press,
+///     Release,
+/// }
+///
+/// impl Signal for Event {
+///     type Kind = EventKind;
+///
+///     fn signal(&self) -> EventKind {
+///         match self {
+///             Event::Press(_) => EventKind::Press,
+///             Event::Release(_) => EventKind::Release,
+///         }
+///     }
+/// }
+/// ```
+pub trait Signal {
+    type Kind: Copy + Eq + core::fmt::Debug + Send + Sync + 'static;
+
+    /// Kind of this event
+    fn signal(&self) -> Self::Kind;
+}
+
+/// Integer events are their own kind
+macro_rules! integer_signal {
+    ($($int:ty),*) => {
+        $(impl Signal for $int {
+            type Kind = $int;
+
+            fn signal(&self) -> $int {
+                *self
+            }
+        })*
+    };
+}
+
+integer_signal!(u8, u16, u32, u64, usize);
+
+/// Shared events are of the kind of their payload
+///
+/// Publishing `Arc<T>` events hands every subscriber a reference to the same
+/// payload instead of a copy of it.
+#[cfg(feature = "alloc")]
+impl<T: Signal + ?Sized> Signal for alloc::sync::Arc<T> {
+    type Kind = T::Kind;
+
+    fn signal(&self) -> T::Kind {
+        (**self).signal()
+    }
+}
+
+/// Identification of a state machine within a runtime context
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MachineId(pub usize);
+
+/// Identification of an armed timer, used to cancel it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerHandle(pub u64);
+
+/// Reason why a context could not accept an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishError {
+    /// The event queue is full
+    QueueFull,
+    /// The runtime is stopped and does not accept events anymore
+    Stopped,
+    /// The runtime cannot publish the event, e.g. as its events cannot be
+    /// copied for several subscribers; sending it to a single state machine
+    /// still works
+    Unsupported,
+}
+
+impl core::fmt::Display for PublishError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PublishError::QueueFull => write!(f, "event queue is full"),
+            PublishError::Stopped => write!(f, "runtime is stopped"),
+            PublishError::Unsupported => write!(f, "runtime does not publish events, send them instead"),
+        }
+    }
 }
 
 /// Minimal functionality a state machine must support
@@ -74,11 +538,124 @@ pub trait StateMachine<E: Send> {
 
     /// Let the state machine process an event
     fn dispatch<'a>(&mut self, context: &mut (dyn StateMachineContext<E> + 'a), event: E);
+
+    /// Let the state machine leave it's active state before the runtime
+    /// context terminates
+    fn stop<'a>(&mut self, _context: &mut (dyn StateMachineContext<E> + 'a)) {}
+}
+
+/// Read access to the active states of a state machine, e.g. for tests
+/// and monitoring
+pub trait Introspect<S: PartialEq> {
+    /// The active state; the innermost active state of a hierarchical
+    /// state machine
+    fn current_state(&self) -> &S;
+
+    /// Visit the active configuration i.e. the active state and its super
+    /// states, innermost first
+    fn active_states(&self, visit: &mut dyn FnMut(&S));
+
+    /// Check whether the given state is active; a super state is active if
+    /// one of its sub states is
+    fn is_in(&self, state: &S) -> bool {
+        let mut found = false;
+        self.active_states(&mut |s| found |= s == state);
+        found
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Error;
 
+/// Inconsistency found in a state list
+///
+/// States are referenced by their index in the state list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The state list does not contain any state
+    EmptyStateList,
+    /// The state at `index` is already described at index `first`
+    DuplicateState { index: usize, first: usize },
+    /// The super state of the state at `index` is not in the state list
+    UnknownSuperState { index: usize },
+    /// The super state chain starting at `index` loops
+    SuperStateCycle { index: usize },
+    /// The `target`-th transition target of the state at `index` is not in the state list
+    UnknownTransitionTarget { index: usize, target: usize },
+    /// The guard or action of the `transition`-th transition of the state at
+    /// `index` is never used as the transition has no trigger and a guard or
+    /// an earlier transition on the same event has no guard
+    UnreachableTransition { index: usize, transition: usize },
+    /// The initial state named by `init` of the state at `index` is not one of its sub states
+    InvalidInitialState { index: usize },
+    /// The state at `index` is nested deeper than the processor supports
+    NestingTooDeep { index: usize },
+    /// The choice pseudo state at `index` is the initial state, a super state,
+    /// a region, names an initial sub state or its last completion transition
+    /// is missing or guarded i.e. it has no else branch
+    InvalidChoice { index: usize },
+    /// The `transition`-th transition of the state at `index` is a completion
+    /// transition with a trigger or whose guard or action expects an event
+    InvalidCompletion { index: usize, transition: usize },
+    /// The unguarded completion transitions starting at the state at `index`
+    /// lead back to it, i.e. they loop forever
+    CompletionCycle { index: usize },
+    /// The state at `index` has a history but no sub states or is orthogonal
+    InvalidHistory { index: usize },
+    /// The orthogonal state at `index` has no regions or names an initial sub state
+    InvalidOrthogonal { index: usize },
+    /// More composite states have a history than the processor supports
+    TooManyHistoryStates,
+    /// More regions can be active at the same time than the processor supports
+    TooManyRegions,
+}
+
+impl core::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ValidationError::EmptyStateList => write!(f, "state list is empty"),
+            ValidationError::DuplicateState { index, first } => {
+                write!(f, "state #{} duplicates state #{}", index, first)
+            }
+            ValidationError::UnknownSuperState { index } => {
+                write!(f, "super state of state #{} is unknown", index)
+            }
+            ValidationError::SuperStateCycle { index } => {
+                write!(f, "super state chain of state #{} is cyclic", index)
+            }
+            ValidationError::UnknownTransitionTarget { index, target } => {
+                write!(f, "transition target #{} of state #{} is unknown", target, index)
+            }
+            ValidationError::UnreachableTransition { index, transition } => {
+                write!(f, "transition #{} of state #{} is unreachable", transition, index)
+            }
+            ValidationError::InvalidInitialState { index } => {
+                write!(f, "initial state of state #{} is not a sub state", index)
+            }
+            ValidationError::NestingTooDeep { index } => {
+                write!(f, "state #{} is nested too deep", index)
+            }
+            ValidationError::InvalidChoice { index } => {
+                write!(f, "choice #{} is initial, composite, a region or has no else branch", index)
+            }
+            ValidationError::InvalidCompletion { index, transition } => {
+                write!(f, "completion transition #{} of state #{} expects an event", transition, index)
+            }
+            ValidationError::CompletionCycle { index } => {
+                write!(f, "unguarded completion transitions of state #{} loop", index)
+            }
+            ValidationError::InvalidHistory { index } => {
+                write!(f, "state #{} cannot have a history", index)
+            }
+            ValidationError::InvalidOrthogonal { index } => {
+                write!(f, "orthogonal state #{} has no regions or an initial state", index)
+            }
+            ValidationError::TooManyHistoryStates => write!(f, "too many states with history"),
+            ValidationError::TooManyRegions => write!(f, "too many active regions"),
+        }
+    }
+}
+
 pub fn find_state_index<D, E, S: PartialEq>(
     state_list: &[State<D, E, S>],
     state: S,
@@ -91,7 +668,148 @@ pub fn find_state_index<D, E, S: PartialEq>(
     Err(Error)
 }
 
+/// Find the index of a state without taking ownership of the state
+pub(crate) fn index_of<D, E, S: PartialEq>(state_list: &[State<D, E, S>], state: &S) -> Option<usize> {
+    state_list.iter().position(|value| value.state == *state)
+}
+
+/// Find the index of the super state of the state at `index` (if any)
+pub(crate) fn super_index<D, E, S: PartialEq>(state_list: &[State<D, E, S>], index: usize) -> Option<usize> {
+    state_list[index].super_state.as_ref().and_then(|s| index_of(state_list, s))
+}
+
+/// Target of the first unguarded completion transition of the state at
+/// `index`, taken whenever the guards of the earlier ones fail
+fn unguarded_completion<D, E, S: PartialEq>(state_list: &[State<D, E, S>], index: usize) -> Option<usize> {
+    let transition = state_list[index].transitions.iter().find(|t| t.is_completion() && t.guard.is_none())?;
+    index_of(state_list, &transition.target)
+}
+
+/// Check a state list for inconsistencies
+///
+/// The compiler cannot check the state list, so this should be done once at
+/// startup (see `try_new` of the state machine processors) rather than
+/// panicking on the first faulty transition.
+pub fn validate<D, E, S: PartialEq>(state_list: &[State<D, E, S>]) -> Result<(), ValidationError> {
+    if state_list.is_empty() {
+        return Err(ValidationError::EmptyStateList);
+    }
+    for (index, value) in state_list.iter().enumerate() {
+        let first = index_of(state_list, &value.state).unwrap_or(index);
+        if first != index {
+            return Err(ValidationError::DuplicateState { index, first });
+        }
+        if let Some(super_state) = &value.super_state {
+            if index_of(state_list, super_state).is_none() {
+                return Err(ValidationError::UnknownSuperState { index });
+            }
+        }
+        for (target, transition) in value.transitions.iter().enumerate() {
+            if index_of(state_list, &transition.target).is_none() {
+                return Err(ValidationError::UnknownTransitionTarget { index, target });
+            }
+            // the processor takes transitions by trigger, the guard of a
+            // transition the dispatch function returns is never used, neither
+            // is a guard or action behind an unguarded transition on the same
+            // event; the dispatch function returns the action itself
+            let returned = !transition.is_completion() && transition.trigger.is_none();
+            let shadowed = value.transitions[..target].iter().any(|earlier| {
+                earlier.event == transition.event
+                    && earlier.trigger.is_some() == transition.trigger.is_some()
+                    && earlier.guard.is_none()
+            });
+            let unused = match returned {
+                true => transition.guard.is_some(),
+                false => shadowed && (transition.guard.is_some() || transition.action.is_some()),
+            };
+            if unused {
+                return Err(ValidationError::UnreachableTransition { index, transition: target });
+            }
+            let event_guard = transition.guard.as_ref().is_some_and(|guard| matches!(guard.check, Check::Event(_)));
+            let event_action = transition.action.as_ref().is_some_and(Action::needs_event);
+            if transition.is_completion() && (transition.trigger.is_some() || event_guard || event_action) {
+                return Err(ValidationError::InvalidCompletion { index, transition: target });
+            }
+        }
+    }
+    for index in 0..state_list.len() {
+        // a super state chain longer than the state list must contain a loop
+        let mut current = super_index(state_list, index);
+        let mut steps = 0;
+        while let Some(i) = current {
+            if steps == state_list.len() {
+                return Err(ValidationError::SuperStateCycle { index });
+            }
+            current = super_index(state_list, i);
+            steps += 1;
+        }
+    }
+    for (index, value) in state_list.iter().enumerate() {
+        if let Some(sub_state) = (value.init)() {
+            let mut current = index_of(state_list, &sub_state).and_then(|i| super_index(state_list, i));
+            while let Some(i) = current {
+                if i == index {
+                    break;
+                }
+                current = super_index(state_list, i);
+            }
+            if current != Some(index) {
+                return Err(ValidationError::InvalidInitialState { index });
+            }
+        }
+    }
+    for (index, value) in state_list.iter().enumerate() {
+        let composite = (0..state_list.len()).any(|i| super_index(state_list, i) == Some(index));
+        let orthogonal = value.kind == StateKind::Orthogonal;
+        if value.history.is_some() && (!composite || orthogonal) {
+            return Err(ValidationError::InvalidHistory { index });
+        }
+        if orthogonal && (!composite || (value.init)().is_some()) {
+            return Err(ValidationError::InvalidOrthogonal { index });
+        }
+        if value.kind != StateKind::Choice {
+            continue;
+        }
+        // regions are entered together with their orthogonal state
+        let region = super_index(state_list, index).is_some_and(|i| state_list[i].kind == StateKind::Orthogonal);
+        let initial = index == 0
+            || state_list
+                .iter()
+                .any(|other| (other.init)().is_some_and(|sub_state| sub_state == value.state));
+        // the last branch must be enabled whenever no other one is
+        let otherwise = value.transitions.iter().rev().find(|t| t.is_completion()).is_some_and(|t| t.guard.is_none());
+        if initial || composite || region || (value.init)().is_some() || !otherwise {
+            return Err(ValidationError::InvalidChoice { index });
+        }
+    }
+    for index in 0..state_list.len() {
+        // an unguarded completion chain longer than the state list must loop
+        let mut current = unguarded_completion(state_list, index);
+        let mut steps = 0;
+        while let Some(i) = current {
+            if i == index {
+                return Err(ValidationError::CompletionCycle { index });
+            }
+            if steps == state_list.len() {
+                break;
+            }
+            current = unguarded_completion(state_list, i);
+            steps += 1;
+        }
+    }
+    Ok(())
+}
+
+mod context;
+pub use context::{MAX_DEFERRED_EVENTS, MAX_INTERNAL_EVENTS, MAX_OWNED_TIMERS};
+mod macros;
+mod ring;
+pub mod observer;
+pub use observer::{NoObserver, StateMachineObserver};
 pub mod fsm;
+pub mod hsm;
+pub mod diagram;
+pub mod testing;
 
 #[cfg(test)]
 mod tests;
\ No newline at end of file
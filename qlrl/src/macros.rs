@@ -0,0 +1,261 @@
+//! Declarative definition of state lists
+//!
+
+/// Define the state enum and the state list of a state machine in one go
+///
+/// Each state is given by its name followed by the non default parts of its
+/// `State` description:
+///
+/// - `super_state: Name` - the enclosing state
+/// - `init: Name` - the initial sub state
+/// - `transitions: [Event => Name, ...]` - transitions given by the name of
+///   the triggering event and the target state; `Event [guard] => Name / action`
+///   adds a guard and a transition action given by function name (see
+///   `TransitionSpec`); a transition without event, e.g. `[guard] => Name`, is
+///   a completion transition
+/// - `kind: Choice` - a choice pseudo state (see `StateKind`)
+/// - `kind: Orthogonal` - a state whose sub states are orthogonal regions
+/// - `history: Shallow` or `history: Deep` - history of a composite state
+/// - `entry: function`, `exit: function`, `dispatch: function` - state handler
+///   functions given by name
+///
+/// Omitted parts default to `State::new` i.e. no super state, no initial sub
+/// state, no entry and exit actions and a dispatch function ignoring all
+/// events. The state enum must derive `PartialEq`.
+///
+/// By default the transitions describe what the dispatch functions return,
+/// an action names the one returned via `ProcessingResult::TransitionWith`.
+/// Declaring the event type as `[State<Data, Event: Signal>]` makes the
+/// processor take them itself: a transition named `Name` is triggered by the
+/// events whose `Signal::signal` is `Kind::Name` and may have a guard and an
+/// action.
+///
+/// The generated enum gives compile time checks the hand-written state list
+/// lacks: duplicate state names as well as unknown super states, initial
+/// states and transition targets do not compile.
+///
+/// # Example
+///
+/// ```
+/// use qlrl::{ProcessingResult, StateMachineContext};
+///
+/// struct Data;
+/// enum Event {
+///     Toggle,
+/// }
+///
+/// fn off_dispatch<'a>(
+///     _data: &'a mut Data,
+///     _context: &mut (dyn StateMachineContext<Event> + 'a),
+///     _event: &Event,
+/// ) -> ProcessingResult<Data, Event, Switch> {
+///     ProcessingResult::Transition(Switch::On)
+/// }
+///
+/// fn on_dispatch<'a>(
+///     _data: &'a mut Data,
+///     _context: &mut (dyn StateMachineContext<Event> + 'a),
+///     _event: &Event,
+/// ) -> ProcessingResult<Data, Event, Switch> {
+///     ProcessingResult::Transition(Switch::Off)
+/// }
+///
+/// qlrl::state_machine! {
+///     #[derive(Debug, PartialEq)]
+///     pub enum Switch;
+///
+///     pub const SWITCH_STATES: [State<Data, Event>] = [
+///         Off { dispatch: off_dispatch, transitions: [Toggle => On] },
+///         On { dispatch: on_dispatch, transitions: [Toggle => Off] },
+///     ];
+/// }
+///
+/// assert_eq!(Ok(()), qlrl::validate(&SWITCH_STATES));
+/// ```
+///
+/// The same switch with transitions triggered by the signal of the events
+///
+/// ```
+/// use qlrl::Signal;
+///
+/// struct Data;
+/// enum Event {
+///     Toggle,
+/// }
+///
+/// #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// enum EventKind {
+///     Toggle,
+/// }
+///
+/// impl Signal for Event {
+///     type Kind = EventKind;
+///     fn signal(&self) -> EventKind {
+///         match self {
+///             Event::Toggle => EventKind::Toggle,
+///         }
+///     }
+/// }
+///
+/// qlrl::state_machine! {
+///     #[derive(Debug, PartialEq)]
+///     pub enum Switch;
+///
+///     pub const SWITCH_STATES: [State<Data, Event: Signal>] = [
+///         Off { transitions: [Toggle => On] },
+///         On { transitions: [Toggle => Off] },
+///     ];
+/// }
+///
+/// assert_eq!(Ok(()), qlrl::validate(&SWITCH_STATES));
+/// ```
+///
+/// Unknown transition targets are rejected by the compiler
+///
+/// ```compile_fail
+/// struct Data;
+/// struct Event;
+///
+/// qlrl::state_machine! {
+///     #[derive(PartialEq)]
+///     enum Switch;
+///     const SWITCH_STATES: [State<Data, Event>] = [
+///         Off { transitions: [Dim => Dimmed] },
+///         On { transitions: [Toggle => Off] },
+///     ];
+/// }
+/// ```
+///
+/// as are duplicate states
+///
+/// ```compile_fail
+/// struct Data;
+/// struct Event;
+///
+/// qlrl::state_machine! {
+///     #[derive(PartialEq)]
+///     enum Switch;
+///     const SWITCH_STATES: [State<Data, Event>] = [
+///         Off {},
+///         On {},
+///         Off {},
+///     ];
+/// }
+/// ```
+#[macro_export]
+macro_rules! state_machine {
+    (@field $S:ident, $E:ty, $signal:tt; super_state: $value:ident) => {
+        ::core::option::Option::Some($S::$value)
+    };
+    (@field $S:ident, $E:ty, $signal:tt; init: $value:ident) => {
+        || ::core::option::Option::Some($S::$value)
+    };
+    (@field $S:ident, $E:ty, $signal:tt; kind: $value:ident) => {
+        $crate::StateKind::$value
+    };
+    (@field $S:ident, $E:ty, $signal:tt; history: $value:ident) => {
+        ::core::option::Option::Some($crate::History::$value)
+    };
+    (@field $S:ident, $E:ty, $signal:tt; transitions: [$($($event:ident)? $([$guard:ident])? => $target:ident $(/ $action:ident)?),* $(,)?]) => {
+        &[$($crate::TransitionSpec {
+            trigger: $crate::state_machine!(@trigger $E, $signal $($event)?),
+            guard: $crate::state_machine!(@guard [$($event)?] $($guard)?),
+            action: $crate::state_machine!(@action [$($event)?] $($action)?),
+            ..$crate::TransitionSpec::new(::core::stringify!($($event)?), $S::$target)
+        }),*]
+    };
+    (@trigger $E:ty, $signal:tt) => {
+        ::core::option::Option::None
+    };
+    (@trigger $E:ty, [] $event:ident) => {
+        ::core::option::Option::None
+    };
+    (@trigger $E:ty, [$signal:ident] $event:ident) => {
+        ::core::option::Option::Some(
+            (|event: &$E| <$E as $crate::$signal>::signal(event) == <$E as $crate::$signal>::Kind::$event)
+                as $crate::TriggerFn<$E>,
+        )
+    };
+    (@guard [$($event:ident)?]) => {
+        ::core::option::Option::None
+    };
+    (@guard [] $guard:ident) => {
+        ::core::option::Option::Some($crate::Guard { name: ::core::stringify!($guard), check: $crate::Check::Completion($guard) })
+    };
+    (@guard [$event:ident] $guard:ident) => {
+        ::core::option::Option::Some($crate::Guard { name: ::core::stringify!($guard), check: $crate::Check::Event($guard) })
+    };
+    (@action [$($event:ident)?]) => {
+        ::core::option::Option::None
+    };
+    (@action [] $action:ident) => {
+        ::core::option::Option::Some($crate::Action { name: ::core::stringify!($action), run: $crate::Effect::Completion($action) })
+    };
+    (@action [$event:ident] $action:ident) => {
+        ::core::option::Option::Some($crate::Action { name: ::core::stringify!($action), run: $crate::Effect::Event($action) })
+    };
+    (@field $S:ident, $E:ty, $signal:tt; $key:ident: $value:ident) => {
+        $value
+    };
+    (@count $($state:ident)*) => {
+        0usize $(+ $crate::state_machine!(@one $state))*
+    };
+    (@one $state:ident) => {
+        1usize
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $S:ident;
+
+        $table_vis:vis const $TABLE:ident: [State<$D:ty, $E:ty $(: $signal:ident)?>] = [
+            $(
+                $(#[$state_meta:meta])*
+                $state:ident { $($key:ident: $value:tt),* $(,)? }
+            ),* $(,)?
+        ];
+    ) => {
+        // the optional signal is handed on as a single token tree to be
+        // available within the repetitions of the states and their fields
+        $crate::state_machine! {
+            @table [$($signal)?]
+            $(#[$meta])*
+            $vis enum $S;
+
+            $table_vis const $TABLE: [State<$D, $E>] = [
+                $(
+                    $(#[$state_meta])*
+                    $state { $($key: $value),* }
+                ),*
+            ];
+        }
+    };
+    (
+        @table $signal:tt
+        $(#[$meta:meta])*
+        $vis:vis enum $S:ident;
+
+        $table_vis:vis const $TABLE:ident: [State<$D:ty, $E:ty>] = [
+            $(
+                $(#[$state_meta:meta])*
+                $state:ident { $($key:ident: $value:tt),* }
+            ),*
+        ];
+    ) => {
+        $(#[$meta])*
+        $vis enum $S {
+            $(
+                $(#[$state_meta])*
+                $state,
+            )*
+        }
+
+        $table_vis const $TABLE: [$crate::State<$D, $E, $S>; $crate::state_machine!(@count $($state)*)] = [
+            $(
+                $crate::State::<$D, $E, $S> {
+                    $($key: $crate::state_machine!(@field $S, $E, $signal; $key: $value),)*
+                    ..$crate::State::<$D, $E, $S>::new($S::$state)
+                },
+            )*
+        ];
+    };
+}
@@ -0,0 +1,107 @@
+//! Observation of the state machine processors
+//!
+//! A processor reports every step of the event processing to its observer,
+//! e.g. to trace state machines while debugging. The default `NoObserver`
+//! does nothing and is optimized away. With the `log` feature `LogObserver`
+//! reports to the `log` facade.
+//!
+#[cfg(feature = "log")]
+use core::fmt::{Debug, Display};
+#[cfg(feature = "log")]
+use log::{debug, trace, warn};
+
+/// Callbacks invoked by the processors; all of them do nothing by default
+pub trait StateMachineObserver<E, S> {
+    /// The active `state` receives an event
+    fn event_received(&mut self, _state: &S, _event: &E) {}
+
+    /// The event is consumed by `state` without a transition
+    fn event_handled(&mut self, _state: &S, _event: &E) {}
+
+    /// The event is not consumed; for hierarchical state machines `state` is
+    /// the last state it was offered to
+    fn event_ignored(&mut self, _state: &S, _event: &E) {}
+
+    /// The event is deferred by `state`
+    fn event_deferred(&mut self, _state: &S, _event: &E) {}
+
+    /// The event deferred by `state` is dropped as the queue of deferred
+    /// events is full
+    fn event_dropped(&mut self, _state: &S, _event: &E) {}
+
+    /// The guard named `guard` of a declared transition of `state` is
+    /// evaluated; `holds` tells whether the transition is enabled
+    fn guard_evaluated(&mut self, _state: &S, _guard: &'static str, _holds: bool) {}
+
+    /// A handler of state `from` triggers a transition to state `to`;
+    /// reported before any exit action
+    fn transition(&mut self, _from: &S, _to: &S) {}
+
+    /// The entry action of `state` is executed
+    fn entered(&mut self, _state: &S) {}
+
+    /// The exit action of `state` is executed
+    fn exited(&mut self, _state: &S) {}
+}
+
+/// Observer ignoring everything
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NoObserver;
+
+impl<E, S> StateMachineObserver<E, S> for NoObserver {}
+
+/// Observer logging the processing steps of a state machine
+///
+/// Ignored events and guards are logged at trace level, dropped events at warn
+/// level, everything else at debug level.
+#[cfg(feature = "log")]
+pub struct LogObserver<N = &'static str> {
+    name: N,
+}
+
+#[cfg(feature = "log")]
+impl<N: Display> LogObserver<N> {
+    /// Create an observer naming the state machine by `name` in each record
+    pub fn new(name: N) -> Self {
+        LogObserver { name }
+    }
+}
+
+#[cfg(feature = "log")]
+impl<E: Debug, S: Debug, N: Display> StateMachineObserver<E, S> for LogObserver<N> {
+    fn event_received(&mut self, state: &S, event: &E) {
+        debug!("{}: {:?} receives {:?}", self.name, state, event);
+    }
+
+    fn event_handled(&mut self, state: &S, event: &E) {
+        debug!("{}: {:?} handles {:?}", self.name, state, event);
+    }
+
+    fn event_ignored(&mut self, state: &S, event: &E) {
+        trace!("{}: {:?} ignores {:?}", self.name, state, event);
+    }
+
+    fn event_deferred(&mut self, state: &S, event: &E) {
+        debug!("{}: {:?} defers {:?}", self.name, state, event);
+    }
+
+    fn event_dropped(&mut self, state: &S, event: &E) {
+        warn!("{}: {:?} drops deferred {:?}, queue full", self.name, state, event);
+    }
+
+    fn guard_evaluated(&mut self, state: &S, guard: &'static str, holds: bool) {
+        trace!("{}: {:?} guard [{}] {}", self.name, state, guard, if holds { "holds" } else { "fails" });
+    }
+
+    fn transition(&mut self, from: &S, to: &S) {
+        debug!("{}: transition {:?} -> {:?}", self.name, from, to);
+    }
+
+    fn entered(&mut self, state: &S) {
+        debug!("{}: enter {:?}", self.name, state);
+    }
+
+    fn exited(&mut self, state: &S) {
+        debug!("{}: exit {:?}", self.name, state);
+    }
+}
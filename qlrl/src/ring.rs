@@ -0,0 +1,49 @@
+//! Fixed size queue without heap allocation
+//!
+/// Fixed size first in first out buffer
+pub(crate) struct Ring<T, const N: usize> {
+    items: [Option<T>; N],
+    head: usize,
+    len: usize,
+}
+
+impl<T, const N: usize> Ring<T, N> {
+    pub(crate) fn new() -> Self {
+        Ring {
+            items: core::array::from_fn(|_| None),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Append an item; the oldest item is dropped if the buffer is full
+    pub(crate) fn push(&mut self, item: T) {
+        if self.is_full() {
+            self.pop();
+        }
+        self.items[(self.head + self.len) % N] = Some(item);
+        self.len += 1;
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let item = self.items[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        item
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &T> {
+        (0..self.len).filter_map(move |i| self.items[(self.head + i) % N].as_ref())
+    }
+
+    pub(crate) fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
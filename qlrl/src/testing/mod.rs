@@ -0,0 +1,274 @@
+//! Deterministic single threaded runtime for tests
+//!
+//! `SimulatedContext` replaces a runtime context in tests:
+//!
+//! - published and sent events are recorded and queued in fixed size buffers
+//!   (no heap, no threads), so it works in `no_std` environments
+//! - delayed events and timers run on a virtual clock that a test steps
+//!   forward via `advance`
+//! - `run` dispatches the queued events to a set of state machines until no
+//!   event is left (run to quiescence); a state machine is addressed by its
+//!   index in that set
+//!
+//! ```
+//! use qlrl::{fsm::FiniteStateMachine, state_machine, testing::SimulatedContext};
+//! use qlrl::{ProcessingResult, StateMachineContext};
+//!
+//! #[derive(Clone, Debug, PartialEq)]
+//! enum Event { Timeout, Done }
+//!
+//! fn arm<'a>(_data: &'a mut (), context: &mut (dyn StateMachineContext<Event> + 'a)) {
+//!     context.arm_timer(100, Event::Timeout);
+//! }
+//!
+//! fn wait<'a>(
+//!     _data: &'a mut (),
+//!     context: &mut (dyn StateMachineContext<Event> + 'a),
+//!     event: &Event,
+//! ) -> ProcessingResult<(), Event, Waiter> {
+//!     match event {
+//!         Event::Timeout => {
+//!             context.publish_event(Event::Done);
+//!             ProcessingResult::Handled
+//!         }
+//!         _ => ProcessingResult::Ignored,
+//!     }
+//! }
+//!
+//! state_machine! {
+//!     #[derive(Debug, PartialEq)]
+//!     enum Waiter;
+//!     const WAITER_STATES: [State<(), Event>] = [
+//!         Waiting { entry: arm, dispatch: wait },
+//!     ];
+//! }
+//!
+//! let mut waiter = FiniteStateMachine::new(&WAITER_STATES, ());
+//! let mut context = SimulatedContext::<Event>::new();
+//! context.start(&mut [&mut waiter]);
+//! context.advance(99, &mut [&mut waiter]);
+//! assert_eq!(0, context.published().count());
+//! context.advance(1, &mut [&mut waiter]);
+//! assert!(context.published().eq([&Event::Done]));
+//! ```
+//!
+use super::ring::Ring;
+use super::{MachineId, PublishError, StateMachine, StateMachineContext, TimerHandle};
+
+/// Default capacity of the event queue, the event record and the timer slots
+pub const DEFAULT_CAPACITY: usize = 16;
+
+/// Maximum number of events `SimulatedContext::run` dispatches before it
+/// considers the state machines to be caught in an endless loop
+pub const MAX_RUN_EVENTS: usize = 10_000;
+
+struct SimulatedTimer<E> {
+    handle: TimerHandle,
+    due: u64,
+    period: Option<u64>,
+    target: Option<MachineId>, // `None` publishes to all state machines
+    event: E,
+}
+
+/// Runtime context with a virtual clock for deterministic tests
+///
+/// `N` is the capacity of the event queue, of the record of published
+/// events and of the timer slots. Queuing into a full queue and arming more
+/// than `N` timers panics; the record keeps the latest `N` events.
+pub struct SimulatedContext<E, const N: usize = DEFAULT_CAPACITY> {
+    now: u64,
+    queue: Ring<(Option<MachineId>, E), N>,
+    published: Ring<(Option<MachineId>, E), N>,
+    timers: [Option<SimulatedTimer<E>>; N],
+    next_handle: u64,
+    current: Option<MachineId>, // the state machine running a handler
+}
+
+impl<E: Clone, const N: usize> SimulatedContext<E, N> {
+    pub fn new() -> Self {
+        SimulatedContext {
+            now: 0,
+            queue: Ring::new(),
+            published: Ring::new(),
+            timers: core::array::from_fn(|_| None),
+            next_handle: 1,
+            current: None,
+        }
+    }
+
+    /// Virtual time in milliseconds since creation
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Events published to all state machines so far, oldest first
+    pub fn published(&self) -> impl Iterator<Item = &E> {
+        self.published.iter().filter(|(target, _)| target.is_none()).map(|(_, e)| e)
+    }
+
+    /// Events sent to the given state machine so far, oldest first
+    pub fn sent_to(&self, target: MachineId) -> impl Iterator<Item = &E> {
+        self.published.iter().filter(move |(t, _)| *t == Some(target)).map(|(_, e)| e)
+    }
+
+    /// Forget the recorded events
+    pub fn clear_published(&mut self) {
+        self.published.clear();
+    }
+
+    /// Number of armed timers
+    pub fn pending_timers(&self) -> usize {
+        self.timers.iter().flatten().count()
+    }
+
+    fn enqueue(&mut self, target: Option<MachineId>, e: E) -> Result<(), PublishError> {
+        if self.queue.is_full() {
+            return Err(PublishError::QueueFull);
+        }
+        self.published.push((target, e.clone()));
+        self.queue.push((target, e));
+        Ok(())
+    }
+
+    fn arm(&mut self, delay_in_ms: u64, period: Option<u64>, target: Option<MachineId>, e: E) -> TimerHandle {
+        let handle = TimerHandle(self.next_handle);
+        self.next_handle += 1;
+        let slot = self
+            .timers
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .expect("SimulatedContext: too many timers armed");
+        *slot = Some(SimulatedTimer {
+            handle,
+            due: self.now + delay_in_ms,
+            period,
+            target,
+            event: e,
+        });
+        handle
+    }
+
+    /// Start the state machines and run to quiescence
+    pub fn start(&mut self, machines: &mut [&mut dyn StateMachine<E>])
+    where
+        E: Send,
+    {
+        for (index, machine) in machines.iter_mut().enumerate() {
+            self.current = Some(MachineId(index));
+            machine.start(self);
+        }
+        self.current = None;
+        self.run(machines);
+    }
+
+    /// Dispatch the queued events to the state machines until no event is
+    /// left; published events are dispatched to all state machines
+    ///
+    /// Returns the number of dispatched events.
+    pub fn run(&mut self, machines: &mut [&mut dyn StateMachine<E>]) -> usize
+    where
+        E: Send,
+    {
+        let mut count = 0;
+        while let Some((target, e)) = self.queue.pop() {
+            count += 1;
+            assert!(count <= MAX_RUN_EVENTS, "SimulatedContext: no quiescence");
+            match target {
+                Some(MachineId(index)) => {
+                    if let Some(machine) = machines.get_mut(index) {
+                        self.current = target;
+                        machine.dispatch(self, e);
+                    }
+                }
+                None => {
+                    for (index, machine) in machines.iter_mut().enumerate() {
+                        self.current = Some(MachineId(index));
+                        machine.dispatch(self, e.clone());
+                    }
+                }
+            }
+            self.current = None;
+        }
+        count
+    }
+
+    /// Step the virtual clock forward
+    ///
+    /// Expired timers fire in due order; the state machines run to
+    /// quiescence after each of them at its due time.
+    pub fn advance(&mut self, ms: u64, machines: &mut [&mut dyn StateMachine<E>])
+    where
+        E: Send,
+    {
+        let until = self.now + ms;
+        self.run(machines);
+        while let Some(slot) = self
+            .timers
+            .iter_mut()
+            .filter(|slot| matches!(slot, Some(timer) if timer.due <= until))
+            .min_by_key(|slot| slot.as_ref().map(|timer| (timer.due, timer.handle.0)))
+        {
+            let Some(timer) = slot.as_mut() else { break };
+            self.now = timer.due;
+            let target = timer.target;
+            let e = match timer.period {
+                Some(period) => {
+                    timer.due += period;
+                    timer.event.clone()
+                }
+                None => slot.take().map(|timer| timer.event).expect("timer expired"),
+            };
+            self.enqueue(target, e).expect("SimulatedContext: queue full");
+            self.run(machines);
+        }
+        self.now = until;
+    }
+}
+
+impl<E: Clone, const N: usize> Default for SimulatedContext<E, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E: Clone, const N: usize> StateMachineContext<E> for SimulatedContext<E, N> {
+    /// Queue and record an event; panics if the queue is full
+    fn publish_event(&mut self, e: E) {
+        self.enqueue(None, e).expect("SimulatedContext: queue full");
+    }
+
+    fn try_publish_event(&mut self, e: E) -> Result<(), PublishError> {
+        self.enqueue(None, e)
+    }
+
+    fn send_to(&mut self, target: MachineId, e: E) {
+        self.enqueue(Some(target), e).expect("SimulatedContext: queue full");
+    }
+
+    fn publish_delayed_event(&mut self, delay_in_ms: u64, e: E) {
+        self.arm(delay_in_ms, None, None, e);
+    }
+
+    /// Arm a timer sending the event to the state machine arming it
+    fn arm_timer(&mut self, delay_in_ms: u64, e: E) -> TimerHandle {
+        self.arm(delay_in_ms, None, self.current, e)
+    }
+
+    fn arm_periodic_timer(&mut self, period_in_ms: u64, e: E) -> TimerHandle {
+        let period = period_in_ms.max(1);
+        self.arm(period, Some(period), self.current, e)
+    }
+
+    fn cancel_timer(&mut self, handle: TimerHandle) -> bool {
+        match self.timers.iter_mut().find(|slot| matches!(slot, Some(timer) if timer.handle == handle)) {
+            Some(slot) => {
+                *slot = None;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,106 @@
+extern crate std;
+use std::{vec, vec::Vec};
+
+use super::*;
+
+/// State machine recording the received events and reacting to some of them
+#[derive(Default)]
+struct Probe {
+    received: Vec<u8>,
+}
+
+impl StateMachine<u8> for Probe {
+    fn start<'a>(&mut self, _context: &mut (dyn StateMachineContext<u8> + 'a)) {}
+
+    fn dispatch<'a>(&mut self, context: &mut (dyn StateMachineContext<u8> + 'a), event: u8) {
+        self.received.push(event);
+        match event {
+            0 => context.publish_event(0), // endless
+            7 => {
+                context.arm_timer(10, 8);
+            }
+            n if n > 10 => context.publish_event(n - 10),
+            _ => (),
+        }
+    }
+}
+
+#[test]
+fn run_delivers_published_events_to_all_and_sent_events_to_one() {
+    let (mut a, mut b) = (Probe::default(), Probe::default());
+    let mut context = SimulatedContext::<u8>::new();
+    context.publish_event(12);
+    context.send_to(MachineId(1), 5);
+    assert_eq!(4, context.run(&mut [&mut a, &mut b]));
+    assert_eq!(vec![12, 2, 2], a.received);
+    assert_eq!(vec![12, 5, 2, 2], b.received);
+    assert!(context.published().eq(&[12, 2, 2]));
+    assert!(context.sent_to(MachineId(1)).eq(&[5]));
+}
+
+#[test]
+fn delayed_events_fire_on_virtual_clock_in_due_order() {
+    let mut a = Probe::default();
+    let mut context = SimulatedContext::<u8>::new();
+    context.publish_delayed_event(30, 3);
+    context.publish_delayed_event(20, 2);
+    context.advance(19, &mut [&mut a]);
+    assert!(a.received.is_empty());
+    assert_eq!(19, context.now());
+    context.advance(20, &mut [&mut a]);
+    assert_eq!(vec![2, 3], a.received);
+    assert_eq!(39, context.now());
+}
+
+#[test]
+fn timer_events_are_sent_to_the_arming_machine() {
+    let (mut a, mut b) = (Probe::default(), Probe::default());
+    let mut context = SimulatedContext::<u8>::new();
+    context.send_to(MachineId(1), 7);
+    context.advance(10, &mut [&mut a, &mut b]);
+    assert!(a.received.is_empty());
+    assert_eq!(vec![7, 8], b.received);
+}
+
+#[test]
+fn periodic_timer_fires_until_cancelled() {
+    let mut a = Probe::default();
+    let mut context = SimulatedContext::<u8>::new();
+    let handle = context.arm_periodic_timer(10, 1);
+    context.advance(35, &mut [&mut a]);
+    assert_eq!(vec![1, 1, 1], a.received);
+    assert!(context.cancel_timer(handle));
+    assert!(!context.cancel_timer(handle));
+    assert_eq!(0, context.pending_timers());
+    context.advance(100, &mut [&mut a]);
+    assert_eq!(3, a.received.len());
+}
+
+#[test]
+fn record_keeps_latest_events() {
+    let mut context = SimulatedContext::<u8, 2>::new();
+    context.publish_event(1);
+    context.publish_event(2);
+    context.run(&mut []);
+    context.publish_event(3);
+    assert!(context.published().eq(&[2, 3]));
+    context.clear_published();
+    assert_eq!(0, context.published().count());
+}
+
+#[test]
+fn full_queue_refuses_events() {
+    let mut context = SimulatedContext::<u8, 2>::new();
+    assert_eq!(Ok(()), context.try_publish_event(1));
+    assert_eq!(Ok(()), context.try_publish_event(2));
+    assert_eq!(Err(PublishError::QueueFull), context.try_publish_event(3));
+}
+
+#[test]
+#[should_panic(expected = "no quiescence")]
+fn run_detects_endless_loops() {
+    let mut a = Probe::default();
+    let mut context = SimulatedContext::<u8>::new();
+    context.publish_event(0);
+    context.run(&mut [&mut a]);
+}
@@ -12,24 +12,24 @@ enum StateName {
     ThirdBusyProcess,
 }
 
-fn init<D, E, S: PartialEq>() -> Option<State<D, E, S>> { None }
-fn entry<'a, D, E>(_data: &'a mut D, _context: &mut (dyn StateMachineContext<E> + 'a)) -> () {}
-fn exit<'a, D, E>(_data: &'a mut D, _context: &mut (dyn StateMachineContext<E> + 'a)) -> () {}
+fn init<S>() -> Option<S> { None }
+fn entry<'a, D, E>(_data: &'a mut D, _context: &mut (dyn StateMachineContext<E> + 'a)) {}
+fn exit<'a, D, E>(_data: &'a mut D, _context: &mut (dyn StateMachineContext<E> + 'a)) {}
 fn dispatch<'a, D, E, S: PartialEq>(
     _data: &'a mut D,
     _context: &mut (dyn StateMachineContext<E> + 'a),
-    _event: E,
-) -> ProcessingResult<S> {
+    _event: &E,
+) -> ProcessingResult<D, E, S> {
     ProcessingResult::Ignored
 }
 
 const COMPLEX_STATE_MACHINE_DEFINITION : [State<Data, Event, StateName>; 6 ] = [
-    State::<Data, Event, StateName> { state: StateName::TopIdle, super_state: None, init, entry, exit, dispatch},
-    State::<Data, Event, StateName> { state: StateName::TopOperational, super_state: None, init, entry, exit, dispatch},
-    State::<Data, Event, StateName> { state: StateName::SecondBusy, super_state: Some(StateName::TopOperational), init, entry, exit, dispatch},
-    State::<Data, Event, StateName> { state: StateName::SecondWaiting, super_state: Some(StateName::TopOperational), init, entry, exit, dispatch},
-    State::<Data, Event, StateName> { state: StateName::ThirdBusyGetReady, super_state: Some(StateName::SecondBusy), init, entry, exit, dispatch},
-    State::<Data, Event, StateName> { state: StateName::ThirdBusyProcess, super_state: Some(StateName::SecondBusy), init, entry, exit, dispatch},
+    State::<Data, Event, StateName> { state: StateName::TopIdle, super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
+    State::<Data, Event, StateName> { state: StateName::TopOperational, super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
+    State::<Data, Event, StateName> { state: StateName::SecondBusy, super_state: Some(StateName::TopOperational), kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
+    State::<Data, Event, StateName> { state: StateName::SecondWaiting, super_state: Some(StateName::TopOperational), kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
+    State::<Data, Event, StateName> { state: StateName::ThirdBusyGetReady, super_state: Some(StateName::SecondBusy), kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
+    State::<Data, Event, StateName> { state: StateName::ThirdBusyProcess, super_state: Some(StateName::SecondBusy), kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
 ];
 
 
@@ -47,8 +47,305 @@ fn find_state_index_ok() {
 #[should_panic]
 fn find_state_index_fail() {
     let state_machine_definitions = [
-        State::<Data, Event, StateName> { state: StateName::TopIdle, super_state: None, init, entry, exit, dispatch},
-        State::<Data, Event, StateName> { state: StateName::TopOperational, super_state: None, init, entry, exit, dispatch},
+        State::<Data, Event, StateName> { state: StateName::TopIdle, super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
+        State::<Data, Event, StateName> { state: StateName::TopOperational, super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
     ];
     find_state_index(&state_machine_definitions, StateName::SecondBusy).expect("Not found panic");
 }
+
+fn init_second_busy() -> Option<StateName> { Some(StateName::SecondBusy) }
+fn init_top_idle() -> Option<StateName> { Some(StateName::TopIdle) }
+
+#[test]
+fn validate_ok() {
+    assert_eq!(Ok(()), validate(&COMPLEX_STATE_MACHINE_DEFINITION));
+}
+
+#[test]
+fn validate_empty_state_list() {
+    let state_machine_definitions: [State<Data, Event, StateName>; 0] = [];
+    assert_eq!(Err(ValidationError::EmptyStateList), validate(&state_machine_definitions));
+}
+
+#[test]
+fn validate_duplicate_state() {
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { state: StateName::TopIdle, super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
+        State::<Data, Event, StateName> { state: StateName::TopOperational, super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
+        State::<Data, Event, StateName> { state: StateName::TopIdle, super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
+    ];
+    assert_eq!(Err(ValidationError::DuplicateState { index: 2, first: 0 }), validate(&state_machine_definitions));
+}
+
+#[test]
+fn validate_unknown_super_state() {
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { state: StateName::TopIdle, super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
+        State::<Data, Event, StateName> { state: StateName::SecondBusy, super_state: Some(StateName::TopOperational), kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
+    ];
+    assert_eq!(Err(ValidationError::UnknownSuperState { index: 1 }), validate(&state_machine_definitions));
+}
+
+#[test]
+fn validate_unknown_transition_target() {
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { state: StateName::TopIdle, super_state: None, kind: StateKind::Regular, history: None, transitions: &[TransitionSpec { event: "Start", target: StateName::TopOperational, trigger: None, guard: None, action: None }, TransitionSpec { event: "Wait", target: StateName::SecondWaiting, trigger: None, guard: None, action: None }], init, entry, exit, dispatch},
+        State::<Data, Event, StateName> { state: StateName::TopOperational, super_state: None, kind: StateKind::Regular, history: None, transitions: &[TransitionSpec { event: "Stop", target: StateName::TopIdle, trigger: None, guard: None, action: None }], init, entry, exit, dispatch},
+    ];
+    assert_eq!(Err(ValidationError::UnknownTransitionTarget { index: 0, target: 1 }), validate(&state_machine_definitions));
+}
+
+#[test]
+fn validate_super_state_cycle() {
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { state: StateName::TopIdle, super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
+        State::<Data, Event, StateName> { state: StateName::SecondBusy, super_state: Some(StateName::SecondWaiting), kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
+        State::<Data, Event, StateName> { state: StateName::SecondWaiting, super_state: Some(StateName::SecondBusy), kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
+    ];
+    assert_eq!(Err(ValidationError::SuperStateCycle { index: 1 }), validate(&state_machine_definitions));
+}
+
+#[test]
+fn validate_initial_state_must_be_sub_state() {
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { state: StateName::TopOperational, super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init: init_second_busy, entry, exit, dispatch},
+        State::<Data, Event, StateName> { state: StateName::SecondBusy, super_state: Some(StateName::TopOperational), kind: StateKind::Regular, history: None, transitions: &[], init, entry, exit, dispatch},
+        State::<Data, Event, StateName> { state: StateName::TopIdle, super_state: None, kind: StateKind::Regular, history: None, transitions: &[], init: init_top_idle, entry, exit, dispatch},
+    ];
+    assert_eq!(Err(ValidationError::InvalidInitialState { index: 2 }), validate(&state_machine_definitions));
+}
+
+fn triggered(_event: &Event) -> bool { true }
+
+#[test]
+fn validate_guard_without_trigger_is_unreachable() {
+    static TRANSITIONS: [TransitionSpec<Data, Event, StateName>; 2] = [
+        TransitionSpec::new("Start", StateName::TopOperational),
+        TransitionSpec {
+            guard: Some(Guard { name: "is_ready", check: Check::Event(is_ready) }),
+            ..TransitionSpec::new("Resume", StateName::TopOperational)
+        },
+    ];
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { transitions: &TRANSITIONS[..1], ..State::new(StateName::TopIdle) },
+        State::<Data, Event, StateName> { transitions: &TRANSITIONS, ..State::new(StateName::TopOperational) },
+    ];
+    assert_eq!(Err(ValidationError::UnreachableTransition { index: 1, transition: 1 }), validate(&state_machine_definitions));
+}
+
+#[test]
+fn validate_action_without_trigger_names_returned_action() {
+    // the dispatch function picks one of both via `TransitionWith`
+    static TRANSITIONS: [TransitionSpec<Data, Event, StateName>; 2] = [
+        TransitionSpec::new("Start", StateName::TopOperational),
+        TransitionSpec {
+            action: Some(Action { name: "prepare", run: Effect::Event(prepare) }),
+            ..TransitionSpec::new("Start", StateName::TopOperational)
+        },
+    ];
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { transitions: &TRANSITIONS, ..State::new(StateName::TopIdle) },
+        State::<Data, Event, StateName>::new(StateName::TopOperational),
+    ];
+    assert_eq!(Ok(()), validate(&state_machine_definitions));
+}
+
+#[test]
+fn validate_guard_behind_unguarded_transition_is_unreachable() {
+    static TRANSITIONS: [TransitionSpec<Data, Event, StateName>; 3] = [
+        TransitionSpec {
+            trigger: Some(triggered),
+            action: Some(Action { name: "prepare", run: Effect::Event(prepare) }),
+            ..TransitionSpec::new("Start", StateName::TopOperational)
+        },
+        TransitionSpec {
+            trigger: Some(triggered),
+            action: Some(Action { name: "prepare", run: Effect::Event(prepare) }),
+            ..TransitionSpec::new("Resume", StateName::TopOperational)
+        },
+        TransitionSpec {
+            trigger: Some(triggered),
+            guard: Some(Guard { name: "is_ready", check: Check::Event(is_ready) }),
+            ..TransitionSpec::new("Resume", StateName::TopIdle)
+        },
+    ];
+    // different events to the same target with different actions
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { transitions: &TRANSITIONS[..2], ..State::new(StateName::TopIdle) },
+        State::<Data, Event, StateName>::new(StateName::TopOperational),
+    ];
+    assert_eq!(Ok(()), validate(&state_machine_definitions));
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { transitions: &TRANSITIONS, ..State::new(StateName::TopIdle) },
+        State::<Data, Event, StateName>::new(StateName::TopOperational),
+    ];
+    assert_eq!(Err(ValidationError::UnreachableTransition { index: 0, transition: 2 }), validate(&state_machine_definitions));
+}
+
+fn has_power(_data: &Data) -> bool { true }
+
+#[test]
+fn validate_completion_must_not_expect_event() {
+    static TRANSITIONS: [TransitionSpec<Data, Event, StateName>; 2] = [
+        TransitionSpec {
+            guard: Some(Guard { name: "has_power", check: Check::Completion(has_power) }),
+            ..TransitionSpec::new("", StateName::TopOperational)
+        },
+        TransitionSpec {
+            guard: Some(Guard { name: "is_ready", check: Check::Event(is_ready) }),
+            ..TransitionSpec::new("", StateName::TopOperational)
+        },
+    ];
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { transitions: &TRANSITIONS[..1], ..State::new(StateName::TopIdle) },
+        State::<Data, Event, StateName> { transitions: &TRANSITIONS, ..State::new(StateName::TopOperational) },
+    ];
+    assert_eq!(Err(ValidationError::InvalidCompletion { index: 1, transition: 1 }), validate(&state_machine_definitions));
+    static TRIGGERED: [TransitionSpec<Data, Event, StateName>; 1] =
+        [TransitionSpec { trigger: Some(triggered), ..TransitionSpec::new("", StateName::TopOperational) }];
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { transitions: &TRIGGERED, ..State::new(StateName::TopIdle) },
+        State::<Data, Event, StateName>::new(StateName::TopOperational),
+    ];
+    assert_eq!(Err(ValidationError::InvalidCompletion { index: 0, transition: 0 }), validate(&state_machine_definitions));
+}
+
+#[test]
+fn validate_unguarded_completion_cycle() {
+    static TO_OPERATIONAL: [TransitionSpec<Data, Event, StateName>; 1] = [TransitionSpec::new("", StateName::TopOperational)];
+    static TO_IDLE: [TransitionSpec<Data, Event, StateName>; 2] = [
+        TransitionSpec {
+            guard: Some(Guard { name: "has_power", check: Check::Completion(has_power) }),
+            ..TransitionSpec::new("", StateName::TopIdle)
+        },
+        TransitionSpec::new("", StateName::TopIdle),
+    ];
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { transitions: &TO_OPERATIONAL, ..State::new(StateName::TopIdle) },
+        State::<Data, Event, StateName> { transitions: &TO_IDLE, ..State::new(StateName::TopOperational) },
+        State::<Data, Event, StateName>::new(StateName::SecondBusy),
+    ];
+    assert_eq!(Err(ValidationError::CompletionCycle { index: 0 }), validate(&state_machine_definitions));
+    // a loop through a guarded transition is left to the run time check
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { transitions: &TO_OPERATIONAL, ..State::new(StateName::TopIdle) },
+        State::<Data, Event, StateName> { transitions: &TO_IDLE[..1], ..State::new(StateName::TopOperational) },
+        State::<Data, Event, StateName>::new(StateName::SecondBusy),
+    ];
+    assert_eq!(Ok(()), validate(&state_machine_definitions));
+}
+
+#[test]
+fn validate_choice_needs_branch_and_must_not_be_initial() {
+    static BRANCHES: [TransitionSpec<Data, Event, StateName>; 1] = [TransitionSpec::new("", StateName::TopIdle)];
+    let state_machine_definitions = [
+        State::<Data, Event, StateName>::new(StateName::TopIdle),
+        State::<Data, Event, StateName> { kind: StateKind::Choice, ..State::new(StateName::SecondBusy) },
+    ];
+    assert_eq!(Err(ValidationError::InvalidChoice { index: 1 }), validate(&state_machine_definitions));
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { kind: StateKind::Choice, history: None, transitions: &BRANCHES, ..State::new(StateName::TopIdle) },
+    ];
+    assert_eq!(Err(ValidationError::InvalidChoice { index: 0 }), validate(&state_machine_definitions));
+    let state_machine_definitions = [
+        State::<Data, Event, StateName>::new(StateName::TopIdle),
+        State::<Data, Event, StateName> { kind: StateKind::Choice, history: None, transitions: &BRANCHES, ..State::new(StateName::SecondBusy) },
+    ];
+    assert_eq!(Ok(()), validate(&state_machine_definitions));
+}
+
+#[test]
+fn validate_choice_needs_else_branch() {
+    static BRANCHES: [TransitionSpec<Data, Event, StateName>; 2] = [
+        TransitionSpec {
+            guard: Some(Guard { name: "has_power", check: Check::Completion(has_power) }),
+            ..TransitionSpec::new("", StateName::TopIdle)
+        },
+        TransitionSpec {
+            guard: Some(Guard { name: "has_power", check: Check::Completion(has_power) }),
+            ..TransitionSpec::new("", StateName::TopOperational)
+        },
+    ];
+    let state_machine_definitions = [
+        State::<Data, Event, StateName>::new(StateName::TopIdle),
+        State::<Data, Event, StateName>::new(StateName::TopOperational),
+        State::<Data, Event, StateName> { kind: StateKind::Choice, transitions: &BRANCHES, ..State::new(StateName::SecondBusy) },
+    ];
+    assert_eq!(Err(ValidationError::InvalidChoice { index: 2 }), validate(&state_machine_definitions));
+}
+
+#[test]
+fn validate_history_needs_sub_states() {
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { history: Some(History::Deep), ..State::new(StateName::TopOperational) },
+        State::<Data, Event, StateName> { history: Some(History::Shallow), ..State::new(StateName::TopIdle) },
+        State::<Data, Event, StateName> { super_state: Some(StateName::TopOperational), ..State::new(StateName::SecondBusy) },
+    ];
+    assert_eq!(Err(ValidationError::InvalidHistory { index: 1 }), validate(&state_machine_definitions));
+}
+
+#[test]
+fn validate_orthogonal_state_needs_regions() {
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { kind: StateKind::Orthogonal, ..State::new(StateName::TopIdle) },
+    ];
+    assert_eq!(Err(ValidationError::InvalidOrthogonal { index: 0 }), validate(&state_machine_definitions));
+    let state_machine_definitions = [
+        State::<Data, Event, StateName> { kind: StateKind::Orthogonal, history: Some(History::Shallow), ..State::new(StateName::TopOperational) },
+        State::<Data, Event, StateName> { super_state: Some(StateName::TopOperational), ..State::new(StateName::SecondBusy) },
+        State::<Data, Event, StateName> { super_state: Some(StateName::TopOperational), ..State::new(StateName::SecondWaiting) },
+    ];
+    assert_eq!(Err(ValidationError::InvalidHistory { index: 0 }), validate(&state_machine_definitions));
+}
+
+fn is_ready(_data: &Data, _event: &Event) -> bool { true }
+fn prepare<'a>(_data: &'a mut Data, _context: &mut (dyn StateMachineContext<Event> + 'a), _event: &Event) {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EventKind {
+    Wait,
+}
+
+impl Signal for Event {
+    type Kind = EventKind;
+    fn signal(&self) -> EventKind {
+        EventKind::Wait
+    }
+}
+
+crate::state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum Generated;
+
+    const GENERATED_STATES: [State<Data, Event: Signal>] = [
+        Idle { transitions: [Wait => Operational] },
+        Operational { init: Busy, entry: entry, exit: exit },
+        /// Nested state
+        Busy { super_state: Operational, dispatch: dispatch, transitions: [Wait [is_ready] => Waiting / prepare, Wait => Idle] },
+        Waiting { super_state: Operational },
+    ];
+}
+
+#[test]
+fn state_machine_macro_generates_state_list() {
+    assert_eq!(4, GENERATED_STATES.len());
+    assert_eq!(Ok(()), validate(&GENERATED_STATES));
+    assert_eq!(2_usize, find_state_index(&GENERATED_STATES, Generated::Busy).unwrap());
+    assert_eq!(Some(Generated::Operational), GENERATED_STATES[2].super_state);
+    assert_eq!(None, GENERATED_STATES[1].super_state);
+    assert_eq!(Some(Generated::Busy), (GENERATED_STATES[1].init)());
+    assert_eq!(None, (GENERATED_STATES[2].init)());
+    assert_eq!(
+        [
+            TransitionSpec {
+                trigger: Some(triggered),
+                guard: Some(Guard { name: "is_ready", check: Check::Event(is_ready) }),
+                action: Some(Action { name: "prepare", run: Effect::Event(prepare) }),
+                ..TransitionSpec::new("Wait", Generated::Waiting)
+            },
+            TransitionSpec { trigger: Some(triggered), ..TransitionSpec::new("Wait", Generated::Idle) },
+        ],
+        GENERATED_STATES[2].transitions
+    );
+    assert!(GENERATED_STATES[2].transitions.iter().all(|t| t.trigger.is_some_and(|trigger| trigger(&Event))));
+}
@@ -0,0 +1,36 @@
+//! Tick source on top of the host clock
+//!
+//! Lets the kernel run on a host computer, e.g. to test firmware logic
+//! before flashing it.
+//!
+extern crate std;
+use std::time::{Duration, Instant};
+
+use super::TickSource;
+
+/// Tick source counting the whole milliseconds of the host clock
+///
+/// Fractions of a millisecond are carried over to the next call.
+pub struct HostTicks {
+    last: Instant,
+}
+
+impl HostTicks {
+    pub fn new() -> Self {
+        HostTicks { last: Instant::now() }
+    }
+}
+
+impl Default for HostTicks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TickSource for HostTicks {
+    fn elapsed_ms(&mut self) -> u64 {
+        let elapsed_ms = u64::try_from(self.last.elapsed().as_millis()).unwrap_or(u64::MAX);
+        self.last += Duration::from_millis(elapsed_ms);
+        elapsed_ms
+    }
+}
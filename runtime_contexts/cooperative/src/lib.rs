@@ -0,0 +1,396 @@
+//! Cooperative run to completion kernel for `no_std` targets
+//!
+//! - every state machine has a statically sized event queue of its own
+//! - the kernel always dispatches the oldest event of the non-empty queue
+//!   with the highest priority; events are processed to completion, a state
+//!   machine is never preempted by another one
+//! - published events are copied into the queues of all subscribed state
+//!   machines right away, there is no dispatcher
+//! - timers are kept in a statically sized table and advanced by ticks of a
+//!   `TickSource`, i.e. a hardware timer or the host clock
+//! - when all queues are empty, an idle hook is called with the time until
+//!   the next timer expires, e.g. to put the processor to sleep
+//!
+//! Neither heap nor threads are used; the capacities are const generic
+//! parameters of the `Kernel`. The state machines are borrowed by the kernel.
+//!
+//! Events from interrupt handlers must be handed over to the main loop,
+//! e.g. via a lock-free queue polled in the idle hook, and injected with
+//! `Kernel::publish` or `Kernel::send_to`.
+//!
+#![no_std]
+
+use core::{cmp::Reverse, ops::ControlFlow};
+use log::{debug, error, warn};
+
+use qlrl::{MachineId, PublishError, StateMachine, StateMachineContext, TimerHandle};
+
+mod queue;
+use queue::EventQueue;
+mod timer;
+use timer::{TimerTable, DEAD_TIMER};
+#[cfg(any(test, feature = "host"))]
+mod host;
+#[cfg(any(test, feature = "host"))]
+pub use host::HostTicks;
+
+/// Subscription filter deciding which published events a state machine receives
+pub type Subscription<E> = fn(&E) -> bool;
+
+/// Source of the kernel time, e.g. a hardware timer or the host clock
+pub trait TickSource {
+    /// Milliseconds elapsed since the previous call
+    fn elapsed_ms(&mut self) -> u64;
+}
+
+/// Registration of a state machine
+struct Route<E> {
+    priority: u8,
+    subscription: Option<Subscription<E>>, // None: all published events
+}
+
+impl<E> Route<E> {
+    fn accepts(&self, e: &E) -> bool {
+        self.subscription.is_none_or(|subscribed| subscribed(e))
+    }
+}
+
+/// Everything but the state machines, shared by all state machine contexts
+struct Bus<E, const MACHINES: usize, const QUEUE: usize, const TIMERS: usize> {
+    routes: [Option<Route<E>>; MACHINES],
+    queues: [EventQueue<E, QUEUE>; MACHINES],
+    timers: TimerTable<E, TIMERS>,
+    now_ms: u64,
+    stopped: bool,
+}
+
+impl<E: Clone, const MACHINES: usize, const QUEUE: usize, const TIMERS: usize> Bus<E, MACHINES, QUEUE, TIMERS> {
+    /// Copy an event into the queues of all subscribed state machines
+    ///
+    /// The event is refused as a whole if one of the queues is full.
+    fn publish(&mut self, e: E) -> Result<(), PublishError> {
+        if self.stopped {
+            return Err(PublishError::Stopped);
+        }
+        let subscribed = |route: &Option<Route<E>>| route.as_ref().is_some_and(|route| route.accepts(&e));
+        let full = self.routes.iter().zip(&self.queues).any(|(route, queue)| subscribed(route) && queue.is_full());
+        if full {
+            return Err(PublishError::QueueFull);
+        }
+        for (route, queue) in self.routes.iter().zip(self.queues.iter_mut()) {
+            if subscribed(route) {
+                let _ = queue.push(e.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Queue an event for a single state machine, regardless of its subscription
+    fn send_to(&mut self, target: MachineId, e: E) -> Result<(), PublishError> {
+        if self.stopped {
+            return Err(PublishError::Stopped);
+        }
+        match self.routes.get(target.0) {
+            Some(Some(_)) => self.queues[target.0].push(e).map_err(|_| PublishError::QueueFull),
+            _ => {
+                warn!("Kernel: Event for unknown state machine {} dropped", target.0);
+                Ok(())
+            }
+        }
+    }
+
+    /// State machine to dispatch next: the highest priority with queued
+    /// events, the first added one among equal priorities
+    fn next_ready(&self) -> Option<usize> {
+        self.routes
+            .iter()
+            .zip(&self.queues)
+            .enumerate()
+            .filter_map(|(index, (route, queue))| match route {
+                Some(route) if !queue.is_empty() => Some((route.priority, Reverse(index))),
+                _ => None,
+            })
+            .max()
+            .map(|(_, Reverse(index))| index)
+    }
+}
+
+/// Runtime context handed to a state machine by the kernel
+pub struct KernelContext<'b, E, const MACHINES: usize, const QUEUE: usize, const TIMERS: usize> {
+    id: MachineId,
+    bus: &'b mut Bus<E, MACHINES, QUEUE, TIMERS>,
+}
+
+impl<E, const MACHINES: usize, const QUEUE: usize, const TIMERS: usize> StateMachineContext<E>
+    for KernelContext<'_, E, MACHINES, QUEUE, TIMERS>
+where
+    E: Clone,
+{
+    /// Publish an event; failures are logged
+    fn publish_event(&mut self, e: E) {
+        match self.bus.publish(e) {
+            Ok(()) => (),
+            Err(PublishError::Stopped) => debug!("Event dropped: kernel stopped"),
+            Err(reason) => error!("Event dropped: {}", reason),
+        }
+    }
+
+    /// Publish an event; it is refused if the queue of a subscribed state
+    /// machine is full
+    fn try_publish_event(&mut self, e: E) -> Result<(), PublishError> {
+        self.bus.publish(e)
+    }
+
+    /// Send an event to a single state machine; failures are logged
+    fn send_to(&mut self, target: MachineId, e: E) {
+        if let Err(reason) = self.bus.send_to(target, e) {
+            error!("Event for {:?} dropped: {}", target, reason);
+        }
+    }
+
+    /// Publish an event to all subscribed state machines after a delay
+    ///
+    /// The delay takes one of the `TIMERS` slots of the kernel; if all are
+    /// armed the event is dropped and an error is logged.
+    fn publish_delayed_event(&mut self, delay_in_ms: u64, e: E) {
+        if self.bus.timers.arm(self.bus.now_ms, delay_in_ms, None, e).is_err() {
+            error!("Delayed event dropped: timer table full");
+        }
+    }
+
+    /// Send an event to this state machine after a delay
+    ///
+    /// If the timer table is full the event is dropped, an error is logged
+    /// and the returned handle cannot be cancelled.
+    fn arm_timer(&mut self, delay_in_ms: u64, e: E) -> TimerHandle {
+        self.bus.timers.arm(self.bus.now_ms, delay_in_ms, Some(self.id), e).unwrap_or_else(|_| {
+            error!("Timer of {:?} not armed: timer table full", self.id);
+            DEAD_TIMER
+        })
+    }
+
+    /// Send a copy of the event to this state machine every period
+    ///
+    /// If the timer table is full the event is dropped, an error is logged
+    /// and the returned handle cannot be cancelled.
+    fn arm_periodic_timer(&mut self, period_in_ms: u64, e: E) -> TimerHandle {
+        self.bus.timers.arm_periodic(self.bus.now_ms, period_in_ms, Some(self.id), e).unwrap_or_else(|_| {
+            error!("Periodic timer of {:?} not armed: timer table full", self.id);
+            DEAD_TIMER
+        })
+    }
+
+    fn cancel_timer(&mut self, handle: TimerHandle) -> bool {
+        self.bus.timers.cancel(handle)
+    }
+}
+
+/// Cooperative kernel running up to `MACHINES` state machines
+///
+/// - `QUEUE` is the capacity of the event queue of each state machine
+/// - `TIMERS` is the number of timers that can be armed at the same time,
+///   including delayed published events; further ones are dropped with an
+///   error
+///
+/// A higher priority value is dispatched first.
+pub struct Kernel<'m, E, const MACHINES: usize, const QUEUE: usize, const TIMERS: usize> {
+    machines: [Option<&'m mut (dyn StateMachine<E> + 'm)>; MACHINES],
+    bus: Bus<E, MACHINES, QUEUE, TIMERS>,
+    started: bool,
+}
+
+impl<'m, E, const MACHINES: usize, const QUEUE: usize, const TIMERS: usize> Kernel<'m, E, MACHINES, QUEUE, TIMERS>
+where
+    E: Clone + Send,
+{
+    pub fn new() -> Self {
+        Kernel {
+            machines: core::array::from_fn(|_| None),
+            bus: Bus {
+                routes: core::array::from_fn(|_| None),
+                queues: core::array::from_fn(|_| EventQueue::new()),
+                timers: TimerTable::new(),
+                now_ms: 0,
+                stopped: false,
+            },
+            started: false,
+        }
+    }
+
+    /// Add a state machine receiving all published events
+    ///
+    /// Panics if more than `MACHINES` state machines are added.
+    pub fn add(&mut self, state_machine: &'m mut (dyn StateMachine<E> + 'm), priority: u8) -> MachineId {
+        self.register(state_machine, priority, None)
+    }
+
+    /// Add a state machine receiving the published events accepted by the
+    /// subscription; events sent to it are always received
+    ///
+    /// Panics if more than `MACHINES` state machines are added.
+    pub fn add_subscribed(
+        &mut self,
+        state_machine: &'m mut (dyn StateMachine<E> + 'm),
+        priority: u8,
+        subscription: Subscription<E>,
+    ) -> MachineId {
+        self.register(state_machine, priority, Some(subscription))
+    }
+
+    fn register(
+        &mut self,
+        state_machine: &'m mut (dyn StateMachine<E> + 'm),
+        priority: u8,
+        subscription: Option<Subscription<E>>,
+    ) -> MachineId {
+        let index = self
+            .machines
+            .iter()
+            .position(Option::is_none)
+            .expect("More state machines than the kernel capacity");
+        self.machines[index] = Some(state_machine);
+        self.bus.routes[index] = Some(Route { priority, subscription });
+        MachineId(index)
+    }
+
+    /// Publish an event to all subscribed state machines from outside
+    pub fn publish(&mut self, e: E) -> Result<(), PublishError> {
+        self.bus.publish(e)
+    }
+
+    /// Send an event to a single state machine from outside
+    pub fn send_to(&mut self, target: MachineId, e: E) -> Result<(), PublishError> {
+        self.bus.send_to(target, e)
+    }
+
+    /// Milliseconds of kernel time, i.e. the sum of all ticks
+    pub fn now_ms(&self) -> u64 {
+        self.bus.now_ms
+    }
+
+    /// Milliseconds until the next timer expires, `None` if no timer is armed
+    pub fn next_timeout(&self) -> Option<u64> {
+        self.bus.timers.next_due().map(|due_ms| due_ms.saturating_sub(self.bus.now_ms))
+    }
+
+    /// Indices of the state machines, highest priority first
+    fn by_priority(&self) -> [usize; MACHINES] {
+        let mut order: [usize; MACHINES] = core::array::from_fn(|index| index);
+        order.sort_unstable_by_key(|&index| (Reverse(self.bus.routes[index].as_ref().map(|r| r.priority)), index));
+        order
+    }
+
+    /// Start all state machines, highest priority first
+    ///
+    /// Events queued before are processed afterwards. Starting twice does
+    /// nothing.
+    pub fn start(&mut self) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        debug!("Kernel: start");
+        for index in self.by_priority() {
+            if let Some(state_machine) = self.machines[index].as_mut() {
+                let mut context = KernelContext {
+                    id: MachineId(index),
+                    bus: &mut self.bus,
+                };
+                state_machine.start(&mut context);
+            }
+        }
+    }
+
+    /// Dispatch the next event of the highest priority non-empty queue
+    ///
+    /// Returns `false` if all queues are empty.
+    pub fn dispatch_next(&mut self) -> bool {
+        let Some(index) = self.bus.next_ready() else {
+            return false;
+        };
+        let Some(event) = self.bus.queues[index].pop() else {
+            return false;
+        };
+        if let Some(state_machine) = self.machines[index].as_mut() {
+            let mut context = KernelContext {
+                id: MachineId(index),
+                bus: &mut self.bus,
+            };
+            state_machine.dispatch(&mut context, event);
+        }
+        true
+    }
+
+    /// Dispatch events until all queues are empty
+    pub fn run_until_idle(&mut self) {
+        while self.dispatch_next() {}
+    }
+
+    /// Advance the kernel time and queue the events of expired timers
+    ///
+    /// Timers expire in the order they are due; an event that does not fit
+    /// into a queue is dropped.
+    pub fn tick(&mut self, elapsed_ms: u64) {
+        self.bus.now_ms = self.bus.now_ms.saturating_add(elapsed_ms);
+        while let Some((target, e)) = self.bus.timers.expire(self.bus.now_ms) {
+            let delivered = match target {
+                Some(target) => self.bus.send_to(target, e),
+                None => self.bus.publish(e),
+            };
+            if let Err(reason) = delivered {
+                error!("Kernel: Timer event dropped: {}", reason);
+            }
+        }
+    }
+
+    /// Start the state machines and process events until the idle hook breaks
+    ///
+    /// The idle hook is called whenever all queues are empty, with the
+    /// milliseconds until the next timer expires. Afterwards the kernel is
+    /// stopped.
+    pub fn run(&mut self, ticks: &mut impl TickSource, mut idle: impl FnMut(Option<u64>) -> ControlFlow<()>) {
+        self.start();
+        loop {
+            self.tick(ticks.elapsed_ms());
+            if !self.dispatch_next() && idle(self.next_timeout()).is_break() {
+                break;
+            }
+        }
+        self.stop();
+    }
+
+    /// Let all state machines leave their active states, highest priority
+    /// first; queued events and armed timers are discarded
+    ///
+    /// Afterwards the kernel refuses events. Stopping twice does nothing.
+    pub fn stop(&mut self) {
+        if self.bus.stopped {
+            return;
+        }
+        debug!("Kernel: stop");
+        self.bus.stopped = true;
+        for index in self.by_priority() {
+            if let Some(state_machine) = self.machines[index].as_mut() {
+                let mut context = KernelContext {
+                    id: MachineId(index),
+                    bus: &mut self.bus,
+                };
+                state_machine.stop(&mut context);
+            }
+        }
+        self.bus.queues.iter_mut().for_each(EventQueue::clear);
+        self.bus.timers.clear();
+    }
+}
+
+impl<E, const MACHINES: usize, const QUEUE: usize, const TIMERS: usize> Default for Kernel<'_, E, MACHINES, QUEUE, TIMERS>
+where
+    E: Clone + Send,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,57 @@
+//! Statically sized event queue
+//!
+//! First in first out buffer without heap allocation; a full queue refuses
+//! new events instead of dropping queued ones.
+//!
+
+/// Fixed size event queue of a state machine
+pub(crate) struct EventQueue<E, const N: usize> {
+    items: [Option<E>; N],
+    head: usize,
+    len: usize,
+}
+
+impl<E, const N: usize> EventQueue<E, N> {
+    pub(crate) fn new() -> Self {
+        EventQueue {
+            items: core::array::from_fn(|_| None),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Append an event; a full queue hands the event back
+    pub(crate) fn push(&mut self, e: E) -> Result<(), E> {
+        if self.is_full() {
+            return Err(e);
+        }
+        self.items[(self.head + self.len) % N] = Some(e);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<E> {
+        if self.is_empty() {
+            return None;
+        }
+        let e = self.items[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        e
+    }
+
+    pub(crate) fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests;
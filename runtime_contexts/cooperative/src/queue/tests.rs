@@ -0,0 +1,25 @@
+use super::*;
+
+#[test]
+fn events_leave_in_order() {
+    let mut queue = EventQueue::<u8, 3>::new();
+    assert!(queue.is_empty());
+    for e in 1..=3 {
+        queue.push(e).unwrap();
+    }
+    assert_eq!(Some(1), queue.pop());
+    queue.push(4).unwrap(); // wraps around
+    assert_eq!([Some(2), Some(3), Some(4), None], [(); 4].map(|_| queue.pop()));
+}
+
+#[test]
+fn full_queue_refuses_events() {
+    let mut queue = EventQueue::<u8, 2>::new();
+    queue.push(1).unwrap();
+    queue.push(2).unwrap();
+    assert!(queue.is_full());
+    assert_eq!(Err(3), queue.push(3));
+    queue.clear();
+    assert!(queue.is_empty());
+    assert_eq!(Ok(()), queue.push(3));
+}
@@ -0,0 +1,250 @@
+extern crate std;
+use std::{cell::RefCell, format, rc::Rc, string::String, vec, vec::Vec};
+
+use super::*;
+use qlrl::{fsm::FiniteStateMachine, state_machine, ProcessingResult};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Ev {
+    Note(u8),
+    Forward(u8), // publish a note
+    Delay(u64),  // arm a timer for note 0
+    Every(u64),  // arm a periodic timer for note 1
+    Cancel,
+}
+
+type Trace = Rc<RefCell<Vec<String>>>;
+
+struct Recorder {
+    name: char,
+    trace: Trace,
+    timer: Option<TimerHandle>,
+}
+
+fn recorder_exit<'a>(data: &'a mut Recorder, _context: &mut (dyn StateMachineContext<Ev> + 'a)) {
+    data.trace.borrow_mut().push(format!("{}x", data.name));
+}
+
+fn recorder_dispatch<'a>(
+    data: &'a mut Recorder,
+    context: &mut (dyn StateMachineContext<Ev> + 'a),
+    event: &Ev,
+) -> ProcessingResult<Recorder, Ev, RecorderState> {
+    match *event {
+        Ev::Note(n) => data.trace.borrow_mut().push(format!("{}{}", data.name, n)),
+        Ev::Forward(n) => context.publish_event(Ev::Note(n)),
+        Ev::Delay(ms) => data.timer = Some(context.arm_timer(ms, Ev::Note(0))),
+        Ev::Every(ms) => data.timer = Some(context.arm_periodic_timer(ms, Ev::Note(1))),
+        Ev::Cancel => {
+            if let Some(handle) = data.timer.take() {
+                context.cancel_timer(handle);
+            }
+        }
+    }
+    ProcessingResult::Handled
+}
+
+state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum RecorderState;
+
+    const RECORDER_STATES: [State<Recorder, Ev>] = [
+        Recording { exit: recorder_exit, dispatch: recorder_dispatch },
+    ];
+}
+
+fn recorder(name: char, trace: &Trace) -> FiniteStateMachine<Recorder, Ev, RecorderState> {
+    FiniteStateMachine::new(
+        &RECORDER_STATES,
+        Recorder {
+            name,
+            trace: trace.clone(),
+            timer: None,
+        },
+    )
+}
+
+fn is_note(e: &Ev) -> bool {
+    matches!(e, Ev::Note(_))
+}
+
+type TestKernel<'m> = Kernel<'m, Ev, 3, 4, 2>;
+
+#[test]
+fn highest_priority_queue_is_dispatched_first() {
+    let trace = Trace::default();
+    let (mut low, mut high) = (recorder('l', &trace), recorder('h', &trace));
+    let mut kernel = TestKernel::new();
+    kernel.add(&mut low, 1);
+    kernel.add(&mut high, 2);
+    kernel.start();
+    kernel.publish(Ev::Note(1)).unwrap();
+    kernel.publish(Ev::Note(2)).unwrap();
+    kernel.run_until_idle();
+    assert_eq!(vec!["h1", "h2", "l1", "l2"], *trace.borrow());
+}
+
+#[test]
+fn published_events_are_dispatched_before_lower_priorities_continue() {
+    let trace = Trace::default();
+    let (mut low, mut high) = (recorder('l', &trace), recorder('h', &trace));
+    let mut kernel = TestKernel::new();
+    kernel.add(&mut low, 1);
+    kernel.add_subscribed(&mut high, 2, is_note);
+    kernel.start();
+    kernel.publish(Ev::Forward(3)).unwrap();
+    kernel.publish(Ev::Note(5)).unwrap();
+    kernel.run_until_idle();
+    assert_eq!(vec!["h5", "h3", "l5", "l3"], *trace.borrow());
+}
+
+#[test]
+fn equal_priorities_are_dispatched_in_order_of_addition() {
+    let trace = Trace::default();
+    let (mut a, mut b) = (recorder('a', &trace), recorder('b', &trace));
+    let mut kernel = TestKernel::new();
+    kernel.add(&mut a, 1);
+    kernel.add(&mut b, 1);
+    kernel.start();
+    kernel.publish(Ev::Note(1)).unwrap();
+    kernel.run_until_idle();
+    assert_eq!(vec!["a1", "b1"], *trace.borrow());
+}
+
+#[test]
+fn sent_events_reach_addressed_machine_only() {
+    let trace = Trace::default();
+    let (mut a, mut b) = (recorder('a', &trace), recorder('b', &trace));
+    let mut kernel = TestKernel::new();
+    kernel.add(&mut a, 1);
+    let b = kernel.add_subscribed(&mut b, 1, |_| false);
+    kernel.start();
+    kernel.publish(Ev::Note(1)).unwrap();
+    kernel.send_to(b, Ev::Note(2)).unwrap(); // not filtered by the subscription
+    assert_eq!(Ok(()), kernel.send_to(MachineId(7), Ev::Note(3)));
+    kernel.run_until_idle();
+    assert_eq!(vec!["a1", "b2"], *trace.borrow());
+}
+
+#[test]
+fn full_queue_refuses_published_event_as_a_whole() {
+    let trace = Trace::default();
+    let (mut a, mut b) = (recorder('a', &trace), recorder('b', &trace));
+    let mut kernel = TestKernel::new();
+    kernel.add(&mut a, 1);
+    let b = kernel.add(&mut b, 1);
+    for n in 0..4 {
+        kernel.send_to(b, Ev::Note(n)).unwrap();
+    }
+    assert_eq!(Err(PublishError::QueueFull), kernel.send_to(b, Ev::Note(4)));
+    assert_eq!(Err(PublishError::QueueFull), kernel.publish(Ev::Note(9)));
+    kernel.start(); // events queued before are processed after start
+    kernel.run_until_idle();
+    assert_eq!(vec!["b0", "b1", "b2", "b3"], *trace.borrow());
+}
+
+#[test]
+fn timers_send_to_the_arming_state_machine() {
+    let trace = Trace::default();
+    let (mut a, mut b) = (recorder('a', &trace), recorder('b', &trace));
+    let mut kernel = TestKernel::new();
+    let a = kernel.add(&mut a, 1);
+    kernel.add(&mut b, 2);
+    kernel.start();
+    kernel.send_to(a, Ev::Delay(10)).unwrap();
+    kernel.run_until_idle();
+    assert_eq!(Some(10), kernel.next_timeout());
+    kernel.tick(9);
+    assert!(!kernel.dispatch_next());
+    kernel.tick(1);
+    kernel.run_until_idle();
+    assert_eq!(vec!["a0"], *trace.borrow());
+    assert_eq!(None, kernel.next_timeout());
+    assert_eq!(10, kernel.now_ms());
+}
+
+#[test]
+fn periodic_timers_repeat_until_cancelled() {
+    let trace = Trace::default();
+    let mut a = recorder('a', &trace);
+    let mut kernel = TestKernel::new();
+    let a = kernel.add(&mut a, 1);
+    kernel.start();
+    kernel.send_to(a, Ev::Every(5)).unwrap();
+    kernel.run_until_idle();
+    kernel.tick(5);
+    kernel.run_until_idle();
+    kernel.tick(12); // missed periods are skipped
+    kernel.run_until_idle();
+    assert_eq!(Some(3), kernel.next_timeout());
+    kernel.send_to(a, Ev::Cancel).unwrap();
+    kernel.run_until_idle();
+    kernel.tick(100);
+    kernel.run_until_idle();
+    assert_eq!(vec!["a1", "a1"], *trace.borrow());
+}
+
+/// Tick source advancing one millisecond per call
+struct StepTicks;
+
+impl TickSource for StepTicks {
+    fn elapsed_ms(&mut self) -> u64 {
+        1
+    }
+}
+
+#[test]
+fn run_calls_idle_hook_until_it_breaks() {
+    let trace = Trace::default();
+    let mut a = recorder('a', &trace);
+    let mut kernel = TestKernel::new();
+    let a = kernel.add(&mut a, 1);
+    kernel.send_to(a, Ev::Delay(3)).unwrap();
+    let mut timeouts = vec![];
+    kernel.run(&mut StepTicks, |timeout| {
+        timeouts.push(timeout);
+        if trace.borrow().is_empty() {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(())
+        }
+    });
+    assert_eq!(vec![Some(2), Some(1), None], timeouts);
+    assert_eq!(vec!["a0", "ax"], *trace.borrow());
+}
+
+#[test]
+fn stopped_kernel_refuses_events() {
+    let trace = Trace::default();
+    let mut a = recorder('a', &trace);
+    let mut kernel = TestKernel::new();
+    let a = kernel.add(&mut a, 1);
+    kernel.start();
+    kernel.send_to(a, Ev::Delay(10)).unwrap();
+    kernel.stop();
+    kernel.stop();
+    assert_eq!(Err(PublishError::Stopped), kernel.publish(Ev::Note(1)));
+    assert_eq!(Err(PublishError::Stopped), kernel.send_to(a, Ev::Note(2)));
+    assert!(!kernel.dispatch_next());
+    assert_eq!(None, kernel.next_timeout());
+    assert_eq!(vec!["ax"], *trace.borrow());
+}
+
+#[test]
+#[should_panic(expected = "More state machines than the kernel capacity")]
+fn adding_more_state_machines_than_capacity_panics() {
+    let trace = Trace::default();
+    let mut machines = [(); 3].map(|_| recorder('a', &trace));
+    let mut kernel = Kernel::<Ev, 2, 4, 2>::new();
+    for state_machine in machines.iter_mut() {
+        kernel.add(state_machine, 1);
+    }
+}
+
+#[test]
+fn host_ticks_count_whole_milliseconds() {
+    let mut ticks = HostTicks::new();
+    std::thread::sleep(std::time::Duration::from_millis(3));
+    assert!(ticks.elapsed_ms() >= 3);
+    assert!(ticks.elapsed_ms() <= 1);
+}
@@ -0,0 +1,115 @@
+//! Statically sized timer table driven by the kernel tick
+//!
+//! - timers are kept in a table of fixed size, no heap is needed
+//! - the kernel advances the time and takes the expired timers in the order
+//!   they are due
+//! - a periodic timer skips the periods missed by a late tick
+//! - a full table hands the event back, the kernel reports it
+//!
+use qlrl::{MachineId, TimerHandle};
+
+/// Handle of a timer that could not be armed; cancelling it fails
+pub(crate) const DEAD_TIMER: TimerHandle = TimerHandle(u64::MAX);
+
+/// Period of a periodic timer and how to copy its event
+type Repeat<E> = (u64, fn(&E) -> E);
+
+/// Armed timer; `target` is `None` for a delayed event published to all
+struct Timer<E> {
+    handle: TimerHandle,
+    target: Option<MachineId>,
+    due_ms: u64,
+    repeat: Option<Repeat<E>>,
+    event: E,
+}
+
+/// Timers of a kernel
+pub(crate) struct TimerTable<E, const N: usize> {
+    timers: [Option<Timer<E>>; N],
+    next_handle: u64,
+}
+
+impl<E, const N: usize> TimerTable<E, N> {
+    pub(crate) fn new() -> Self {
+        TimerTable {
+            timers: core::array::from_fn(|_| None),
+            next_handle: 0,
+        }
+    }
+
+    fn insert(&mut self, target: Option<MachineId>, due_ms: u64, repeat: Option<Repeat<E>>, event: E) -> Result<TimerHandle, E> {
+        let Some(slot) = self.timers.iter_mut().find(|slot| slot.is_none()) else {
+            return Err(event);
+        };
+        let handle = TimerHandle(self.next_handle);
+        self.next_handle += 1;
+        *slot = Some(Timer {
+            handle,
+            target,
+            due_ms,
+            repeat,
+            event,
+        });
+        Ok(handle)
+    }
+
+    /// Arm a one-shot timer expiring `delay_ms` after `now_ms`
+    ///
+    /// A full table hands the event back.
+    pub(crate) fn arm(&mut self, now_ms: u64, delay_ms: u64, target: Option<MachineId>, e: E) -> Result<TimerHandle, E> {
+        self.insert(target, now_ms.saturating_add(delay_ms), None, e)
+    }
+
+    /// Arm a timer expiring every period after `now_ms`; a period of zero is
+    /// treated as one millisecond
+    ///
+    /// A full table hands the event back.
+    pub(crate) fn arm_periodic(&mut self, now_ms: u64, period_ms: u64, target: Option<MachineId>, e: E) -> Result<TimerHandle, E>
+    where
+        E: Clone,
+    {
+        let period_ms = period_ms.max(1);
+        self.insert(target, now_ms.saturating_add(period_ms), Some((period_ms, E::clone)), e)
+    }
+
+    pub(crate) fn cancel(&mut self, handle: TimerHandle) -> bool {
+        match self.timers.iter_mut().find(|slot| matches!(slot, Some(t) if t.handle == handle)) {
+            Some(slot) => {
+                *slot = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.timers.iter_mut().for_each(|slot| *slot = None);
+    }
+
+    /// Point in time the next timer expires
+    pub(crate) fn next_due(&self) -> Option<u64> {
+        self.timers.iter().flatten().map(|t| t.due_ms).min()
+    }
+
+    /// Take the earliest timer expired at `now_ms`; a periodic timer is
+    /// rearmed for the first period ending after `now_ms`
+    pub(crate) fn expire(&mut self, now_ms: u64) -> Option<(Option<MachineId>, E)> {
+        let slot = self
+            .timers
+            .iter_mut()
+            .filter(|slot| matches!(slot, Some(t) if t.due_ms <= now_ms))
+            .min_by_key(|slot| slot.as_ref().map(|t| (t.due_ms, t.handle.0)))?;
+        let timer = slot.as_mut()?;
+        match timer.repeat {
+            Some((period_ms, copy)) => {
+                let missed = (now_ms - timer.due_ms) / period_ms;
+                timer.due_ms += (missed + 1) * period_ms;
+                Some((timer.target, copy(&timer.event)))
+            }
+            None => slot.take().map(|t| (t.target, t.event)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,50 @@
+extern crate std;
+use std::{vec, vec::Vec};
+
+use super::*;
+
+fn expire_all(timers: &mut TimerTable<u8, 4>, now_ms: u64) -> Vec<(Option<MachineId>, u8)> {
+    core::iter::from_fn(|| timers.expire(now_ms)).collect()
+}
+
+#[test]
+fn timers_expire_in_due_order() {
+    let mut timers = TimerTable::<u8, 4>::new();
+    timers.arm(0, 30, None, 3).unwrap();
+    timers.arm(0, 10, Some(MachineId(1)), 1).unwrap();
+    timers.arm(5, 5, None, 2).unwrap(); // same due time, armed later
+    assert_eq!(Some(10), timers.next_due());
+    assert!(expire_all(&mut timers, 9).is_empty());
+    assert_eq!(vec![(Some(MachineId(1)), 1), (None, 2)], expire_all(&mut timers, 20));
+    assert_eq!(vec![(None, 3)], expire_all(&mut timers, 30));
+    assert_eq!(None, timers.next_due());
+}
+
+#[test]
+fn periodic_timer_skips_missed_periods() {
+    let mut timers = TimerTable::<u8, 4>::new();
+    timers.arm_periodic(0, 10, None, 7).unwrap();
+    assert_eq!(vec![(None, 7)], expire_all(&mut timers, 10));
+    assert_eq!(vec![(None, 7)], expire_all(&mut timers, 35)); // 20 and 30 at once
+    assert_eq!(Some(40), timers.next_due());
+}
+
+#[test]
+fn cancelled_timers_do_not_expire() {
+    let mut timers = TimerTable::<u8, 4>::new();
+    let handle = timers.arm(0, 10, None, 1).unwrap();
+    assert!(timers.cancel(handle));
+    assert!(!timers.cancel(handle));
+    assert!(expire_all(&mut timers, 10).is_empty());
+}
+
+#[test]
+fn arming_more_timers_than_slots_hands_the_event_back() {
+    let mut timers = TimerTable::<u8, 4>::new();
+    for e in 0..4 {
+        timers.arm(0, 10, None, e).unwrap();
+    }
+    assert_eq!(Err(4), timers.arm(0, 10, None, 4));
+    assert_eq!(Err(5), timers.arm_periodic(0, 10, None, 5));
+    assert!(!timers.cancel(DEAD_TIMER));
+}
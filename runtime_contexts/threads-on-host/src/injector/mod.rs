@@ -0,0 +1,78 @@
+//! Injection of events from outside the state machines
+//!
+//! - an injector is cloneable and can be used from any thread
+//! - it publishes to all subscribed state machines or sends to a single one
+//! - events are injected into the fan-in queue like published events
+//! - publishing is refused if the events of the runtime cannot be copied
+//!
+use std::{sync::Arc, time::Duration};
+
+use qlrl::{MachineId, PublishError};
+
+use super::{queue::EventQueue, ContextEvent};
+
+/// Cloneable handle to inject events into a `ThreadedContext`
+///
+/// Unlike publishing by state machines, injecting ignores the overflow
+/// policy of the event queue: the variants wait for room, refuse at once or
+/// wait for a limited time if the queue is full.
+pub struct EventInjector<E: Send> {
+    queue: Arc<EventQueue<ContextEvent<E>>>,
+    publishes: bool,
+    target: Option<MachineId>,
+}
+
+impl<E: Send> Clone for EventInjector<E> {
+    fn clone(&self) -> Self {
+        EventInjector {
+            queue: self.queue.clone(),
+            publishes: self.publishes,
+            target: self.target,
+        }
+    }
+}
+
+impl<E: Send> EventInjector<E> {
+    pub(crate) fn new(queue: Arc<EventQueue<ContextEvent<E>>>, publishes: bool) -> Self {
+        EventInjector {
+            queue,
+            publishes,
+            target: None,
+        }
+    }
+
+    /// Injector sending to the given state machine only
+    pub fn to(&self, target: MachineId) -> Self {
+        EventInjector {
+            queue: self.queue.clone(),
+            publishes: self.publishes,
+            target: Some(target),
+        }
+    }
+
+    fn envelope(&self, e: E) -> Result<ContextEvent<E>, PublishError> {
+        match self.target {
+            Some(target) => Ok(ContextEvent::Addressed(target, e)),
+            None if self.publishes => Ok(ContextEvent::Envelope(e)),
+            None => Err(PublishError::Unsupported),
+        }
+    }
+
+    /// Inject an event, wait for room if the queue is full
+    pub fn inject(&self, e: E) -> Result<(), PublishError> {
+        self.queue.push_blocking(self.envelope(e)?)
+    }
+
+    /// Inject an event if the queue has room
+    pub fn try_inject(&self, e: E) -> Result<(), PublishError> {
+        self.queue.try_push(self.envelope(e)?)
+    }
+
+    /// Inject an event, wait at most `timeout` for room if the queue is full
+    pub fn inject_timeout(&self, e: E, timeout: Duration) -> Result<(), PublishError> {
+        self.queue.push_timeout(self.envelope(e)?, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,42 @@
+use super::*;
+use crate::OverflowPolicy;
+
+fn injector(capacity: usize) -> (EventInjector<u8>, Arc<EventQueue<ContextEvent<u8>>>) {
+    let queue = Arc::new(EventQueue::new(capacity, OverflowPolicy::DropOldest));
+    (EventInjector::new(queue.clone(), true), queue)
+}
+
+#[test]
+fn inject_publishes_or_sends() {
+    let (injector, queue) = injector(2);
+    injector.inject(1).unwrap();
+    injector.to(MachineId(3)).inject(2).unwrap();
+    assert!(matches!(queue.pop(), Some(ContextEvent::Envelope(1))));
+    assert!(matches!(queue.pop(), Some(ContextEvent::Addressed(MachineId(3), 2))));
+}
+
+#[test]
+fn try_inject_and_inject_timeout_refuse_when_full() {
+    let (injector, _queue) = injector(1);
+    injector.try_inject(1).unwrap();
+    assert_eq!(Err(PublishError::QueueFull), injector.try_inject(2));
+    assert_eq!(Err(PublishError::QueueFull), injector.inject_timeout(2, Duration::from_millis(5)));
+}
+
+#[test]
+fn inject_into_stopped_queue_fails() {
+    let (injector, queue) = injector(1);
+    queue.close();
+    assert_eq!(Err(PublishError::Stopped), injector.clone().inject(1));
+}
+
+#[test]
+fn publishing_is_refused_without_copies() {
+    let queue = Arc::new(EventQueue::new(2, OverflowPolicy::Block));
+    let injector = EventInjector::new(queue.clone(), false);
+    assert_eq!(Err(PublishError::Unsupported), injector.inject(1));
+    assert_eq!(Err(PublishError::Unsupported), injector.try_inject(1));
+    injector.to(MachineId(0)).inject(2).unwrap();
+    assert!(matches!(queue.try_pop(), Some(ContextEvent::Addressed(MachineId(0), 2))));
+    assert!(queue.try_pop().is_none());
+}
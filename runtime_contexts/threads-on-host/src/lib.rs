@@ -1,64 +1,170 @@
 //! State machine execution context
 //!
-//! - uses threads and mpsc to inject events
+//! - uses threads and a bounded event queue to inject events
 //! - each state machine runs in a dedicated thread
+//! - events are either broadcast or sent to a single state machine
+//! - delayed events and timers are posted by a dedicated timer thread
+//! - an `EventInjector` injects events from outside the state machines
+//! - a `ShutdownHandle` stops all threads after draining the events in flight
+//! - alternatively all state machines run on a single executor thread that
+//!   dispatches by priority, see `Scheduling::Priority`
 //!
-use bus::Bus;
-use log::{debug};
+use log::{debug, error, warn};
 use std::{
-    sync::mpsc,
-    thread::{sleep, spawn, JoinHandle},
-    time,
-    fmt::Debug,
-    marker::{Send, Sync},
+    collections::VecDeque,
+    sync::{mpsc, Arc, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::{self, Instant},
+    marker::Send,
 };
-use ctrlc;
 
-use qlrl::{StateMachine, StateMachineContext};
+use qlrl::{MachineId, PublishError, Signal, StateMachine, StateMachineContext, TimerHandle};
 
+mod queue;
+pub use queue::{EventQueue, OverflowPolicy};
+mod timer;
+pub use timer::{TimerScheduler, TimerService};
+mod injector;
+pub use injector::EventInjector;
+mod shutdown;
+use shutdown::{join_all, Drain, InFlight};
+pub use shutdown::{ShutdownError, ShutdownHandle, ThreadPanic, DEFAULT_DRAIN_TIMEOUT};
+mod scheduler;
+use scheduler::{executor, Task};
+pub use scheduler::{ExecutorContext, Scheduling};
+mod registry;
+use registry::{everything, Copier, SubscriberRegistry};
+
+/// Capacity of the event queues used by `ThreadedContext::new`
+pub const DEFAULT_QUEUE_CAPACITY: usize = 100;
+
+/// Subscription filter deciding which broadcast events a state machine receives
+pub type Subscription<E> = fn(&E) -> bool;
 
 #[derive(Clone, Debug)]
-pub enum ContextEvent<E: Clone + Debug + Send + Sync> {
+pub enum ContextEvent<E: Send> {
     Start,
     Stop,
+    /// The state machine finished its start; queued by the state machine
+    /// threads for the dispatcher only
+    Started(MachineId),
+    /// Event for all subscribed state machines
     Envelope(E),
+    /// Event for a single state machine
+    Addressed(MachineId, E),
 }
 
-pub struct WorkerContext<E: Clone + Debug + Send + Sync>(mpsc::SyncSender<ContextEvent<E>>);
+/// Subscribers shared by the dispatcher and the state machine threads
+type SharedRegistry<E> = Arc<Mutex<SubscriberRegistry<E>>>;
 
-impl <E: Clone + Debug + Send + Sync> StateMachineContext<E> for WorkerContext<E> {
+fn lock<E>(registry: &SharedRegistry<E>) -> MutexGuard<'_, SubscriberRegistry<E>> {
+    // a panicking state machine cannot leave the registry inconsistent
+    registry.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub struct WorkerContext<E: Send> {
+    id: MachineId,
+    publishes: bool,
+    queue: Arc<EventQueue<ContextEvent<E>>>,
+    timers: TimerScheduler<ContextEvent<E>>,
+    registry: SharedRegistry<E>,
+}
+
+impl <E: Send> StateMachineContext<E> for WorkerContext<E> {
+    /// Publish an event; events the queue does not accept are logged and dropped
     fn publish_event(&mut self, e: E) {
-        self.0.send(ContextEvent::Envelope(e)).unwrap();  // panic in case of an error
+        match self.try_publish_event(e) {
+            Ok(()) => (),
+            Err(PublishError::Stopped) => debug!("Event dropped: runtime stopped"),
+            Err(reason) => error!("Event dropped: {}", reason),
+        }
     }
 
+    fn try_publish_event(&mut self, e: E) -> Result<(), PublishError> {
+        if !self.publishes {
+            return Err(PublishError::Unsupported);
+        }
+        self.queue.push(ContextEvent::Envelope(e))
+    }
+
+    /// Send an event to a single state machine; failures are logged
+    fn send_to(&mut self, target: MachineId, e: E) {
+        if let Err(reason) = self.queue.push(ContextEvent::Addressed(target, e)) {
+            error!("Event for {:?} dropped: {}", target, reason);
+        }
+    }
+
+    /// Publish an event to all subscribed state machines after a delay
     fn publish_delayed_event(&mut self, delay_in_ms: u64, e: E) {
+        if !self.publishes {
+            error!("Delayed event dropped: {}", PublishError::Unsupported);
+            return;
+        }
+        let millis = time::Duration::from_millis(delay_in_ms);
+        self.timers.schedule(millis, ContextEvent::Envelope(e));
+    }
+
+    /// Send an event to this state machine after a delay; the timer thread
+    /// posts it, so the state machine continues to process events meanwhile
+    fn arm_timer(&mut self, delay_in_ms: u64, e: E) -> TimerHandle {
         let millis = time::Duration::from_millis(delay_in_ms);
-        sleep(millis);
-        self.0.send(ContextEvent::Envelope(e)).unwrap();  // panic in case of an error
+        TimerHandle(self.timers.schedule(millis, ContextEvent::Addressed(self.id, e)))
+    }
+
+    fn arm_periodic_timer(&mut self, period_in_ms: u64, e: E) -> TimerHandle
+    where
+        E: Clone,
+    {
+        let millis = time::Duration::from_millis(period_in_ms);
+        TimerHandle(self.timers.schedule_periodic(millis, ContextEvent::Addressed(self.id, e)))
+    }
+
+    fn cancel_timer(&mut self, handle: TimerHandle) -> bool {
+        self.timers.cancel(handle.0)
+    }
+
+    /// Subscribe to a kind; the dispatcher forwards published events of
+    /// that kind from now on
+    fn subscribe(&mut self, kind: E::Kind)
+    where
+        E: Signal,
+    {
+        lock(&self.registry).subscribe(self.id, kind);
+    }
+
+    fn unsubscribe(&mut self, kind: E::Kind)
+    where
+        E: Signal,
+    {
+        lock(&self.registry).unsubscribe(self.id, kind);
     }
 }
 
-pub fn sm_worker<E: Clone + Debug + Sync + Send >(
+fn sm_worker<E: Send>(
     sm: Box<dyn StateMachine<E>>,
-    tx: mpsc::SyncSender<ContextEvent<E>>,
-    rx: bus::BusReader<ContextEvent<E>>,
+    mut context: WorkerContext<E>,
+    rx: mpsc::Receiver<ContextEvent<E>>,
+    drain: Arc<Drain>,
 ) {
     debug!("Thread: started");
     let mut sm = sm;
-    let mut rx = rx;
-    let mut context = WorkerContext(tx);
     while let Ok(request) = rx.recv() {
         match request {
             ContextEvent::Start => {
                 debug!("Thread: Receives start event");
+                let _processed = InFlight(&drain);
+                let _started = StartAck(context.queue.clone(), context.id); // dropped first, while still in flight
                 sm.start(&mut context);
             }
             ContextEvent::Stop => {
                 debug!("Thread: Receives stop event");
+                sm.stop(&mut context);
                 break;
             }
-            ContextEvent::Envelope(event) => {
-                debug!("Thread: Receives event: {:?}", event);
+            ContextEvent::Started(_) => (), // for the dispatcher only
+            ContextEvent::Envelope(event) | ContextEvent::Addressed(_, event) => {
+                // state machines trace their event processing via `qlrl::observer::LogObserver`
+                let _processed = InFlight(&drain);
                 sm.dispatch(&mut context, event);
             }
         }
@@ -66,17 +172,154 @@ pub fn sm_worker<E: Clone + Debug + Sync + Send >(
     debug!("Finish thread");
 }
 
+/// Tells the dispatcher that a state machine is started when dropped, also
+/// if the state machine panics on start
+struct StartAck<E: Send>(Arc<EventQueue<ContextEvent<E>>>, MachineId);
+
+impl<E: Send> Drop for StartAck<E> {
+    fn drop(&mut self) {
+        // unbounded, a full queue must not block the start
+        let _ = self.0.push_unbounded(ContextEvent::Started(self.1));
+    }
+}
+
+/// Channel of the dispatcher to a state machine thread
+struct Route<E: Send> {
+    tx: mpsc::SyncSender<ContextEvent<E>>,
+}
+
+/// Forward an event to a state machine thread counting it as in flight
+fn forward<E: Send>(route: &Route<E>, drain: &Drain, m: ContextEvent<E>) {
+    drain.forwarded();
+    if route.tx.send(m).is_err() {
+        drain.processed(); // the state machine thread is gone
+    }
+}
+
+/// Forward an event of the fan-in queue to the state machine threads
+fn route<E: Send>(m: ContextEvent<E>, routes: &[Route<E>], registry: &SharedRegistry<E>, drain: &Drain) {
+    match m {
+        ContextEvent::Addressed(MachineId(index), _) if index >= routes.len() => {
+            warn!("Dispatcher: Event for unknown state machine {} dropped", index);
+        }
+        ContextEvent::Addressed(MachineId(index), _) => forward(&routes[index], drain, m),
+        ContextEvent::Envelope(event) => {
+            // release the registry before forwarding blocks on a full
+            // channel; the state machine may be subscribing meanwhile
+            let deliveries = {
+                let registry = lock(registry);
+                let subscribers = registry.subscribers(&event);
+                registry.distribute(subscribers, event)
+            };
+            match deliveries {
+                Ok(deliveries) => {
+                    for (MachineId(index), event) in deliveries {
+                        forward(&routes[index], drain, ContextEvent::Envelope(event));
+                    }
+                }
+                Err(reason) => error!("Dispatcher: Event dropped: {}", reason),
+            }
+        }
+        // start is forwarded on its own and never queued, the dispatcher
+        // takes stop and started
+        ContextEvent::Start | ContextEvent::Stop | ContextEvent::Started(_) => (),
+    }
+}
+
+/// Start the state machines, then forward the events of the fan-in queue
+/// to the state machine threads
+///
+/// Published events are forwarded to the subscribers in the registry. On
+/// stop the events in flight are drained until all state machines are idle
+/// or the drain timeout expires, then the state machines are stopped.
+fn dispatcher<E: Send>(
+    queue: Arc<EventQueue<ContextEvent<E>>>,
+    routes: Vec<Route<E>>,
+    registry: SharedRegistry<E>,
+    drain: Arc<Drain>,
+) {
+    // start before any queued event is forwarded, e.g. an injected one; the
+    // events are held back until all state machines made their subscriptions
+    // on start, but still taken from the queue so the starts can publish
+    for route in &routes {
+        forward(route, &drain, ContextEvent::Start);
+    }
+    let mut starting = routes.len();
+    let mut held = VecDeque::new();
+    let mut deadline = None;
+    loop {
+        let m = match deadline {
+            None => match queue.pop() {
+                Some(ContextEvent::Stop) => {
+                    debug!("Dispatcher: Drain events in flight");
+                    deadline = Some(Instant::now() + drain.timeout());
+                    continue;
+                }
+                Some(m) => m,
+                None => break,
+            },
+            Some(deadline) => match queue.try_pop() {
+                Some(ContextEvent::Stop) => continue, // stopping already
+                Some(m) => m,
+                None if drain.in_flight() == 0 => break,
+                None if Instant::now() >= deadline => {
+                    warn!("Dispatcher: Drain timeout, {} events in flight", drain.in_flight());
+                    break;
+                }
+                None => {
+                    thread::sleep(time::Duration::from_millis(1));
+                    continue;
+                }
+            },
+        };
+        match m {
+            ContextEvent::Started(_) => {
+                starting -= 1;
+                if starting == 0 {
+                    debug!("Dispatcher: All state machines started, {} events held back", held.len());
+                    held.drain(..).for_each(|m| route(m, &routes, &registry, &drain));
+                }
+            }
+            m if starting > 0 => held.push_back(m),
+            m => route(m, &routes, &registry, &drain),
+        }
+    }
+    // refuse further events, i.e. the ones published by exit actions
+    queue.close();
+    for route in &routes {
+        let _ = route.tx.send(ContextEvent::Stop);
+    }
+    debug!("Dispatcher: finished");
+}
+
 /// Threaded Context for state machines
 ///
 /// - Each thread runs a state machine
-/// - Each thread publishes events, all threads receive the events
-///   (multiple producer, multiple consumer)
-///   - mpsc is used for multiple producer single consumer
+/// - Each thread publishes events (multiple producer, multiple consumer)
+///   - a bounded event queue is used for multiple producer single consumer;
+///     its overflow policy decides what happens if producers are too fast
 ///   - a dispatcher is the single consumer
-///   - bus is used for single producer multiple consumer
-///   - the events to be distributed need to implement the Clone trait
-/// - This multiple producer "all" consumer approach is good enough for this example
-///   with limited threads and demonstration purposes only
+///   - the dispatcher forwards published events to all state machines whose
+///     subscription accepts them or that subscribed to their kind, sent
+///     events to the addressed state machine only; every state machine
+///     thread has a channel of its own
+///   - only subscribing by kind requires the events to implement `Signal`
+///   - a published event is moved to its last subscriber and copied for the
+///     others, a targeted event is moved to its state machine; large
+///     payloads are best published as `Arc<T>` events, every subscriber
+///     gets a clone of the `Arc` sharing the payload, which is not copied
+///   - events that do not implement `Clone` are supported via
+///     `with_moved_events`; they can only be sent to single state machines,
+///     publishing them fails with `PublishError::Unsupported`
+/// - A timer thread posts delayed events into the fan-in queue; timer events
+///   are sent to the state machine that armed the timer
+/// - `run` blocks the caller, `spawn` returns a `RuntimeHandle`
+/// - With `Scheduling::Priority` a single executor thread replaces the
+///   dispatcher and the state machine threads; it dispatches the state
+///   machine with the highest priority first, see `set_priority`
+/// - A `ShutdownHandle` or Ctrl-C stops the runtime: the events in flight
+///   are drained, each state machine exits its active state and all
+///   threads are joined
 ///
 /// # Example
 ///
@@ -85,95 +328,352 @@ pub fn sm_worker<E: Clone + Debug + Sync + Send >(
 /// let context = ThreadedContext::<u8>::new();
 ///
 /// // Register the state machine threads
-/// context.add(StateMachine::<u8>::new("Some State machine on u8 events"));
-/// context.add((StateMachine::<u8>::new("Some other State machine on u8 events"))
+/// let id = context.add(StateMachine::<u8>::new("Some State machine on u8 events"));
+/// context.add_subscribed(StateMachine::<u8>::new("Some other State machine on even events"),
+///     |e| e % 2 == 0);
+///
+/// // run the state machines until stopped via Ctrl-C
+/// context.run()?;
 ///
-/// // run the state machines
-/// context.run();
+/// // or run them alongside other subsystems
+/// let runtime = context.spawn();
+/// runtime.inject_to(id, 42)?;
+/// runtime.stop();
+/// runtime.join()?;
+///
+/// // or run them on a single executor thread, the first one preempting
+/// let context = ThreadedContext::<u8>::new().with_scheduling(Scheduling::Priority);
+/// let monitor = context.add(StateMachine::<u8>::new("Safety monitor"));
+/// context.add(StateMachine::<u8>::new("Logger"));
+/// context.set_priority(monitor, 1);
+/// context.run()?;
 ///
 /// ```
 pub struct ThreadedContext<E>
 where
-    E: Debug + Clone + Send + Sync + 'static,
-    mpsc::Receiver<ContextEvent<E>>: Send ,
+    E: Send + 'static,
 {
-    base_tx: mpsc::SyncSender<ContextEvent<E>>,
-    mix_rx: Option<mpsc::Receiver<ContextEvent<E>>>,
-    mix_tx: Option<bus::Bus<ContextEvent<E>>>,
-    threads: Option<Vec<JoinHandle<()>>>,
+    queue: Arc<EventQueue<ContextEvent<E>>>,
+    capacity: usize,
+    scheduling: Scheduling,
+    tasks: Vec<Task<E>>,
+    registry: SubscriberRegistry<E>,
+    timer: TimerService<ContextEvent<E>>,
+    drain: Arc<Drain>,
 }
 
 impl <E> ThreadedContext<E>
 where
-    E: Clone + Debug + Send + Sync + 'static,
-    mpsc::Receiver<ContextEvent<E>>: Send ,
+    E: Clone + Send + 'static,
 {
 
+    /// Create a context with queues of `DEFAULT_QUEUE_CAPACITY` events
+    /// blocking publishers if full
     pub fn new() -> Self {
-        debug!("new: Start state machine runtime context using threads, channels and busses");
-        let (base_tx, mix_rx) = mpsc::sync_channel(100); // set up fan-in
-        let mix_tx = Bus::new(100); // set up fan-out
+        Self::with_queue(DEFAULT_QUEUE_CAPACITY, OverflowPolicy::Block)
+    }
+
+    /// Create a context with queues of `capacity` events applying the
+    /// given overflow policy if the fan-in queue is full
+    pub fn with_queue(capacity: usize, overflow: OverflowPolicy) -> Self {
+        Self::build(capacity, overflow, Some(E::clone))
+    }
+}
+
+impl <E> ThreadedContext<E>
+where
+    E: Send + 'static,
+{
+
+    /// Create a context for events that cannot be copied, with queues of
+    /// `capacity` events applying the given overflow policy
+    ///
+    /// Events can only be sent to single state machines, via `send_to`,
+    /// `inject_to` or timers; publishing them fails with
+    /// `PublishError::Unsupported` and delayed published events are dropped
+    /// with an error. Events that are merely expensive to copy are better
+    /// published as `Arc<T>` with `new`: subscribers share the payload and
+    /// `Arc<T>` is a `Signal` of the payload's kind.
+    pub fn with_moved_events(capacity: usize, overflow: OverflowPolicy) -> Self {
+        Self::build(capacity, overflow, None)
+    }
+
+    fn build(capacity: usize, overflow: OverflowPolicy, copy: Option<Copier<E>>) -> Self {
+        debug!("new: Start state machine runtime context using threads and channels");
+        let queue = Arc::new(EventQueue::new(capacity, overflow)); // set up fan-in
+        let timer = TimerService::new(queue.clone());
 
         ThreadedContext::<E> {
-            base_tx,
-            mix_tx: Some(mix_tx),
-            mix_rx: Some(mix_rx),
-            threads: Some(vec![]),
+            queue,
+            capacity,
+            scheduling: Scheduling::Threads,
+            tasks: vec![],
+            registry: SubscriberRegistry::new(copy),
+            timer,
+            drain: Arc::new(Drain::new()),
         }
     }
 
-    pub fn add(&mut self, state_machine: Box< dyn StateMachine<E> + Send>)
+    /// Run the state machines as given by `scheduling`
+    pub fn with_scheduling(mut self, scheduling: Scheduling) -> Self {
+        self.scheduling = scheduling;
+        self
+    }
+
+    /// Set the priority of a state machine; the default is 0
+    ///
+    /// With `Scheduling::Priority` a state machine with a higher priority is
+    /// dispatched first, otherwise the priority is ignored. Panics if the
+    /// state machine is unknown.
+    pub fn set_priority(&mut self, id: MachineId, priority: u8) {
+        self.tasks[id.0].priority = priority;
+    }
+
+    /// Handle to stop the runtime from code, e.g. from another thread
+    pub fn shutdown_handle(&self) -> ShutdownHandle<E> {
+        ShutdownHandle::new(self.queue.clone(), self.drain.clone())
+    }
+
+    /// Handle to inject events from outside, e.g. from another thread
+    ///
+    /// Events injected before the runtime is started are processed after start.
+    pub fn injector(&self) -> EventInjector<E> {
+        EventInjector::new(self.queue.clone(), self.registry.publishes())
+    }
+
+    /// Add a state machine receiving all published events
+    ///
+    /// Returns the id to send events to the state machine.
+    pub fn add(&mut self, state_machine: Box< dyn StateMachine<E> + Send>) -> MachineId
     {
-        let tx = self.base_tx.clone(); // clone fan in for move to thread
-        if let Some(thread) = &mut self.threads {
-        if let Some(mix_tx) = &mut self.mix_tx {
-            let rx = mix_tx.add_rx(); // register fan out for move to thread
-            thread.push(spawn(move || {
-                sm_worker(state_machine, tx, rx);
-            }));
-            debug!("add: State machine thread spawned");
-        }}
-    }
-
-    pub fn run(&mut self) {
-        debug!("run: function invoked");
-        // start dispatcher
-
-         // cannot inject mix_rx and mix_tx directly
-         // require 'static lifetime so we have to move
-        if let Some(mix_rx) = self.mix_rx.take() {
-        if let Some(mut mix_tx) = self.mix_tx.take() {
-            let _dispatcher = spawn(move || {
-                for m in mix_rx.iter() {
-                    mix_tx.broadcast(m);
-                }
-            });
+        self.add_route(state_machine, Some(everything))
+    }
+
+    /// Add a state machine receiving the published events its subscription
+    /// accepts; events sent to the state machine are always received
+    ///
+    /// Returns the id to send events to the state machine.
+    pub fn add_subscribed(
+        &mut self,
+        state_machine: Box< dyn StateMachine<E> + Send>,
+        subscription: Subscription<E>,
+    ) -> MachineId
+    {
+        self.add_route(state_machine, Some(subscription))
+    }
 
-            debug!("run: Message dispatcher thread started");
+    /// Add a state machine receiving the published events of the given kinds
+    ///
+    /// The state machine can subscribe to further kinds or unsubscribe via
+    /// its context; events sent to it are always received. Returns the id to
+    /// send events to the state machine.
+    pub fn add_subscribed_to(
+        &mut self,
+        state_machine: Box<dyn StateMachine<E> + Send>,
+        kinds: &[E::Kind],
+    ) -> MachineId
+    where
+        E: Signal,
+    {
+        let id = self.add_route(state_machine, None);
+        for kind in kinds {
+            self.registry.subscribe(id, *kind);
+        }
+        id
+    }
 
-            // Start all state machines
-            self.base_tx.send(ContextEvent::Start).expect("Could not send start event");
+    fn add_route(
+        &mut self,
+        state_machine: Box< dyn StateMachine<E> + Send>,
+        subscription: Option<Subscription<E>>,
+    ) -> MachineId
+    {
+        let id = self.registry.register(subscription);
+        self.tasks.push(Task {
+            state_machine,
+            priority: 0,
+        });
+        id
+    }
 
-            // // Stop all state machines
-            let tx = self.base_tx.clone();
-            ctrlc::set_handler(
-                move || tx.send(ContextEvent::Stop).expect("Could not send stop signal"))
-            .expect("Error setting Ctrl-C...");
+    /// Spawn a thread per state machine and the dispatcher thread
+    fn spawn_threads(&mut self) -> Vec<JoinHandle<()>> {
+        let publishes = self.registry.publishes();
+        let registry = Arc::new(Mutex::new(core::mem::replace(&mut self.registry, SubscriberRegistry::new(None))));
+        let mut threads = vec![];
+        let mut routes = vec![];
+        for (index, task) in core::mem::take(&mut self.tasks).into_iter().enumerate() {
+            let context = WorkerContext {
+                id: MachineId(index),
+                publishes,
+                queue: self.queue.clone(), // clone fan in for move to thread
+                timers: self.timer.scheduler(),
+                registry: registry.clone(),
+            };
+            let drain = self.drain.clone();
+            let (tx, rx) = mpsc::sync_channel(self.capacity); // set up fan out
+            routes.push(Route { tx });
+            let id = context.id;
+            let state_machine = task.state_machine;
+            threads.push(
+                thread::Builder::new()
+                    .name(format!("sm-{}", id.0))
+                    .spawn(move || sm_worker(state_machine, context, rx, drain))
+                    .expect("Could not spawn state machine thread"),
+            );
+            debug!("spawn: State machine thread {:?} spawned", id);
+        }
 
-            // Note: state machines will probably never be stopped
-            // are supposed to run forever; panic handling is not implemented on purpose
-            if let Some(threads) = self.threads.take() {
-                for handle in threads {
-                    handle.join().expect("Could not stop thread");
-                }
-            }
+        // the routes require 'static lifetime so we have to move
+        let queue = self.queue.clone();
+        let drain = self.drain.clone();
+        threads.push(
+            thread::Builder::new()
+                .name("dispatcher".into())
+                .spawn(move || dispatcher(queue, routes, registry, drain))
+                .expect("Could not spawn dispatcher thread"),
+        );
+        debug!("spawn: Message dispatcher thread started");
+        threads
+    }
 
-            // Ideally the dispatcher thread needs to be shutdown as well.
-            // This would require some extra handling in the dispatcher thread
-            // However it will be closed after leaving this scope and the process anyway.
-            // Thus, it is not done on purpose
-            // _dispatcher.join().unwrap();
+    /// Spawn the executor thread running all state machines
+    fn spawn_executor(&mut self) -> Vec<JoinHandle<()>> {
+        let tasks = core::mem::take(&mut self.tasks);
+        let registry = core::mem::replace(&mut self.registry, SubscriberRegistry::new(None));
+        let queue = self.queue.clone();
+        let timers = self.timer.scheduler();
+        let capacity = self.capacity;
+        let drain = self.drain.clone();
+        let executor = thread::Builder::new()
+            .name("executor".into())
+            .spawn(move || executor(queue, timers, tasks, registry, capacity, drain))
+            .expect("Could not spawn executor thread");
+        debug!("spawn: Executor thread started");
+        vec![executor]
+    }
+
+    /// Start the state machines without blocking the caller
+    ///
+    /// The returned handle injects events and stops and joins the runtime.
+    /// Ctrl-C is not handled unless `RuntimeHandle::stop_on_ctrl_c` is called.
+    pub fn spawn(mut self) -> RuntimeHandle<E> {
+        debug!("spawn: function invoked");
+        let shutdown = self.shutdown_handle();
+        let publishes = self.registry.publishes();
+        let threads = match self.scheduling {
+            Scheduling::Threads => self.spawn_threads(),
+            Scheduling::Priority => self.spawn_executor(),
+        };
+
+        RuntimeHandle {
+            queue: self.queue,
+            publishes,
+            shutdown,
+            threads,
+            timer: self.timer,
+        }
+    }
+
+    /// Run the state machines until the runtime is stopped via a
+    /// `ShutdownHandle` or Ctrl-C
+    ///
+    /// Returns the threads that panicked, if any.
+    pub fn run(self) -> Result<(), ShutdownError> {
+        let runtime = self.spawn();
+        if let Err(reason) = runtime.stop_on_ctrl_c() {
+            warn!("run: Ctrl-C handler not set: {}", reason);
         }
-    }}
+        runtime.join()
+    }
+}
+
+impl <E> Default for ThreadedContext<E>
+where
+    E: Clone + Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Handle of a spawned `ThreadedContext`
+///
+/// Dropping the handle stops the runtime and joins its threads.
+pub struct RuntimeHandle<E>
+where
+    E: Send + 'static,
+{
+    queue: Arc<EventQueue<ContextEvent<E>>>,
+    publishes: bool,
+    shutdown: ShutdownHandle<E>,
+    threads: Vec<JoinHandle<()>>,
+    timer: TimerService<ContextEvent<E>>,
+}
+
+impl <E> RuntimeHandle<E>
+where
+    E: Send + 'static,
+{
+    /// Publish an event to all subscribed state machines from outside, wait
+    /// for room if the event queue is full
+    pub fn inject(&self, e: E) -> Result<(), PublishError> {
+        self.injector().inject(e)
+    }
+
+    /// Send an event to a single state machine from outside, wait for room
+    /// if the event queue is full
+    pub fn inject_to(&self, target: MachineId, e: E) -> Result<(), PublishError> {
+        self.injector().to(target).inject(e)
+    }
+
+    /// Handle to inject events, e.g. from another thread
+    pub fn injector(&self) -> EventInjector<E> {
+        EventInjector::new(self.queue.clone(), self.publishes)
+    }
+
+    /// Handle to stop the runtime, e.g. from another thread
+    pub fn shutdown_handle(&self) -> ShutdownHandle<E> {
+        self.shutdown.clone()
+    }
+
+    /// Stop the runtime; see `ShutdownHandle::stop`
+    pub fn stop(&self) {
+        self.shutdown.stop();
+    }
+
+    /// Stop the runtime on Ctrl-C
+    ///
+    /// The handler can be set once per process only.
+    pub fn stop_on_ctrl_c(&self) -> Result<(), ctrlc::Error> {
+        let shutdown = self.shutdown.clone();
+        ctrlc::set_handler(move || shutdown.stop())
+    }
+
+    /// Wait until the runtime is stopped and all threads are finished
+    ///
+    /// Returns the threads that panicked, if any.
+    pub fn join(mut self) -> Result<(), ShutdownError> {
+        let result = join_all(core::mem::take(&mut self.threads));
+        self.timer.stop();
+        debug!("join: all threads joined");
+        result
+    }
+}
+
+impl <E> Drop for RuntimeHandle<E>
+where
+    E: Send + 'static,
+{
+    fn drop(&mut self) {
+        if !self.threads.is_empty() {
+            self.stop();
+            if let Err(reason) = join_all(core::mem::take(&mut self.threads)) {
+                error!("Runtime stopped: {}", reason);
+            }
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,191 @@
+//! Bounded multiple producer event queue
+//!
+//! Replacement for `mpsc::sync_channel` that applies a configurable policy
+//! when the queue is full and can be closed to refuse further events.
+//!
+use log::{error, warn};
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use qlrl::PublishError;
+
+/// What to do with an event published into a full queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until the queue has room again
+    Block,
+    /// Discard the oldest queued event to make room for the new one; control
+    /// items like stop are never discarded
+    DropOldest,
+    /// Discard the new event; `try_publish_event` reports
+    /// `PublishError::QueueFull` for the discarded event
+    DropNewest,
+    /// Refuse the new event; `try_publish_event` reports `PublishError::QueueFull`
+    Error,
+}
+
+struct QueueState<T> {
+    items: VecDeque<(T, bool)>, // with whether the item is a control item
+    closed: bool,
+}
+
+pub struct EventQueue<T> {
+    state: Mutex<QueueState<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    policy: OverflowPolicy,
+}
+
+impl<T> EventQueue<T> {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        assert!(capacity > 0, "Queue capacity must not be zero");
+        EventQueue {
+            state: Mutex::new(QueueState {
+                items: VecDeque::with_capacity(capacity),
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity,
+            policy,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState<T>> {
+        // a panicking producer cannot leave the queue inconsistent
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Queue an item applying the overflow policy if the queue is full
+    pub fn push(&self, item: T) -> Result<(), PublishError> {
+        let mut state = self.lock();
+        if state.closed {
+            return Err(PublishError::Stopped);
+        }
+        if state.items.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::Block => {
+                    while state.items.len() >= self.capacity && !state.closed {
+                        state = self.not_full.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+                    }
+                    if state.closed {
+                        return Err(PublishError::Stopped);
+                    }
+                }
+                OverflowPolicy::DropOldest => {
+                    if let Some(oldest) = state.items.iter().position(|(_, control)| !control) {
+                        warn!("Event queue full: drop oldest event");
+                        state.items.remove(oldest);
+                    }
+                }
+                OverflowPolicy::DropNewest => {
+                    warn!("Event queue full: drop newest event");
+                    return Err(PublishError::QueueFull);
+                }
+                OverflowPolicy::Error => {
+                    error!("Event queue full: refuse event");
+                    return Err(PublishError::QueueFull);
+                }
+            }
+        }
+        state.items.push_back((item, false));
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Queue an item waiting for room until `deadline` (forever if `None`)
+    /// regardless of the overflow policy
+    fn push_until(&self, item: T, deadline: Option<Instant>) -> Result<(), PublishError> {
+        let mut state = self.lock();
+        while state.items.len() >= self.capacity && !state.closed {
+            state = match deadline {
+                None => self.not_full.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(PublishError::QueueFull);
+                    }
+                    self.not_full
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .0
+                }
+            };
+        }
+        if state.closed {
+            return Err(PublishError::Stopped);
+        }
+        state.items.push_back((item, false));
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Queue an item, wait for room if the queue is full
+    pub fn push_blocking(&self, item: T) -> Result<(), PublishError> {
+        self.push_until(item, None)
+    }
+
+    /// Queue an item if the queue has room, refuse it otherwise
+    pub fn try_push(&self, item: T) -> Result<(), PublishError> {
+        self.push_until(item, Some(Instant::now()))
+    }
+
+    /// Queue an item, wait at most `timeout` for room if the queue is full
+    pub fn push_timeout(&self, item: T, timeout: Duration) -> Result<(), PublishError> {
+        self.push_until(item, Instant::now().checked_add(timeout))
+    }
+
+    /// Queue an item regardless of capacity and policy
+    ///
+    /// Used for runtime control items like start and stop which must not get
+    /// lost; `OverflowPolicy::DropOldest` does not discard them either.
+    pub fn push_unbounded(&self, item: T) -> Result<(), PublishError> {
+        let mut state = self.lock();
+        if state.closed {
+            return Err(PublishError::Stopped);
+        }
+        state.items.push_back((item, true));
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Take the oldest item, wait if the queue is empty
+    ///
+    /// Returns `None` once the queue is closed and empty.
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self.lock();
+        loop {
+            if let Some((item, _)) = state.items.pop_front() {
+                self.not_full.notify_one();
+                return Some(item);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.not_empty.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    /// Take the oldest item without waiting
+    pub fn try_pop(&self) -> Option<T> {
+        let item = self.lock().items.pop_front().map(|(item, _)| item);
+        if item.is_some() {
+            self.not_full.notify_one();
+        }
+        item
+    }
+
+    /// Refuse further items; queued items can still be taken
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests;
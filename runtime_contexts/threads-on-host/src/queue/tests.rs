@@ -0,0 +1,149 @@
+use super::*;
+use std::{sync::Arc, thread, time::Duration};
+
+fn full_queue(policy: OverflowPolicy) -> EventQueue<u8> {
+    let queue = EventQueue::new(2, policy);
+    queue.push(1).unwrap();
+    queue.push(2).unwrap();
+    queue
+}
+
+#[test]
+fn pop_in_fifo_order() {
+    let queue = full_queue(OverflowPolicy::Error);
+    assert_eq!(Some(1), queue.pop());
+    assert_eq!(Some(2), queue.pop());
+}
+
+#[test]
+fn drop_oldest_when_full() {
+    let queue = full_queue(OverflowPolicy::DropOldest);
+    assert_eq!(Ok(()), queue.push(3));
+    assert_eq!(Some(2), queue.pop());
+    assert_eq!(Some(3), queue.pop());
+}
+
+#[test]
+fn drop_oldest_keeps_control_items() {
+    let queue = EventQueue::new(2, OverflowPolicy::DropOldest);
+    queue.push(1).unwrap();
+    queue.push_unbounded(0).unwrap();
+    queue.push(2).unwrap();
+    assert_eq!(Ok(()), queue.push(3));
+    assert_eq!(Ok(()), queue.push(4));
+    assert_eq!(Some(0), queue.pop());
+    assert_eq!(Some(4), queue.pop());
+    assert_eq!(None, queue.try_pop());
+}
+
+#[test]
+fn drop_newest_when_full() {
+    let queue = full_queue(OverflowPolicy::DropNewest);
+    assert_eq!(Err(PublishError::QueueFull), queue.push(3));
+    assert_eq!(Some(1), queue.pop());
+    assert_eq!(Some(2), queue.pop());
+    queue.close();
+    assert_eq!(None, queue.pop());
+}
+
+#[test]
+fn error_when_full() {
+    let queue = full_queue(OverflowPolicy::Error);
+    assert_eq!(Err(PublishError::QueueFull), queue.push(3));
+}
+
+#[test]
+fn block_until_room() {
+    let queue = Arc::new(full_queue(OverflowPolicy::Block));
+    let producer = {
+        let queue = queue.clone();
+        thread::spawn(move || queue.push(3))
+    };
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(Some(1), queue.pop());
+    assert_eq!(Ok(()), producer.join().unwrap());
+    assert_eq!(Some(2), queue.pop());
+    assert_eq!(Some(3), queue.pop());
+}
+
+#[test]
+fn push_unbounded_ignores_capacity() {
+    let queue = full_queue(OverflowPolicy::Error);
+    assert_eq!(Ok(()), queue.push_unbounded(3));
+    assert_eq!(Some(1), queue.pop());
+}
+
+#[test]
+fn closed_queue_refuses_and_drains() {
+    let queue = full_queue(OverflowPolicy::Block);
+    queue.close();
+    assert_eq!(Err(PublishError::Stopped), queue.push(3));
+    assert_eq!(Err(PublishError::Stopped), queue.push_unbounded(3));
+    assert_eq!(Some(1), queue.pop());
+    assert_eq!(Some(2), queue.pop());
+    assert_eq!(None, queue.pop());
+}
+
+#[test]
+fn close_releases_blocked_producer() {
+    let queue = Arc::new(full_queue(OverflowPolicy::Block));
+    let producer = {
+        let queue = queue.clone();
+        thread::spawn(move || queue.push(3))
+    };
+    thread::sleep(Duration::from_millis(20));
+    queue.close();
+    assert_eq!(Err(PublishError::Stopped), producer.join().unwrap());
+}
+
+#[test]
+fn try_pop_does_not_wait() {
+    let queue = EventQueue::new(2, OverflowPolicy::Error);
+    assert_eq!(None, queue.try_pop());
+    queue.push(1).unwrap();
+    assert_eq!(Some(1), queue.try_pop());
+}
+
+#[test]
+fn try_push_refuses_when_full_regardless_of_policy() {
+    let queue = full_queue(OverflowPolicy::DropOldest);
+    assert_eq!(Err(PublishError::QueueFull), queue.try_push(3));
+    assert_eq!(Some(1), queue.pop());
+    assert_eq!(Ok(()), queue.try_push(3));
+}
+
+#[test]
+fn push_timeout_expires_when_full() {
+    let queue = full_queue(OverflowPolicy::DropNewest);
+    let start = std::time::Instant::now();
+    assert_eq!(Err(PublishError::QueueFull), queue.push_timeout(3, Duration::from_millis(20)));
+    assert!(start.elapsed() >= Duration::from_millis(20));
+}
+
+#[test]
+fn push_timeout_succeeds_once_room() {
+    let queue = Arc::new(full_queue(OverflowPolicy::Error));
+    let consumer = {
+        let queue = queue.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            queue.pop()
+        })
+    };
+    assert_eq!(Ok(()), queue.push_timeout(3, Duration::from_secs(5)));
+    assert_eq!(Some(1), consumer.join().unwrap());
+}
+
+#[test]
+fn push_blocking_waits_regardless_of_policy() {
+    let queue = Arc::new(full_queue(OverflowPolicy::Error));
+    let producer = {
+        let queue = queue.clone();
+        thread::spawn(move || queue.push_blocking(3))
+    };
+    thread::sleep(Duration::from_millis(10));
+    assert_eq!(Some(1), queue.pop());
+    assert_eq!(Ok(()), producer.join().unwrap());
+    queue.close();
+    assert_eq!(Err(PublishError::Stopped), queue.push_blocking(4));
+}
@@ -0,0 +1,129 @@
+//! Subscriber registry deciding which state machines receive a published event
+//!
+//! - a table lists the subscribers of every event kind (`Signal`)
+//! - state machines subscribe when they are added or at run time via their
+//!   context
+//! - alternatively a subscription filter accepts events regardless of their
+//!   kind, e.g. all events
+//! - only subscribing by kind requires events to implement `Signal`; the
+//!   table is created by the first subscription
+//! - a published event is moved to its last subscriber, the others receive
+//!   copies; events that cannot be copied are not published at all
+//!
+use core::any::Any;
+
+use qlrl::{MachineId, PublishError, Signal};
+
+use super::Subscription;
+
+/// Filter of state machines receiving all published events
+pub(crate) fn everything<E>(_event: &E) -> bool {
+    true
+}
+
+/// How a published event is copied for further subscribers
+pub(crate) type Copier<E> = fn(&E) -> E;
+
+/// Subscribers by kind
+type KindTable<K> = Vec<(K, Vec<MachineId>)>;
+
+/// Kind table of an event type only known to be a `Signal` when subscribing
+struct Kinds<E> {
+    table: Box<dyn Any + Send>, // KindTable<E::Kind>
+    subscribed: fn(&(dyn Any + Send), MachineId, &E) -> bool,
+}
+
+/// Whether a state machine subscribed to the kind of an event
+fn subscribed<E: Signal>(table: &(dyn Any + Send), id: MachineId, e: &E) -> bool {
+    let kind = e.signal();
+    table
+        .downcast_ref::<KindTable<E::Kind>>()
+        .is_some_and(|table| table.iter().any(|(k, subscribers)| *k == kind && subscribers.contains(&id)))
+}
+
+/// Subscribers of the published events of a runtime
+pub(crate) struct SubscriberRegistry<E> {
+    filters: Vec<Option<Subscription<E>>>, // by state machine
+    kinds: Option<Kinds<E>>,               // None: nobody subscribed to a kind
+    copy: Option<Copier<E>>,               // None: events cannot be copied
+}
+
+impl<E: Signal> SubscriberRegistry<E> {
+    /// Kind table, created on first use
+    fn table(&mut self) -> &mut KindTable<E::Kind> {
+        let kinds = self.kinds.get_or_insert_with(|| Kinds {
+            table: Box::new(KindTable::<E::Kind>::new()),
+            subscribed: subscribed::<E>,
+        });
+        kinds.table.downcast_mut().expect("kind table of the event type")
+    }
+
+    /// Subscribe a state machine to a kind; subscribing twice does nothing
+    pub(crate) fn subscribe(&mut self, id: MachineId, kind: E::Kind) {
+        let table = self.table();
+        match table.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, subscribers)) if subscribers.contains(&id) => (),
+            Some((_, subscribers)) => subscribers.push(id),
+            None => table.push((kind, vec![id])),
+        }
+    }
+
+    /// Unsubscribe a state machine from a kind
+    pub(crate) fn unsubscribe(&mut self, id: MachineId, kind: E::Kind) {
+        if let Some((_, subscribers)) = self.table().iter_mut().find(|(k, _)| *k == kind) {
+            subscribers.retain(|subscriber| *subscriber != id);
+        }
+    }
+}
+
+impl<E> SubscriberRegistry<E> {
+    pub(crate) fn new(copy: Option<Copier<E>>) -> Self {
+        SubscriberRegistry {
+            filters: vec![],
+            kinds: None,
+            copy,
+        }
+    }
+
+    /// Register a state machine receiving the events accepted by `filter`
+    /// and the events of the kinds it subscribes to
+    pub(crate) fn register(&mut self, filter: Option<Subscription<E>>) -> MachineId {
+        self.filters.push(filter);
+        MachineId(self.filters.len() - 1)
+    }
+
+    /// Whether a state machine receives a published event
+    pub(crate) fn accepts(&self, id: MachineId, e: &E) -> bool {
+        let filtered = self.filters.get(id.0).copied().flatten().is_some_and(|accepts| accepts(e));
+        filtered || self.kinds.as_ref().is_some_and(|kinds| (kinds.subscribed)(kinds.table.as_ref(), id, e))
+    }
+
+    /// State machines receiving a published event, in the order they were added
+    pub(crate) fn subscribers(&self, e: &E) -> Vec<MachineId> {
+        (0..self.filters.len()).map(MachineId).filter(|id| self.accepts(*id, e)).collect()
+    }
+
+    /// Whether events are published; events that cannot be copied are sent
+    /// to single state machines only
+    pub(crate) fn publishes(&self) -> bool {
+        self.copy.is_some()
+    }
+
+    /// Hand a published event to its subscribers: copies to all but the
+    /// last one, which receives the event itself
+    ///
+    /// An event that cannot be copied is refused as a whole, regardless of
+    /// its subscribers.
+    pub(crate) fn distribute(&self, subscribers: Vec<MachineId>, e: E) -> Result<Vec<(MachineId, E)>, PublishError> {
+        let copy = self.copy.ok_or(PublishError::Unsupported)?;
+        let Some((&last, others)) = subscribers.split_last() else {
+            return Ok(vec![]);
+        };
+        let mut events: Vec<_> = others.iter().map(|id| (*id, copy(&e))).collect();
+        events.push((last, e));
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests;
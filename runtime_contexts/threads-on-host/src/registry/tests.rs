@@ -0,0 +1,51 @@
+use super::*;
+
+fn receivers(registry: &SubscriberRegistry<u8>, e: u8) -> Vec<usize> {
+    (0..3).filter(|&index| registry.accepts(MachineId(index), &e)).collect()
+}
+
+#[test]
+fn published_events_reach_filters_and_kind_subscribers() {
+    let mut registry = SubscriberRegistry::new(Some(u8::clone));
+    registry.register(Some(everything));
+    registry.register(Some(|e: &u8| e.is_multiple_of(2)));
+    let by_kind = registry.register(None);
+    registry.subscribe(by_kind, 3);
+    registry.subscribe(by_kind, 4);
+    assert_eq!(vec![0, 2], receivers(&registry, 3));
+    assert_eq!(vec![0, 1, 2], receivers(&registry, 4));
+    assert_eq!(vec![0], receivers(&registry, 5));
+}
+
+#[test]
+fn unsubscribed_kinds_are_not_received() {
+    let mut registry = SubscriberRegistry::new(Some(u8::clone));
+    let id = registry.register(None);
+    registry.subscribe(id, 3);
+    registry.subscribe(id, 3);
+    registry.unsubscribe(id, 3);
+    registry.unsubscribe(id, 4); // never subscribed
+    assert!(receivers(&registry, 3).is_empty());
+}
+
+#[test]
+fn published_events_are_copied_for_all_but_the_last_subscriber() {
+    let mut registry = SubscriberRegistry::new(Some(|e: &u8| e + 10));
+    registry.register(Some(everything));
+    registry.register(None);
+    registry.register(Some(everything));
+    let subscribers = registry.subscribers(&3);
+    assert_eq!(Ok(vec![(MachineId(0), 13), (MachineId(2), 3)]), registry.distribute(subscribers, 3));
+    assert_eq!(Ok(vec![]), registry.distribute(vec![], 3));
+}
+
+#[test]
+fn events_without_copier_are_not_published() {
+    let mut registry = SubscriberRegistry::<u8>::new(None);
+    registry.register(Some(everything));
+    registry.register(Some(everything));
+    assert!(!registry.publishes());
+    let subscribers = registry.subscribers(&3);
+    assert_eq!(Err(PublishError::Unsupported), registry.distribute(subscribers, 3));
+    assert_eq!(Err(PublishError::Unsupported), registry.distribute(vec![MachineId(0)], 3));
+}
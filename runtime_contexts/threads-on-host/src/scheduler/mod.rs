@@ -0,0 +1,267 @@
+//! Priority scheduling of the state machines on a single executor thread
+//!
+//! - every state machine has a priority and a bounded ready queue of its own
+//! - the executor dispatches one event at a time (run to completion), always
+//!   of the highest priority state machine with ready events
+//! - between two steps the executor yields to the fan-in queue, so a higher
+//!   priority state machine preempts lower ones at the next step boundary
+//! - events published by the state machines go to the ready queues directly
+//!   and are due before anything injected later
+//!
+use log::{debug, error, warn};
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{self, Instant},
+};
+
+use qlrl::{MachineId, PublishError, Signal, StateMachine, StateMachineContext, TimerHandle};
+
+use super::{queue::EventQueue, registry::SubscriberRegistry, shutdown::Drain, timer::TimerScheduler, ContextEvent};
+
+/// How a `ThreadedContext` runs its state machines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scheduling {
+    /// Every state machine runs in a thread of its own; the operating system
+    /// decides the interleaving, priorities are ignored
+    #[default]
+    Threads,
+    /// All state machines run on a single executor thread; the state machine
+    /// with the highest priority and ready events is dispatched first
+    Priority,
+}
+
+/// State machine registered with a `ThreadedContext`
+pub(crate) struct Task<E> {
+    pub(crate) state_machine: Box<dyn StateMachine<E> + Send>,
+    pub(crate) priority: u8,
+}
+
+/// Ready queues of the state machines of an executor
+struct Ready<E> {
+    queues: Vec<VecDeque<E>>,
+    registry: SubscriberRegistry<E>,
+    priorities: Vec<u8>,
+    capacity: usize,
+    stopped: bool,
+}
+
+impl<E> Ready<E> {
+    /// Queue an event for all subscribed state machines
+    ///
+    /// The event is refused as a whole if one of the ready queues is full or
+    /// if it cannot be copied.
+    fn publish(&mut self, e: E) -> Result<(), PublishError> {
+        if self.stopped {
+            return Err(PublishError::Stopped);
+        }
+        let subscribers = self.registry.subscribers(&e);
+        if subscribers.iter().any(|id| self.queues[id.0].len() >= self.capacity) {
+            return Err(PublishError::QueueFull);
+        }
+        for (id, e) in self.registry.distribute(subscribers, e)? {
+            self.queues[id.0].push_back(e);
+        }
+        Ok(())
+    }
+
+    /// Queue an event for a single state machine, regardless of its subscription
+    fn send_to(&mut self, target: MachineId, e: E) -> Result<(), PublishError> {
+        if self.stopped {
+            return Err(PublishError::Stopped);
+        }
+        match self.queues.get_mut(target.0) {
+            Some(queue) if queue.len() >= self.capacity => Err(PublishError::QueueFull),
+            Some(queue) => {
+                queue.push_back(e);
+                Ok(())
+            }
+            None => {
+                warn!("Executor: Event for unknown state machine {} dropped", target.0);
+                Ok(())
+            }
+        }
+    }
+
+    /// State machine to dispatch next: the highest priority with ready
+    /// events, the first added one among equal priorities
+    fn next_ready(&self) -> Option<usize> {
+        (0..self.queues.len())
+            .filter(|&index| !self.queues[index].is_empty())
+            .min_by_key(|&index| (std::cmp::Reverse(self.priorities[index]), index))
+    }
+
+    fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+}
+
+/// Runtime context handed to a state machine by the executor
+pub struct ExecutorContext<'r, E: Send> {
+    id: MachineId,
+    ready: &'r mut Ready<E>,
+    timers: &'r TimerScheduler<ContextEvent<E>>,
+}
+
+impl<E: Send> StateMachineContext<E> for ExecutorContext<'_, E> {
+    /// Publish an event; events the ready queues do not accept are logged
+    /// and dropped
+    fn publish_event(&mut self, e: E) {
+        match self.try_publish_event(e) {
+            Ok(()) => (),
+            Err(PublishError::Stopped) => debug!("Event dropped: runtime stopped"),
+            Err(reason) => error!("Event dropped: {}", reason),
+        }
+    }
+
+    fn try_publish_event(&mut self, e: E) -> Result<(), PublishError> {
+        self.ready.publish(e)
+    }
+
+    /// Send an event to a single state machine; failures are logged
+    fn send_to(&mut self, target: MachineId, e: E) {
+        if let Err(reason) = self.ready.send_to(target, e) {
+            error!("Event for {:?} dropped: {}", target, reason);
+        }
+    }
+
+    /// Publish an event to all subscribed state machines after a delay
+    fn publish_delayed_event(&mut self, delay_in_ms: u64, e: E) {
+        if !self.ready.registry.publishes() {
+            error!("Delayed event dropped: {}", PublishError::Unsupported);
+            return;
+        }
+        let millis = time::Duration::from_millis(delay_in_ms);
+        self.timers.schedule(millis, ContextEvent::Envelope(e));
+    }
+
+    /// Send an event to this state machine after a delay; the timer thread
+    /// posts it into the fan-in queue
+    fn arm_timer(&mut self, delay_in_ms: u64, e: E) -> TimerHandle {
+        let millis = time::Duration::from_millis(delay_in_ms);
+        TimerHandle(self.timers.schedule(millis, ContextEvent::Addressed(self.id, e)))
+    }
+
+    fn arm_periodic_timer(&mut self, period_in_ms: u64, e: E) -> TimerHandle
+    where
+        E: Clone,
+    {
+        let millis = time::Duration::from_millis(period_in_ms);
+        TimerHandle(self.timers.schedule_periodic(millis, ContextEvent::Addressed(self.id, e)))
+    }
+
+    fn cancel_timer(&mut self, handle: TimerHandle) -> bool {
+        self.timers.cancel(handle.0)
+    }
+
+    /// Subscribe to a kind; published events of that kind are queued from
+    /// now on
+    fn subscribe(&mut self, kind: E::Kind)
+    where
+        E: Signal,
+    {
+        self.ready.registry.subscribe(self.id, kind);
+    }
+
+    fn unsubscribe(&mut self, kind: E::Kind)
+    where
+        E: Signal,
+    {
+        self.ready.registry.unsubscribe(self.id, kind);
+    }
+}
+
+/// Run all state machines on the calling thread, highest priority first
+///
+/// Each ready queue holds up to `capacity` events. On stop the ready events
+/// are processed until all queues are empty or the drain timeout expires,
+/// then the state machines are stopped, highest priority first.
+pub(crate) fn executor<E: Send>(
+    queue: Arc<EventQueue<ContextEvent<E>>>,
+    timers: TimerScheduler<ContextEvent<E>>,
+    tasks: Vec<Task<E>>,
+    registry: SubscriberRegistry<E>,
+    capacity: usize,
+    drain: Arc<Drain>,
+) {
+    let mut ready = Ready {
+        queues: tasks.iter().map(|_| VecDeque::new()).collect(),
+        registry,
+        priorities: tasks.iter().map(|task| task.priority).collect(),
+        capacity,
+        stopped: false,
+    };
+    let mut machines: Vec<_> = tasks.into_iter().map(|task| task.state_machine).collect();
+    let mut order: Vec<usize> = (0..machines.len()).collect();
+    order.sort_by_key(|&index| std::cmp::Reverse(ready.priorities[index]));
+
+    for &index in &order {
+        let mut context = ExecutorContext {
+            id: MachineId(index),
+            ready: &mut ready,
+            timers: &timers,
+        };
+        machines[index].start(&mut context);
+    }
+    let mut deadline = None;
+    loop {
+        // yield: take what arrived during the last step before the next one
+        while let Some(m) = queue.try_pop() {
+            take(&mut ready, m, &mut deadline, &drain);
+        }
+        match ready.next_ready() {
+            Some(index) => {
+                if let Some(event) = ready.queues[index].pop_front() {
+                    let mut context = ExecutorContext {
+                        id: MachineId(index),
+                        ready: &mut ready,
+                        timers: &timers,
+                    };
+                    machines[index].dispatch(&mut context, event);
+                }
+            }
+            None if deadline.is_some() => break, // drained
+            None => match queue.pop() {
+                Some(m) => take(&mut ready, m, &mut deadline, &drain),
+                None => break,
+            },
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            warn!("Executor: Drain timeout, {} events ready", ready.len());
+            break;
+        }
+    }
+    // refuse further events, i.e. the ones published by exit actions
+    queue.close();
+    ready.stopped = true;
+    for &index in &order {
+        let mut context = ExecutorContext {
+            id: MachineId(index),
+            ready: &mut ready,
+            timers: &timers,
+        };
+        machines[index].stop(&mut context);
+    }
+    debug!("Executor: finished");
+}
+
+/// Queue an event of the fan-in queue for the state machines
+fn take<E: Send>(ready: &mut Ready<E>, m: ContextEvent<E>, deadline: &mut Option<Instant>, drain: &Drain) {
+    let queued = match m {
+        ContextEvent::Envelope(e) => ready.publish(e),
+        ContextEvent::Addressed(target, e) => ready.send_to(target, e),
+        ContextEvent::Stop if deadline.is_none() => {
+            debug!("Executor: Drain ready events");
+            *deadline = Some(Instant::now() + drain.timeout());
+            Ok(())
+        }
+        // stopping already, started already
+        ContextEvent::Stop | ContextEvent::Start | ContextEvent::Started(_) => Ok(()),
+    };
+    if let Err(reason) = queued {
+        error!("Executor: Event dropped: {}", reason);
+    }
+}
+
+#[cfg(test)]
+mod tests;
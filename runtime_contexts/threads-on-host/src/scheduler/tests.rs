@@ -0,0 +1,110 @@
+use super::*;
+use crate::{registry::everything, OverflowPolicy, Subscription, TimerService};
+use qlrl::{fsm::FiniteStateMachine, state_machine, ProcessingResult};
+use std::sync::Mutex;
+
+type Trace = Arc<Mutex<Vec<String>>>;
+
+struct Recorder {
+    name: char,
+    trace: Trace,
+}
+
+fn recorder_exit<'a>(data: &'a mut Recorder, _context: &mut (dyn StateMachineContext<u8> + 'a)) {
+    data.trace.lock().unwrap().push(format!("{}x", data.name));
+}
+
+/// Record the event; events from 10 on publish the event minus 10
+fn recorder_dispatch<'a>(
+    data: &'a mut Recorder,
+    context: &mut (dyn StateMachineContext<u8> + 'a),
+    event: &u8,
+) -> ProcessingResult<Recorder, u8, RecorderState> {
+    data.trace.lock().unwrap().push(format!("{}{}", data.name, event));
+    if *event >= 10 {
+        context.publish_event(event - 10);
+    }
+    ProcessingResult::Handled
+}
+
+state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum RecorderState;
+
+    const RECORDER_STATES: [State<Recorder, u8>] = [
+        Recording { exit: recorder_exit, dispatch: recorder_dispatch },
+    ];
+}
+
+fn task(name: char, trace: &Trace, priority: u8) -> Task<u8> {
+    let recorder = Recorder {
+        name,
+        trace: trace.clone(),
+    };
+    Task {
+        state_machine: Box::new(FiniteStateMachine::new(&RECORDER_STATES, recorder)),
+        priority,
+    }
+}
+
+/// Run the executor on the given events until the fan-in queue is empty;
+/// the state machines subscribe as given
+fn execute(
+    tasks: Vec<Task<u8>>,
+    subscriptions: &[Option<Subscription<u8>>],
+    capacity: usize,
+    events: Vec<ContextEvent<u8>>,
+) {
+    let queue = Arc::new(EventQueue::new(10, OverflowPolicy::Error));
+    let timer = TimerService::new(queue.clone());
+    let mut registry = SubscriberRegistry::new(Some(u8::clone));
+    for subscription in subscriptions {
+        registry.register(*subscription);
+    }
+    for event in events {
+        queue.push(event).unwrap();
+    }
+    queue.close();
+    executor(queue, timer.scheduler(), tasks, registry, capacity, Arc::new(Drain::new()));
+}
+
+const ALL: Option<Subscription<u8>> = Some(everything);
+
+#[test]
+fn highest_priority_is_dispatched_first() {
+    let trace = Trace::default();
+    let tasks = vec![task('l', &trace, 1), task('h', &trace, 2)];
+    execute(tasks, &[ALL, ALL], 10, vec![ContextEvent::Envelope(1), ContextEvent::Envelope(2)]);
+    assert_eq!(vec!["h1", "h2", "l1", "l2", "hx", "lx"], *trace.lock().unwrap());
+}
+
+#[test]
+fn published_events_preempt_lower_priorities_at_the_next_step() {
+    let trace = Trace::default();
+    let tasks = vec![task('l', &trace, 1), task('h', &trace, 2)];
+    execute(tasks, &[ALL, Some(|e| *e < 10)], 10, vec![ContextEvent::Envelope(13), ContextEvent::Envelope(5)]);
+    assert_eq!(vec!["h5", "l13", "h3", "l5", "l3", "hx", "lx"], *trace.lock().unwrap());
+}
+
+#[test]
+fn equal_priorities_are_dispatched_in_order_of_addition() {
+    let trace = Trace::default();
+    let tasks = vec![task('a', &trace, 0), task('b', &trace, 0)];
+    execute(tasks, &[ALL, ALL], 10, vec![ContextEvent::Addressed(MachineId(1), 1), ContextEvent::Envelope(2)]);
+    assert_eq!(vec!["a2", "b1", "b2", "ax", "bx"], *trace.lock().unwrap());
+}
+
+#[test]
+fn full_ready_queue_refuses_event_as_a_whole() {
+    let trace = Trace::default();
+    let tasks = vec![task('a', &trace, 0), task('b', &trace, 0)];
+    let events = vec![
+        ContextEvent::Addressed(MachineId(1), 1),
+        ContextEvent::Addressed(MachineId(1), 2),
+        ContextEvent::Addressed(MachineId(1), 3), // dropped
+        ContextEvent::Envelope(4),                // dropped
+        ContextEvent::Addressed(MachineId(0), 5),
+    ];
+    execute(tasks, &[ALL, ALL], 2, events);
+    assert_eq!(vec!["a5", "b1", "b2", "ax", "bx"], *trace.lock().unwrap());
+}
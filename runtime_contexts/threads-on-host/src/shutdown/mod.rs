@@ -0,0 +1,162 @@
+//! Programmatic shutdown of a `ThreadedContext`
+//!
+//! - stopping lets the dispatcher drain the events in flight, bounded by a timeout
+//! - afterwards every state machine exits its active state and its thread ends
+//! - panicked threads are reported when the threads are joined
+//!
+use log::debug;
+use std::{
+    any::Any,
+    fmt::{self, Debug},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use super::{queue::EventQueue, ContextEvent};
+
+/// Time to process the events in flight used by `ShutdownHandle::stop`
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Bookkeeping of the dispatcher to drain the events in flight
+pub(crate) struct Drain {
+    in_flight: AtomicUsize, // forwarded to but not yet processed by the state machines
+    timeout_ms: AtomicU64,
+}
+
+impl Drain {
+    pub(crate) fn new() -> Self {
+        Drain {
+            in_flight: AtomicUsize::new(0),
+            timeout_ms: AtomicU64::new(DEFAULT_DRAIN_TIMEOUT.as_millis() as u64),
+        }
+    }
+
+    pub(crate) fn forwarded(&self) {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn processed(&self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms.load(Ordering::SeqCst))
+    }
+}
+
+/// Marks an event as processed when dropped, also if the state machine
+/// panics processing it
+pub(crate) struct InFlight<'d>(pub(crate) &'d Drain);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.processed();
+    }
+}
+
+/// Cloneable handle to stop a `ThreadedContext` from code
+pub struct ShutdownHandle<E: Send> {
+    queue: Arc<EventQueue<ContextEvent<E>>>,
+    drain: Arc<Drain>,
+}
+
+impl<E: Send> Clone for ShutdownHandle<E> {
+    fn clone(&self) -> Self {
+        ShutdownHandle {
+            queue: self.queue.clone(),
+            drain: self.drain.clone(),
+        }
+    }
+}
+
+impl<E: Send> ShutdownHandle<E> {
+    pub(crate) fn new(queue: Arc<EventQueue<ContextEvent<E>>>, drain: Arc<Drain>) -> Self {
+        ShutdownHandle { queue, drain }
+    }
+
+    /// Stop the runtime; events in flight are processed for at most
+    /// `DEFAULT_DRAIN_TIMEOUT`
+    pub fn stop(&self) {
+        self.stop_with_timeout(DEFAULT_DRAIN_TIMEOUT);
+    }
+
+    /// Stop the runtime; events in flight are processed for at most
+    /// `timeout`, remaining events are dropped
+    ///
+    /// Events published before are processed first. Stopping a stopped
+    /// runtime does nothing.
+    pub fn stop_with_timeout(&self, timeout: Duration) {
+        let timeout_ms = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+        self.drain.timeout_ms.store(timeout_ms, Ordering::SeqCst);
+        if self.queue.push_unbounded(ContextEvent::Stop).is_err() {
+            debug!("Shutdown: runtime already stopped");
+        }
+    }
+}
+
+/// A thread of the runtime that panicked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadPanic {
+    /// Name of the thread i.e. `sm-<machine id>` or `dispatcher`
+    pub thread: String,
+    /// Panic message if it is a string
+    pub message: String,
+}
+
+/// Failures detected when the runtime threads are joined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownError {
+    pub panicked: Vec<ThreadPanic>,
+}
+
+impl fmt::Display for ShutdownError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} thread(s) panicked", self.panicked.len())?;
+        for panic in &self.panicked {
+            write!(f, "; {}: {}", panic.thread, panic.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ShutdownError {}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".into()
+    }
+}
+
+/// Join all threads and collect the panicked ones
+pub(crate) fn join_all(threads: Vec<JoinHandle<()>>) -> Result<(), ShutdownError> {
+    let mut panicked = vec![];
+    for handle in threads {
+        let thread = handle.thread().name().unwrap_or("unnamed").to_string();
+        if let Err(payload) = handle.join() {
+            panicked.push(ThreadPanic {
+                thread,
+                message: panic_message(payload.as_ref()),
+            });
+        }
+    }
+    if panicked.is_empty() {
+        Ok(())
+    } else {
+        Err(ShutdownError { panicked })
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,25 @@
+use super::*;
+use std::thread;
+
+#[test]
+fn join_all_reports_panicked_threads() {
+    let spawn = |name: &str, fail: bool| {
+        thread::Builder::new()
+            .name(name.into())
+            .spawn(move || assert!(!fail, "failed on purpose"))
+            .unwrap()
+    };
+    let error = join_all(vec![spawn("ok", false), spawn("bad", true)]).unwrap_err();
+    assert_eq!(
+        vec![ThreadPanic {
+            thread: "bad".into(),
+            message: "failed on purpose".into()
+        }],
+        error.panicked
+    );
+}
+
+#[test]
+fn join_all_succeeds_without_panics() {
+    assert_eq!(Ok(()), join_all(vec![thread::spawn(|| ())]));
+}
@@ -0,0 +1,453 @@
+use super::*;
+use qlrl::{fsm::FiniteStateMachine, state_machine, ProcessingResult};
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+fn is_even(e: &u8) -> bool {
+    e.is_multiple_of(2)
+}
+
+/// Run the dispatcher on the given events for a subscriber to all events
+/// and a subscriber to even events
+fn dispatch(events: Vec<ContextEvent<u8>>) -> (Vec<ContextEvent<u8>>, Vec<ContextEvent<u8>>) {
+    let queue = Arc::new(EventQueue::new(10, OverflowPolicy::Error));
+    let (all_tx, all_rx) = mpsc::sync_channel(10);
+    let (even_tx, even_rx) = mpsc::sync_channel(10);
+    let routes = vec![Route { tx: all_tx }, Route { tx: even_tx }];
+    let mut registry = SubscriberRegistry::new(Some(u8::clone));
+    registry.register(Some(everything));
+    registry.register(Some(is_even));
+    // both state machines are started right away, nobody processes the start
+    for index in 0..routes.len() {
+        queue.push(ContextEvent::Started(MachineId(index))).unwrap();
+    }
+    for event in events {
+        queue.push(event).unwrap();
+    }
+    queue.close();
+    let drain = Arc::new(Drain::new());
+    for _ in &routes {
+        drain.processed();
+    }
+    dispatcher(queue, routes, Arc::new(Mutex::new(registry)), drain);
+    (all_rx.try_iter().collect(), even_rx.try_iter().collect())
+}
+
+fn events(received: &[ContextEvent<u8>]) -> Vec<u8> {
+    received
+        .iter()
+        .filter_map(|m| match m {
+            ContextEvent::Envelope(e) | ContextEvent::Addressed(_, e) => Some(*e),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn published_events_respect_subscriptions() {
+    let (all, even) = dispatch((1..=4).map(ContextEvent::Envelope).collect());
+    assert_eq!(vec![1, 2, 3, 4], events(&all));
+    assert_eq!(vec![2, 4], events(&even));
+}
+
+#[test]
+fn sent_events_reach_addressed_machine_only() {
+    let (all, even) = dispatch(vec![
+        ContextEvent::Addressed(MachineId(1), 3),
+        ContextEvent::Addressed(MachineId(0), 4),
+        ContextEvent::Addressed(MachineId(2), 5),
+    ]);
+    assert_eq!(vec![4], events(&all));
+    assert_eq!(vec![3], events(&even)); // not filtered by the subscription
+}
+
+#[test]
+fn start_and_stop_reach_all_machines() {
+    let (all, even) = dispatch(vec![ContextEvent::Stop]);
+    for received in [all, even] {
+        assert!(matches!(received[..], [ContextEvent::Start, ContextEvent::Stop]));
+    }
+}
+
+//----------------------------------------------------------------------------
+// runtime tests with a state machine counting down published events
+
+type Trace = Arc<Mutex<Vec<String>>>;
+
+struct Counter {
+    trace: Trace,
+}
+
+fn counter_exit<'a>(data: &'a mut Counter, _context: &mut (dyn StateMachineContext<u8> + 'a)) {
+    data.trace.lock().unwrap().push("exit".into());
+}
+
+fn counter_dispatch<'a>(
+    data: &'a mut Counter,
+    context: &mut (dyn StateMachineContext<u8> + 'a),
+    event: &u8,
+) -> ProcessingResult<Counter, u8, CounterState> {
+    assert!(*event != 99, "boom");
+    data.trace.lock().unwrap().push(event.to_string());
+    thread::sleep(time::Duration::from_millis(2));
+    if *event > 0 {
+        context.publish_event(event - 1);
+    }
+    ProcessingResult::Handled
+}
+
+state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum CounterState;
+
+    const COUNTER_STATES: [State<Counter, u8>] = [
+        Counting { exit: counter_exit, dispatch: counter_dispatch },
+    ];
+}
+
+fn counter(trace: &Trace) -> Box<dyn StateMachine<u8> + Send> {
+    Box::new(FiniteStateMachine::new(&COUNTER_STATES, Counter { trace: trace.clone() }))
+}
+
+#[test]
+fn stop_drains_events_in_flight_and_exits_states() {
+    let trace = Trace::default();
+    let mut context = ThreadedContext::new();
+    context.add(counter(&trace));
+    let runtime = context.spawn();
+    runtime.inject(20).unwrap();
+    thread::sleep(time::Duration::from_millis(10));
+    runtime.shutdown_handle().stop_with_timeout(time::Duration::from_millis(1000));
+    assert_eq!(Ok(()), runtime.join());
+    let trace = trace.lock().unwrap();
+    let expected: Vec<String> = (0..=20).rev().map(|n: u8| n.to_string()).chain(["exit".into()]).collect();
+    assert_eq!(expected, *trace);
+}
+
+#[test]
+fn stop_reports_panicked_state_machines() {
+    let trace = Trace::default();
+    let mut context = ThreadedContext::new();
+    context.add(counter(&trace));
+    let failing = context.add_subscribed(counter(&trace), |_| false);
+    let runtime = context.spawn();
+    runtime.inject_to(failing, 99).unwrap();
+    runtime.shutdown_handle().stop_with_timeout(time::Duration::from_millis(10));
+    let error = runtime.join().unwrap_err();
+    assert_eq!(1, error.panicked.len());
+    assert_eq!("sm-1", error.panicked[0].thread);
+    assert_eq!("boom", error.panicked[0].message);
+}
+
+#[test]
+fn injected_events_respect_subscriptions() {
+    let all = Trace::default();
+    let even = Trace::default();
+    let mut context = ThreadedContext::new();
+    context.add(counter(&all));
+    context.add_subscribed(counter(&even), |e| e.is_multiple_of(2));
+    let runtime = context.spawn();
+    runtime.inject(1).unwrap(); // the first state machine publishes 0 then
+    runtime.stop();
+    assert_eq!(Ok(()), runtime.join());
+    assert_eq!(vec!["1", "0", "exit"], *all.lock().unwrap());
+    assert_eq!(vec!["0", "exit"], *even.lock().unwrap());
+}
+
+#[test]
+fn dropping_the_runtime_handle_stops_the_runtime() {
+    let trace = Trace::default();
+    let mut context = ThreadedContext::new();
+    context.add(counter(&trace));
+    drop(context.spawn());
+    assert_eq!(vec!["exit"], *trace.lock().unwrap());
+}
+
+#[test]
+fn injector_drives_state_machines_from_other_threads() {
+    let trace = Trace::default();
+    let mut context = ThreadedContext::new();
+    let id = context.add_subscribed(counter(&trace), |_| false); // ignores its count down
+    let injector = context.injector().to(id);
+    injector.inject(1).unwrap(); // processed after start
+    let runtime = context.spawn();
+    thread::spawn(move || injector.inject_timeout(3, time::Duration::from_secs(1)))
+        .join()
+        .unwrap()
+        .unwrap();
+    runtime.stop();
+    assert_eq!(Ok(()), runtime.join());
+    assert_eq!(vec!["1", "3", "exit"], *trace.lock().unwrap());
+}
+
+#[test]
+fn priority_scheduling_drains_events_and_exits_states() {
+    let trace = Trace::default();
+    let mut context = ThreadedContext::new().with_scheduling(Scheduling::Priority);
+    let id = context.add(counter(&trace));
+    context.set_priority(id, 1);
+    let runtime = context.spawn();
+    runtime.inject(5).unwrap();
+    runtime.shutdown_handle().stop_with_timeout(time::Duration::from_millis(1000));
+    assert_eq!(Ok(()), runtime.join());
+    assert_eq!(vec!["5", "4", "3", "2", "1", "0", "exit"], *trace.lock().unwrap());
+}
+
+#[test]
+fn priority_scheduling_reports_panicked_executor() {
+    let trace = Trace::default();
+    let mut context = ThreadedContext::new().with_scheduling(Scheduling::Priority);
+    let failing = context.add(counter(&trace));
+    let runtime = context.spawn();
+    runtime.inject_to(failing, 99).unwrap();
+    let error = runtime.join().unwrap_err();
+    assert_eq!("executor", error.panicked[0].thread);
+    assert_eq!("boom", error.panicked[0].message);
+}
+
+//----------------------------------------------------------------------------
+// state machine subscribing to a further kind of events on start
+
+struct Listener {
+    trace: Trace,
+}
+
+fn listening_entry<'a>(_data: &'a mut Listener, context: &mut (dyn StateMachineContext<u8> + 'a)) {
+    context.subscribe(2);
+}
+
+fn listening_dispatch<'a>(
+    data: &'a mut Listener,
+    _context: &mut (dyn StateMachineContext<u8> + 'a),
+    event: &u8,
+) -> ProcessingResult<Listener, u8, ListenerState> {
+    data.trace.lock().unwrap().push(event.to_string());
+    ProcessingResult::Handled
+}
+
+state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum ListenerState;
+
+    const LISTENER_STATES: [State<Listener, u8>] = [
+        Listening { entry: listening_entry, dispatch: listening_dispatch },
+    ];
+}
+
+#[test]
+fn published_events_reach_kind_subscribers_only() {
+    for scheduling in [Scheduling::Threads, Scheduling::Priority] {
+        let trace = Trace::default();
+        let mut context = ThreadedContext::new().with_scheduling(scheduling);
+        let listener = Listener { trace: trace.clone() };
+        context.add_subscribed_to(Box::new(FiniteStateMachine::new(&LISTENER_STATES, listener)), &[1]);
+        let injector = context.injector();
+        for e in 1..=3 {
+            injector.inject(e).unwrap(); // processed after the subscription on start
+        }
+        let runtime = context.spawn();
+        runtime.stop();
+        assert_eq!(Ok(()), runtime.join());
+        assert_eq!(vec!["1", "2"], *trace.lock().unwrap(), "{:?}", scheduling);
+    }
+}
+
+fn resubscribing_dispatch<'a>(
+    data: &'a mut Listener,
+    context: &mut (dyn StateMachineContext<u8> + 'a),
+    event: &u8,
+) -> ProcessingResult<Listener, u8, ResubscriberState> {
+    thread::sleep(time::Duration::from_millis(1)); // let the channel fill up
+    context.unsubscribe(*event);
+    context.subscribe(*event);
+    data.trace.lock().unwrap().push(event.to_string());
+    ProcessingResult::Handled
+}
+
+state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum ResubscriberState;
+
+    const RESUBSCRIBER_STATES: [State<Listener, u8>] = [
+        Resubscribing { dispatch: resubscribing_dispatch },
+    ];
+}
+
+#[test]
+fn subscribing_while_the_dispatcher_waits_for_room() {
+    let trace = Trace::default();
+    let mut context = ThreadedContext::with_queue(1, OverflowPolicy::Block);
+    let listener = Listener { trace: trace.clone() };
+    context.add_subscribed_to(Box::new(FiniteStateMachine::new(&RESUBSCRIBER_STATES, listener)), &[1]);
+    let runtime = context.spawn();
+    let (done, finished) = mpsc::channel();
+    thread::spawn(move || {
+        for _ in 0..10 {
+            runtime.inject(1).unwrap();
+        }
+        runtime.stop();
+        done.send(runtime.join()).unwrap();
+    });
+    let result = finished.recv_timeout(time::Duration::from_secs(5)).expect("Runtime deadlocked");
+    assert_eq!(Ok(()), result);
+    assert_eq!(10, trace.lock().unwrap().len());
+}
+
+//----------------------------------------------------------------------------
+// state machine publishing on start
+
+fn announcing_entry<'a>(_data: &'a mut Listener, context: &mut (dyn StateMachineContext<u8> + 'a)) {
+    context.publish_event(0);
+}
+
+fn announcing_dispatch<'a>(
+    data: &'a mut Listener,
+    _context: &mut (dyn StateMachineContext<u8> + 'a),
+    event: &u8,
+) -> ProcessingResult<Listener, u8, AnnouncerState> {
+    data.trace.lock().unwrap().push(event.to_string());
+    ProcessingResult::Handled
+}
+
+state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum AnnouncerState;
+
+    const ANNOUNCER_STATES: [State<Listener, u8>] = [
+        Announcing { entry: announcing_entry, dispatch: announcing_dispatch },
+    ];
+}
+
+#[test]
+fn publishing_on_start_while_injected_events_fill_the_queue() {
+    let trace = Trace::default();
+    let mut context = ThreadedContext::with_queue(2, OverflowPolicy::Block);
+    let listener = Listener { trace: trace.clone() };
+    context.add(Box::new(FiniteStateMachine::new(&ANNOUNCER_STATES, listener)));
+    let injector = context.injector();
+    injector.inject(1).unwrap();
+    injector.inject(2).unwrap(); // the queue is full before the start
+    let runtime = context.spawn();
+    let (done, finished) = mpsc::channel();
+    thread::spawn(move || {
+        runtime.stop();
+        done.send(runtime.join()).unwrap();
+    });
+    let result = finished.recv_timeout(time::Duration::from_secs(5)).expect("Runtime deadlocked");
+    assert_eq!(Ok(()), result);
+    assert_eq!(vec!["1", "2", "0"], *trace.lock().unwrap());
+}
+
+//----------------------------------------------------------------------------
+// state machines replying to requests that can neither be copied nor shared,
+// nor subscribed to by kind
+
+struct Request(Cell<u8>, mpsc::Sender<(usize, u8)>);
+
+struct Replier {
+    id: usize,
+}
+
+fn replying_dispatch<'a>(
+    data: &'a mut Replier,
+    _context: &mut (dyn StateMachineContext<Request> + 'a),
+    event: &Request,
+) -> ProcessingResult<Replier, Request, ReplierState> {
+    event.1.send((data.id, event.0.get())).unwrap();
+    ProcessingResult::Handled
+}
+
+state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum ReplierState;
+
+    const REPLIER_STATES: [State<Replier, Request>] = [
+        Replying { dispatch: replying_dispatch },
+    ];
+}
+
+#[test]
+fn moved_events_are_sent_but_not_published() {
+    for scheduling in [Scheduling::Threads, Scheduling::Priority] {
+        let mut context = ThreadedContext::with_moved_events(10, OverflowPolicy::Block).with_scheduling(scheduling);
+        context.add(Box::new(FiniteStateMachine::new(&REPLIER_STATES, Replier { id: 0 })));
+        let second = context.add(Box::new(FiniteStateMachine::new(&REPLIER_STATES, Replier { id: 1 })));
+        let runtime = context.spawn();
+        let (tx, rx) = mpsc::channel();
+        let published = runtime.inject(Request(Cell::new(1), tx.clone()));
+        assert_eq!(Err(PublishError::Unsupported), published, "{:?}", scheduling);
+        runtime.inject_to(second, Request(Cell::new(2), tx)).unwrap();
+        runtime.stop();
+        assert_eq!(Ok(()), runtime.join());
+        let replies: Vec<_> = rx.try_iter().collect();
+        assert_eq!(vec![(1, 2)], replies, "{:?}", scheduling);
+    }
+}
+
+//----------------------------------------------------------------------------
+// state machines sharing a published payload instead of copying it
+
+static PAYLOAD_COPIES: AtomicUsize = AtomicUsize::new(0);
+
+struct Payload(u8);
+
+impl Clone for Payload {
+    fn clone(&self) -> Self {
+        PAYLOAD_COPIES.fetch_add(1, Ordering::SeqCst);
+        Payload(self.0)
+    }
+}
+
+impl Signal for Payload {
+    type Kind = u8;
+
+    fn signal(&self) -> u8 {
+        self.0
+    }
+}
+
+struct Sharer {
+    trace: Trace,
+}
+
+fn sharing_dispatch<'a>(
+    data: &'a mut Sharer,
+    _context: &mut (dyn StateMachineContext<Arc<Payload>> + 'a),
+    event: &Arc<Payload>,
+) -> ProcessingResult<Sharer, Arc<Payload>, SharerState> {
+    data.trace.lock().unwrap().push(event.0.to_string());
+    ProcessingResult::Handled
+}
+
+state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum SharerState;
+
+    const SHARER_STATES: [State<Sharer, Arc<Payload>>] = [
+        Sharing { dispatch: sharing_dispatch },
+    ];
+}
+
+#[test]
+fn shared_payloads_are_published_without_copies() {
+    for scheduling in [Scheduling::Threads, Scheduling::Priority] {
+        let trace = Trace::default();
+        let mut context = ThreadedContext::new().with_scheduling(scheduling);
+        for _ in 0..3 {
+            let sharer = Sharer { trace: trace.clone() };
+            context.add_subscribed_to(Box::new(FiniteStateMachine::new(&SHARER_STATES, sharer)), &[1]);
+        }
+        let runtime = context.spawn();
+        runtime.inject(Arc::new(Payload(1))).unwrap();
+        runtime.inject(Arc::new(Payload(2))).unwrap(); // no subscriber
+        runtime.stop();
+        assert_eq!(Ok(()), runtime.join());
+        assert_eq!(vec!["1", "1", "1"], *trace.lock().unwrap(), "{:?}", scheduling);
+    }
+    assert_eq!(0, PAYLOAD_COPIES.load(Ordering::SeqCst));
+}
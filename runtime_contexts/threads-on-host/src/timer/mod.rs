@@ -0,0 +1,238 @@
+//! Timer service
+//!
+//! - a dedicated thread keeps pending timers in a min-heap ordered by due time
+//! - a fired timer posts its item into the fan-in event queue
+//! - periodic timers are re-armed with a copy of their item when they fire
+//! - scheduling never blocks the calling state machine thread
+//!
+use log::{debug, error};
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use qlrl::PublishError;
+
+use super::queue::EventQueue;
+
+/// Period of a periodic timer and the function copying its item
+type Period<T> = (Duration, fn(&T) -> T);
+
+struct Timer<T> {
+    due: Instant,
+    sequence: u64, // keeps timers with equal due time in scheduling order
+    id: u64,
+    period: Option<Period<T>>,
+    item: T,
+}
+
+impl<T> PartialEq for Timer<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Timer<T> {}
+
+impl<T> PartialOrd for Timer<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Timer<T> {
+    /// Reversed order so the max-heap `BinaryHeap` yields the earliest timer first
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.due, other.sequence).cmp(&(self.due, self.sequence))
+    }
+}
+
+struct TimerState<T> {
+    timers: BinaryHeap<Timer<T>>,
+    sequence: u64,
+    stopped: bool,
+}
+
+impl<T> TimerState<T> {
+    fn next_sequence(&mut self) -> u64 {
+        self.sequence += 1;
+        self.sequence
+    }
+}
+
+struct Shared<T> {
+    state: Mutex<TimerState<T>>,
+    changed: Condvar,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, TimerState<T>> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Cloneable handle to schedule timers of a `TimerService`
+pub struct TimerScheduler<T>(Arc<Shared<T>>);
+
+impl<T> Clone for TimerScheduler<T> {
+    fn clone(&self) -> Self {
+        TimerScheduler(self.0.clone())
+    }
+}
+
+impl<T> TimerScheduler<T> {
+    fn arm(&self, delay: Duration, period: Option<Period<T>>, item: T) -> u64 {
+        let mut state = self.0.lock();
+        let id = state.next_sequence();
+        if state.stopped {
+            debug!("Timer service stopped: timer discarded");
+            return id;
+        }
+        state.timers.push(Timer {
+            due: Instant::now() + delay,
+            sequence: id,
+            id,
+            period,
+            item,
+        });
+        self.0.changed.notify_one();
+        id
+    }
+
+    /// Post `item` into the event queue after `delay`
+    ///
+    /// Returns the id to cancel the timer.
+    pub fn schedule(&self, delay: Duration, item: T) -> u64 {
+        self.arm(delay, None, item)
+    }
+
+    /// Post a copy of `item` into the event queue every `period`
+    /// (at least every millisecond)
+    ///
+    /// Returns the id to cancel the timer.
+    pub fn schedule_periodic(&self, period: Duration, item: T) -> u64
+    where
+        T: Clone,
+    {
+        let period = period.max(Duration::from_millis(1));
+        self.arm(period, Some((period, T::clone)), item)
+    }
+
+    /// Cancel a pending timer
+    ///
+    /// Returns `false` if there is no pending timer with the given id.
+    pub fn cancel(&self, id: u64) -> bool {
+        let mut state = self.0.lock();
+        let pending = state.timers.len();
+        state.timers.retain(|timer| timer.id != id);
+        pending != state.timers.len()
+    }
+}
+
+/// Timer thread posting items into an event queue when their timers expire
+///
+/// The thread is stopped and joined when the service is dropped; pending
+/// timers are discarded.
+pub struct TimerService<T> {
+    shared: Arc<Shared<T>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> TimerService<T> {
+    pub fn new(queue: Arc<EventQueue<T>>) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(TimerState {
+                timers: BinaryHeap::new(),
+                sequence: 0,
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+        });
+        let thread = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("timer".into())
+                .spawn(move || timer_worker(shared, queue))
+                .expect("Could not spawn timer thread")
+        };
+        TimerService {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn scheduler(&self) -> TimerScheduler<T> {
+        TimerScheduler(self.shared.clone())
+    }
+}
+
+impl<T> TimerService<T> {
+    /// Stop and join the timer thread; pending timers are discarded
+    pub fn stop(&mut self) {
+        self.shared.lock().stopped = true;
+        self.shared.changed.notify_all();
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("Timer thread panicked");
+            }
+        }
+    }
+}
+
+impl<T> Drop for TimerService<T> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn timer_worker<T>(shared: Arc<Shared<T>>, queue: Arc<EventQueue<T>>) {
+    debug!("Timer thread: started");
+    let mut state = shared.lock();
+    while !state.stopped {
+        let now = Instant::now();
+        match state.timers.peek().map(|timer| timer.due) {
+            Some(due) if due <= now => {
+                if let Some(timer) = state.timers.pop() {
+                    let item = match timer.period {
+                        Some((period, copy)) => {
+                            let item = copy(&timer.item);
+                            let sequence = state.next_sequence();
+                            state.timers.push(Timer {
+                                due: timer.due + period,
+                                sequence,
+                                ..timer
+                            });
+                            item
+                        }
+                        None => timer.item,
+                    };
+                    // do not hold the lock while a blocking queue waits for room
+                    drop(state);
+                    match queue.push(item) {
+                        Ok(()) => (),
+                        Err(PublishError::Stopped) => debug!("Timer event dropped: runtime stopped"),
+                        Err(reason) => error!("Timer event dropped: {}", reason),
+                    }
+                    state = shared.lock();
+                }
+            }
+            Some(due) => {
+                state = shared
+                    .changed
+                    .wait_timeout(state, due - now)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .0;
+            }
+            None => {
+                state = shared.changed.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+            }
+        }
+    }
+    debug!("Timer thread: finished");
+}
+
+#[cfg(test)]
+mod tests;
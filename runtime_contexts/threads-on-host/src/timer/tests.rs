@@ -0,0 +1,89 @@
+use super::*;
+use crate::OverflowPolicy;
+
+fn service() -> (TimerService<u8>, Arc<EventQueue<u8>>) {
+    let queue = Arc::new(EventQueue::new(10, OverflowPolicy::Error));
+    (TimerService::new(queue.clone()), queue)
+}
+
+#[test]
+fn schedule_does_not_block() {
+    let (service, _queue) = service();
+    let start = Instant::now();
+    service.scheduler().schedule(Duration::from_millis(500), 1);
+    assert!(start.elapsed() < Duration::from_millis(100));
+}
+
+#[test]
+fn timers_fire_in_due_order() {
+    let (service, queue) = service();
+    let scheduler = service.scheduler();
+    let start = Instant::now();
+    scheduler.schedule(Duration::from_millis(60), 3);
+    scheduler.schedule(Duration::from_millis(20), 1);
+    scheduler.schedule(Duration::from_millis(40), 2);
+    assert_eq!(Some(1), queue.pop());
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    assert_eq!(Some(2), queue.pop());
+    assert_eq!(Some(3), queue.pop());
+    assert!(start.elapsed() >= Duration::from_millis(60));
+}
+
+#[test]
+fn timers_with_equal_due_time_keep_scheduling_order() {
+    let (service, queue) = service();
+    let scheduler = service.scheduler();
+    for item in 0..5 {
+        scheduler.schedule(Duration::ZERO, item);
+    }
+    for item in 0..5 {
+        assert_eq!(Some(item), queue.pop());
+    }
+}
+
+#[test]
+fn drop_discards_pending_timers() {
+    let (service, queue) = service();
+    let scheduler = service.scheduler();
+    scheduler.schedule(Duration::from_millis(20), 1);
+    drop(service);
+    scheduler.schedule(Duration::ZERO, 2);
+    thread::sleep(Duration::from_millis(40));
+    queue.close();
+    assert_eq!(None, queue.pop());
+}
+
+#[test]
+fn cancel_pending_timer() {
+    let (service, queue) = service();
+    let scheduler = service.scheduler();
+    let id = scheduler.schedule(Duration::from_millis(20), 1);
+    scheduler.schedule(Duration::from_millis(40), 2);
+    assert!(scheduler.cancel(id));
+    assert!(!scheduler.cancel(id));
+    assert_eq!(Some(2), queue.pop());
+}
+
+#[test]
+fn cancel_expired_timer_fails() {
+    let (service, queue) = service();
+    let scheduler = service.scheduler();
+    let id = scheduler.schedule(Duration::ZERO, 1);
+    assert_eq!(Some(1), queue.pop());
+    assert!(!scheduler.cancel(id));
+}
+
+#[test]
+fn periodic_timer_fires_until_cancelled() {
+    let (service, queue) = service();
+    let scheduler = service.scheduler();
+    let start = Instant::now();
+    let id = scheduler.schedule_periodic(Duration::from_millis(10), 7);
+    for _ in 0..3 {
+        assert_eq!(Some(7), queue.pop());
+    }
+    assert!(start.elapsed() >= Duration::from_millis(30));
+    assert!(scheduler.cancel(id));
+    scheduler.schedule(Duration::from_millis(30), 8);
+    assert_eq!(Some(8), queue.pop());
+}
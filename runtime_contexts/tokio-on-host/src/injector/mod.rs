@@ -0,0 +1,83 @@
+//! Injection of events from outside the state machines
+//!
+//! - an injector is cloneable and can be used from any task or thread
+//! - it publishes to all subscribed state machines or sends to a single one
+//! - events are injected into the fan-in channel like published events
+//!
+use std::time::Duration;
+use tokio::sync::mpsc::{
+    self,
+    error::{SendTimeoutError, TrySendError},
+};
+
+use qlrl::{MachineId, PublishError};
+
+use super::ContextEvent;
+
+/// Cloneable handle to inject events into a `TokioContext`
+///
+/// Unlike publishing by state machines, which never waits, injecting can
+/// wait for room if the fan-in channel is full.
+pub struct EventInjector<E> {
+    queue: mpsc::Sender<ContextEvent<E>>,
+    target: Option<MachineId>,
+}
+
+impl<E> Clone for EventInjector<E> {
+    fn clone(&self) -> Self {
+        EventInjector {
+            queue: self.queue.clone(),
+            target: self.target,
+        }
+    }
+}
+
+impl<E> EventInjector<E> {
+    pub(crate) fn new(queue: mpsc::Sender<ContextEvent<E>>) -> Self {
+        EventInjector { queue, target: None }
+    }
+
+    /// Injector sending to the given state machine only
+    pub fn to(&self, target: MachineId) -> Self {
+        EventInjector {
+            queue: self.queue.clone(),
+            target: Some(target),
+        }
+    }
+
+    fn envelope(&self, e: E) -> ContextEvent<E> {
+        match self.target {
+            Some(target) => ContextEvent::Addressed(target, e),
+            None => ContextEvent::Envelope(e),
+        }
+    }
+
+    /// Inject an event, wait for room if the channel is full
+    pub async fn inject(&self, e: E) -> Result<(), PublishError> {
+        self.queue.send(self.envelope(e)).await.map_err(|_| PublishError::Stopped)
+    }
+
+    /// Inject an event if the channel has room
+    pub fn try_inject(&self, e: E) -> Result<(), PublishError> {
+        self.queue.try_send(self.envelope(e)).map_err(|error| match error {
+            TrySendError::Full(_) => PublishError::QueueFull,
+            TrySendError::Closed(_) => PublishError::Stopped,
+        })
+    }
+
+    /// Inject an event, wait at most `timeout` for room if the channel is full
+    pub async fn inject_timeout(&self, e: E, timeout: Duration) -> Result<(), PublishError> {
+        self.queue.send_timeout(self.envelope(e), timeout).await.map_err(|error| match error {
+            SendTimeoutError::Timeout(_) => PublishError::QueueFull,
+            SendTimeoutError::Closed(_) => PublishError::Stopped,
+        })
+    }
+
+    /// Inject an event from a thread outside the tokio runtime, wait for room
+    /// if the channel is full
+    ///
+    /// Panics if called within an asynchronous execution context.
+    pub fn blocking_inject(&self, e: E) -> Result<(), PublishError> {
+        self.queue.blocking_send(self.envelope(e)).map_err(|_| PublishError::Stopped)
+    }
+}
@@ -0,0 +1,479 @@
+//! State machine execution context on top of tokio
+//!
+//! - each state machine runs in a tokio task with a channel of its own
+//! - a dispatcher task forwards the events of a bounded fan-in channel;
+//!   events are either broadcast or sent to a single state machine
+//! - delayed events and timers are tasks sleeping via `tokio::time`
+//! - an `EventInjector` injects events from outside the state machines
+//! - a `ShutdownHandle` stops all tasks after draining the events in flight
+//!
+//! No thread is spawned; `TokioContext::spawn` and `run` must be called
+//! within a tokio runtime. Event processing is synchronous, a state machine
+//! blocks the worker thread of its task while it processes an event.
+//!
+use log::{debug, error, warn};
+use std::{fmt::Debug, sync::Arc, time::Duration};
+use tokio::{
+    sync::mpsc::{
+        self,
+        error::{TryRecvError, TrySendError},
+    },
+    task::{AbortHandle, JoinHandle},
+    time::Instant,
+};
+
+use qlrl::{MachineId, PublishError, StateMachine, StateMachineContext, TimerHandle};
+
+mod timer;
+use timer::Timers;
+mod injector;
+pub use injector::EventInjector;
+mod shutdown;
+use shutdown::{join_all, Drain, InFlight};
+pub use shutdown::{ShutdownError, ShutdownHandle, TaskPanic, DEFAULT_DRAIN_TIMEOUT};
+
+/// Capacity of the event channels used by `TokioContext::new`
+pub const DEFAULT_QUEUE_CAPACITY: usize = 100;
+
+/// Subscription filter deciding which broadcast events a state machine receives
+pub type Subscription<E> = fn(&E) -> bool;
+
+#[derive(Clone, Debug)]
+pub enum ContextEvent<E> {
+    Start,
+    Stop,
+    /// Event for all subscribed state machines
+    Envelope(E),
+    /// Event for a single state machine
+    Addressed(MachineId, E),
+}
+
+/// Push into the fan-in channel without waiting
+fn push<E>(queue: &mpsc::Sender<ContextEvent<E>>, m: ContextEvent<E>) -> Result<(), PublishError> {
+    queue.try_send(m).map_err(|error| match error {
+        TrySendError::Full(_) => PublishError::QueueFull,
+        TrySendError::Closed(_) => PublishError::Stopped,
+    })
+}
+
+pub struct TaskContext<E: Clone + Debug + Send + 'static> {
+    id: MachineId,
+    queue: mpsc::Sender<ContextEvent<E>>,
+    timers: Timers<ContextEvent<E>>,
+}
+
+impl<E: Clone + Debug + Send + 'static> StateMachineContext<E> for TaskContext<E> {
+    /// Publish an event; events the fan-in channel does not accept are
+    /// logged and dropped
+    fn publish_event(&mut self, e: E) {
+        match self.try_publish_event(e) {
+            Ok(()) => (),
+            Err(PublishError::Stopped) => debug!("Event dropped: runtime stopped"),
+            Err(reason) => error!("Event dropped: {}", reason),
+        }
+    }
+
+    /// Publish an event; a full fan-in channel refuses the event as the
+    /// state machine task must not wait
+    fn try_publish_event(&mut self, e: E) -> Result<(), PublishError> {
+        push(&self.queue, ContextEvent::Envelope(e))
+    }
+
+    /// Send an event to a single state machine; failures are logged
+    fn send_to(&mut self, target: MachineId, e: E) {
+        if let Err(reason) = push(&self.queue, ContextEvent::Addressed(target, e)) {
+            error!("Event for {:?} dropped: {}", target, reason);
+        }
+    }
+
+    /// Publish an event to all subscribed state machines after a delay
+    fn publish_delayed_event(&mut self, delay_in_ms: u64, e: E) {
+        let millis = Duration::from_millis(delay_in_ms);
+        self.timers.schedule(millis, ContextEvent::Envelope(e));
+    }
+
+    /// Send an event to this state machine after a delay
+    fn arm_timer(&mut self, delay_in_ms: u64, e: E) -> TimerHandle {
+        let millis = Duration::from_millis(delay_in_ms);
+        TimerHandle(self.timers.schedule(millis, ContextEvent::Addressed(self.id, e)))
+    }
+
+    fn arm_periodic_timer(&mut self, period_in_ms: u64, e: E) -> TimerHandle {
+        let millis = Duration::from_millis(period_in_ms);
+        TimerHandle(self.timers.schedule_periodic(millis, ContextEvent::Addressed(self.id, e)))
+    }
+
+    fn cancel_timer(&mut self, handle: TimerHandle) -> bool {
+        self.timers.cancel(handle.0)
+    }
+}
+
+async fn sm_task<E: Clone + Debug + Send + 'static>(
+    sm: Box<dyn StateMachine<E> + Send>,
+    mut context: TaskContext<E>,
+    mut rx: mpsc::Receiver<ContextEvent<E>>,
+    drain: Arc<Drain>,
+) {
+    debug!("Task {:?}: started", context.id);
+    let mut sm = sm;
+    while let Some(request) = rx.recv().await {
+        match request {
+            ContextEvent::Start => {
+                debug!("Task {:?}: Receives start event", context.id);
+                sm.start(&mut context);
+            }
+            ContextEvent::Stop => {
+                debug!("Task {:?}: Receives stop event", context.id);
+                sm.stop(&mut context);
+                break;
+            }
+            ContextEvent::Envelope(event) | ContextEvent::Addressed(_, event) => {
+                let _processed = InFlight(&drain);
+                sm.dispatch(&mut context, event);
+            }
+        }
+    }
+    debug!("Task {:?}: finished", context.id);
+}
+
+/// Channel of the dispatcher to a state machine task
+struct Route<E> {
+    tx: mpsc::Sender<ContextEvent<E>>,
+    subscription: Option<Subscription<E>>,
+}
+
+/// Forward an event to a state machine task counting it as in flight
+async fn forward<E>(route: &Route<E>, drain: &Drain, m: ContextEvent<E>) {
+    drain.forwarded();
+    if route.tx.send(m).await.is_err() {
+        drain.processed(); // the state machine task is gone
+    }
+}
+
+/// Forward an event of the fan-in channel to the receiving state machines
+async fn distribute<E: Clone>(routes: &[Route<E>], drain: &Drain, m: ContextEvent<E>) {
+    match m {
+        ContextEvent::Addressed(MachineId(index), _) if index >= routes.len() => {
+            warn!("Dispatcher: Event for unknown state machine {} dropped", index);
+        }
+        ContextEvent::Addressed(MachineId(index), _) => forward(&routes[index], drain, m).await,
+        ContextEvent::Envelope(event) => {
+            // the event is owned, `E` needs not be `Sync`
+            for route in routes {
+                if route.subscription.is_none_or(|subscribed| subscribed(&event)) {
+                    forward(route, drain, ContextEvent::Envelope(event.clone())).await;
+                }
+            }
+        }
+        ContextEvent::Start | ContextEvent::Stop => (), // sent by the dispatcher only
+    }
+}
+
+/// Start the state machines, then forward the events of the fan-in channel
+/// to the state machine tasks
+///
+/// On stop the events in flight are drained until all state machines are
+/// idle or the drain timeout expires, then the state machines are stopped.
+async fn dispatcher<E: Clone>(mut queue: mpsc::Receiver<ContextEvent<E>>, routes: Vec<Route<E>>, drain: Arc<Drain>) {
+    // start before any queued event is forwarded, e.g. an injected one
+    for route in &routes {
+        let _ = route.tx.send(ContextEvent::Start).await;
+    }
+    loop {
+        let m = tokio::select! {
+            biased;
+            _ = drain.stopped() => break,
+            m = queue.recv() => m,
+        };
+        match m {
+            Some(m) => distribute(&routes, &drain, m).await,
+            None => break,
+        }
+    }
+    debug!("Dispatcher: Drain events in flight");
+    let deadline = Instant::now() + drain.timeout();
+    loop {
+        match queue.try_recv() {
+            Ok(m) => distribute(&routes, &drain, m).await,
+            Err(TryRecvError::Empty) if drain.in_flight() == 0 => break,
+            Err(TryRecvError::Empty) if Instant::now() >= deadline => {
+                warn!("Dispatcher: Drain timeout, {} events in flight", drain.in_flight());
+                break;
+            }
+            Err(TryRecvError::Empty) => tokio::time::sleep(Duration::from_millis(1)).await,
+            Err(TryRecvError::Disconnected) => break,
+        }
+    }
+    // refuse further events, i.e. the ones published by exit actions
+    queue.close();
+    for route in &routes {
+        let _ = route.tx.send(ContextEvent::Stop).await;
+    }
+    debug!("Dispatcher: finished");
+}
+
+/// State machine added but not yet spawned
+struct Pending<E: Clone + Debug + Send + 'static> {
+    id: MachineId,
+    state_machine: Box<dyn StateMachine<E> + Send>,
+    rx: mpsc::Receiver<ContextEvent<E>>,
+}
+
+/// Tokio Context for state machines
+///
+/// - Each task runs a state machine
+/// - Each task publishes events (multiple producer, multiple consumer)
+///   - a bounded fan-in channel is used for multiple producer single
+///     consumer; publishing never waits, a full channel refuses the event
+///   - a dispatcher task is the single consumer
+///   - the dispatcher forwards published events to all state machines whose
+///     subscription accepts them, sent events to the addressed state machine
+///     only; every state machine task has a channel of its own
+///   - the events to be distributed need to implement the Clone trait
+/// - Timer tasks send delayed events into the fan-in channel; timer events
+///   are sent to the state machine that armed the timer
+/// - `run` waits until the runtime is stopped, `spawn` returns a
+///   `RuntimeHandle`
+/// - A `ShutdownHandle` or Ctrl-C stops the runtime: the events in flight
+///   are drained, each state machine exits its active state and all tasks
+///   finish
+///
+/// # Example
+///
+/// ```ignore
+///
+/// let mut context = TokioContext::<u8>::new();
+///
+/// // Register the state machines
+/// let id = context.add(StateMachine::<u8>::new("Some State machine on u8 events"));
+/// context.add_subscribed(StateMachine::<u8>::new("Some other State machine on even events"),
+///     |e| e % 2 == 0);
+///
+/// // run the state machines until stopped via Ctrl-C
+/// context.run().await?;
+///
+/// // or run them alongside other tasks
+/// let runtime = context.spawn();
+/// runtime.inject_to(id, 42).await?;
+/// runtime.stop();
+/// runtime.join().await?;
+///
+/// ```
+pub struct TokioContext<E>
+where
+    E: Clone + Debug + Send + 'static,
+{
+    queue: mpsc::Sender<ContextEvent<E>>,
+    fan_in: mpsc::Receiver<ContextEvent<E>>,
+    capacity: usize,
+    routes: Vec<Route<E>>,
+    pending: Vec<Pending<E>>,
+    timers: Timers<ContextEvent<E>>,
+    drain: Arc<Drain>,
+}
+
+impl<E> TokioContext<E>
+where
+    E: Clone + Debug + Send + 'static,
+{
+    /// Create a context with channels of `DEFAULT_QUEUE_CAPACITY` events
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_QUEUE_CAPACITY)
+    }
+
+    /// Create a context with channels of `capacity` events
+    pub fn with_capacity(capacity: usize) -> Self {
+        debug!("new: Start state machine runtime context using tokio tasks and channels");
+        let (queue, fan_in) = mpsc::channel(capacity); // set up fan-in
+        let timers = Timers::new(queue.clone());
+
+        TokioContext::<E> {
+            queue,
+            fan_in,
+            capacity,
+            routes: vec![],
+            pending: vec![],
+            timers,
+            drain: Arc::new(Drain::new()),
+        }
+    }
+
+    /// Handle to stop the runtime from code, e.g. from another task
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle::new(self.drain.clone())
+    }
+
+    /// Handle to inject events from outside, e.g. from another task
+    ///
+    /// Events injected before the runtime is started are processed after start.
+    pub fn injector(&self) -> EventInjector<E> {
+        EventInjector::new(self.queue.clone())
+    }
+
+    /// Add a state machine receiving all published events
+    ///
+    /// Returns the id to send events to the state machine.
+    pub fn add(&mut self, state_machine: Box<dyn StateMachine<E> + Send>) -> MachineId {
+        self.add_route(state_machine, None)
+    }
+
+    /// Add a state machine receiving the published events its subscription
+    /// accepts; events sent to the state machine are always received
+    ///
+    /// Returns the id to send events to the state machine.
+    pub fn add_subscribed(
+        &mut self,
+        state_machine: Box<dyn StateMachine<E> + Send>,
+        subscription: Subscription<E>,
+    ) -> MachineId {
+        self.add_route(state_machine, Some(subscription))
+    }
+
+    fn add_route(
+        &mut self,
+        state_machine: Box<dyn StateMachine<E> + Send>,
+        subscription: Option<Subscription<E>>,
+    ) -> MachineId {
+        let id = MachineId(self.routes.len());
+        let (tx, rx) = mpsc::channel(self.capacity); // set up fan out
+        self.routes.push(Route { tx, subscription });
+        self.pending.push(Pending { id, state_machine, rx });
+        debug!("add: State machine {:?} added", id);
+        id
+    }
+
+    /// Start the state machines as tokio tasks
+    ///
+    /// The returned handle injects events and stops and joins the runtime.
+    /// Ctrl-C is not handled unless `RuntimeHandle::stop_on_ctrl_c` is called.
+    pub fn spawn(self) -> RuntimeHandle<E> {
+        debug!("spawn: function invoked");
+        let mut tasks = vec![];
+        for Pending { id, state_machine, rx } in self.pending {
+            let context = TaskContext {
+                id,
+                queue: self.queue.clone(),
+                timers: self.timers.clone(),
+            };
+            let drain = self.drain.clone();
+            tasks.push((format!("sm-{}", id.0), tokio::spawn(sm_task(state_machine, context, rx, drain))));
+        }
+        let drain = self.drain.clone();
+        tasks.push(("dispatcher".into(), tokio::spawn(dispatcher(self.fan_in, self.routes, drain))));
+        debug!("spawn: Message dispatcher task started");
+
+        RuntimeHandle {
+            queue: self.queue,
+            shutdown: ShutdownHandle::new(self.drain),
+            tasks,
+            timers: self.timers,
+            ctrl_c: None,
+        }
+    }
+
+    /// Run the state machines until the runtime is stopped via a
+    /// `ShutdownHandle` or Ctrl-C
+    ///
+    /// Returns the tasks that panicked, if any.
+    pub async fn run(self) -> Result<(), ShutdownError> {
+        let mut runtime = self.spawn();
+        runtime.stop_on_ctrl_c();
+        runtime.join().await
+    }
+}
+
+impl<E> Default for TokioContext<E>
+where
+    E: Clone + Debug + Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Handle of a spawned `TokioContext`
+///
+/// Dropping the handle stops the runtime; the tasks finish in the background.
+pub struct RuntimeHandle<E>
+where
+    E: Clone + Debug + Send + 'static,
+{
+    queue: mpsc::Sender<ContextEvent<E>>,
+    shutdown: ShutdownHandle,
+    tasks: Vec<(String, JoinHandle<()>)>,
+    timers: Timers<ContextEvent<E>>,
+    ctrl_c: Option<AbortHandle>,
+}
+
+impl<E> RuntimeHandle<E>
+where
+    E: Clone + Debug + Send + 'static,
+{
+    /// Publish an event to all subscribed state machines from outside, wait
+    /// for room if the fan-in channel is full
+    pub async fn inject(&self, e: E) -> Result<(), PublishError> {
+        self.injector().inject(e).await
+    }
+
+    /// Send an event to a single state machine from outside, wait for room
+    /// if the fan-in channel is full
+    pub async fn inject_to(&self, target: MachineId, e: E) -> Result<(), PublishError> {
+        self.injector().to(target).inject(e).await
+    }
+
+    /// Handle to inject events, e.g. from another task
+    pub fn injector(&self) -> EventInjector<E> {
+        EventInjector::new(self.queue.clone())
+    }
+
+    /// Handle to stop the runtime, e.g. from another task
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Stop the runtime; see `ShutdownHandle::stop`
+    pub fn stop(&self) {
+        self.shutdown.stop();
+    }
+
+    /// Stop the runtime on Ctrl-C
+    pub fn stop_on_ctrl_c(&mut self) {
+        let shutdown = self.shutdown.clone();
+        let task = tokio::spawn(async move {
+            match tokio::signal::ctrl_c().await {
+                Ok(()) => shutdown.stop(),
+                Err(reason) => warn!("Ctrl-C handler not set: {}", reason),
+            }
+        });
+        if let Some(previous) = self.ctrl_c.replace(task.abort_handle()) {
+            previous.abort();
+        }
+    }
+
+    /// Wait until the runtime is stopped and all tasks are finished
+    ///
+    /// Returns the tasks that panicked, if any.
+    pub async fn join(mut self) -> Result<(), ShutdownError> {
+        let result = join_all(core::mem::take(&mut self.tasks)).await;
+        debug!("join: all tasks joined");
+        result
+    }
+}
+
+impl<E> Drop for RuntimeHandle<E>
+where
+    E: Clone + Debug + Send + 'static,
+{
+    fn drop(&mut self) {
+        if !self.tasks.is_empty() {
+            self.stop();
+        }
+        self.timers.cancel_all();
+        if let Some(ctrl_c) = self.ctrl_c.take() {
+            ctrl_c.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
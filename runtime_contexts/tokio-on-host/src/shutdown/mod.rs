@@ -0,0 +1,159 @@
+//! Programmatic shutdown of a `TokioContext`
+//!
+//! - stopping lets the dispatcher drain the events in flight, bounded by a timeout
+//! - afterwards every state machine exits its active state and its task ends
+//! - panicked tasks are reported when the tasks are joined
+//!
+use log::debug;
+use std::{
+    any::Any,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{sync::Notify, task::JoinHandle};
+
+/// Time to process the events in flight used by `ShutdownHandle::stop`
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Bookkeeping of the dispatcher to stop and drain the events in flight
+pub(crate) struct Drain {
+    in_flight: AtomicUsize, // forwarded to but not yet processed by the state machines
+    timeout_ms: AtomicU64,
+    stop: Notify,
+}
+
+impl Drain {
+    pub(crate) fn new() -> Self {
+        Drain {
+            in_flight: AtomicUsize::new(0),
+            timeout_ms: AtomicU64::new(DEFAULT_DRAIN_TIMEOUT.as_millis() as u64),
+            stop: Notify::new(),
+        }
+    }
+
+    pub(crate) fn forwarded(&self) {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn processed(&self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms.load(Ordering::SeqCst))
+    }
+
+    /// Wait until the runtime is stopped; a stop requested before is kept
+    pub(crate) async fn stopped(&self) {
+        self.stop.notified().await;
+    }
+}
+
+/// Marks an event as processed when dropped, also if the state machine
+/// panics processing it
+pub(crate) struct InFlight<'d>(pub(crate) &'d Drain);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.processed();
+    }
+}
+
+/// Cloneable handle to stop a `TokioContext` from code
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    drain: Arc<Drain>,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(drain: Arc<Drain>) -> Self {
+        ShutdownHandle { drain }
+    }
+
+    /// Stop the runtime; events in flight are processed for at most
+    /// `DEFAULT_DRAIN_TIMEOUT`
+    pub fn stop(&self) {
+        self.stop_with_timeout(DEFAULT_DRAIN_TIMEOUT);
+    }
+
+    /// Stop the runtime; events in flight are processed for at most
+    /// `timeout`, remaining events are dropped
+    ///
+    /// Events published before are processed first. Stopping does not wait
+    /// for the fan-in channel to have room. Stopping a stopped runtime does
+    /// nothing.
+    pub fn stop_with_timeout(&self, timeout: Duration) {
+        let timeout_ms = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+        self.drain.timeout_ms.store(timeout_ms, Ordering::SeqCst);
+        debug!("Shutdown: stop requested");
+        self.drain.stop.notify_one();
+    }
+}
+
+/// A task of the runtime that panicked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskPanic {
+    /// Name of the task i.e. `sm-<machine id>` or `dispatcher`
+    pub task: String,
+    /// Panic message if it is a string
+    pub message: String,
+}
+
+/// Failures detected when the runtime tasks are joined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownError {
+    pub panicked: Vec<TaskPanic>,
+}
+
+impl fmt::Display for ShutdownError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} task(s) panicked", self.panicked.len())?;
+        for panic in &self.panicked {
+            write!(f, "; {}: {}", panic.task, panic.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ShutdownError {}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".into()
+    }
+}
+
+/// Wait for all named tasks and collect the panicked ones
+pub(crate) async fn join_all(tasks: Vec<(String, JoinHandle<()>)>) -> Result<(), ShutdownError> {
+    let mut panicked = vec![];
+    for (task, handle) in tasks {
+        match handle.await {
+            Ok(()) => (),
+            Err(error) if error.is_panic() => panicked.push(TaskPanic {
+                task,
+                message: panic_message(error.into_panic().as_ref()),
+            }),
+            Err(_) => debug!("Shutdown: task {} cancelled", task),
+        }
+    }
+    if panicked.is_empty() {
+        Ok(())
+    } else {
+        Err(ShutdownError { panicked })
+    }
+}
+
+#[cfg(test)]
+mod tests;
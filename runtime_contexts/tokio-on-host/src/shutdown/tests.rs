@@ -0,0 +1,26 @@
+use super::*;
+
+#[tokio::test]
+async fn join_all_succeeds_without_panics() {
+    let tasks = vec![("a".to_string(), tokio::spawn(async {}))];
+    assert_eq!(Ok(()), join_all(tasks).await);
+}
+
+#[tokio::test]
+async fn join_all_reports_panicked_tasks() {
+    let tasks = vec![
+        ("ok".to_string(), tokio::spawn(async {})),
+        ("failing".to_string(), tokio::spawn(async { panic!("boom") })),
+    ];
+    let error = join_all(tasks).await.unwrap_err();
+    assert_eq!(vec![TaskPanic { task: "failing".into(), message: "boom".into() }], error.panicked);
+    assert_eq!("1 task(s) panicked; failing: boom", error.to_string());
+}
+
+#[tokio::test]
+async fn stop_before_waiting_is_kept() {
+    let drain = Arc::new(Drain::new());
+    ShutdownHandle::new(drain.clone()).stop_with_timeout(Duration::from_millis(20));
+    drain.stopped().await;
+    assert_eq!(Duration::from_millis(20), drain.timeout());
+}
@@ -0,0 +1,205 @@
+use super::*;
+use qlrl::{fsm::FiniteStateMachine, state_machine, ProcessingResult};
+use std::sync::Mutex;
+
+fn is_even(e: &u8) -> bool {
+    e.is_multiple_of(2)
+}
+
+/// Run the dispatcher on the given events for a subscriber to all events
+/// and a subscriber to even events
+async fn dispatch(events: Vec<ContextEvent<u8>>) -> (Vec<ContextEvent<u8>>, Vec<ContextEvent<u8>>) {
+    let (queue, fan_in) = mpsc::channel(10);
+    let (all_tx, mut all_rx) = mpsc::channel(10);
+    let (even_tx, mut even_rx) = mpsc::channel(10);
+    let routes = vec![
+        Route { tx: all_tx, subscription: None },
+        Route { tx: even_tx, subscription: Some(is_even as Subscription<u8>) },
+    ];
+    for event in events {
+        queue.try_send(event).unwrap();
+    }
+    drop(queue);
+    dispatcher(fan_in, routes, Arc::new(Drain::new())).await;
+    let mut all = vec![];
+    while let Ok(m) = all_rx.try_recv() {
+        all.push(m);
+    }
+    let mut even = vec![];
+    while let Ok(m) = even_rx.try_recv() {
+        even.push(m);
+    }
+    (all, even)
+}
+
+fn events(received: &[ContextEvent<u8>]) -> Vec<u8> {
+    received
+        .iter()
+        .filter_map(|m| match m {
+            ContextEvent::Envelope(e) | ContextEvent::Addressed(_, e) => Some(*e),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn published_events_respect_subscriptions() {
+    let (all, even) = dispatch((1..=4).map(ContextEvent::Envelope).collect()).await;
+    assert_eq!(vec![1, 2, 3, 4], events(&all));
+    assert_eq!(vec![2, 4], events(&even));
+}
+
+#[tokio::test]
+async fn sent_events_reach_addressed_machine_only() {
+    let (all, even) = dispatch(vec![
+        ContextEvent::Addressed(MachineId(1), 3),
+        ContextEvent::Addressed(MachineId(0), 4),
+        ContextEvent::Addressed(MachineId(2), 5),
+    ])
+    .await;
+    assert_eq!(vec![4], events(&all));
+    assert_eq!(vec![3], events(&even)); // not filtered by the subscription
+}
+
+#[tokio::test]
+async fn start_and_stop_reach_all_machines() {
+    let (all, even) = dispatch(vec![]).await;
+    for received in [all, even] {
+        assert!(matches!(received[..], [ContextEvent::Start, ContextEvent::Stop]));
+    }
+}
+
+//----------------------------------------------------------------------------
+// runtime tests with a state machine counting down published events
+
+type Trace = Arc<Mutex<Vec<String>>>;
+
+struct Counter {
+    trace: Trace,
+}
+
+fn counter_exit<'a>(data: &'a mut Counter, _context: &mut (dyn StateMachineContext<u8> + 'a)) {
+    data.trace.lock().unwrap().push("exit".into());
+}
+
+fn counter_dispatch<'a>(
+    data: &'a mut Counter,
+    context: &mut (dyn StateMachineContext<u8> + 'a),
+    event: &u8,
+) -> ProcessingResult<Counter, u8, CounterState> {
+    assert!(*event != 99, "boom");
+    data.trace.lock().unwrap().push(event.to_string());
+    match *event {
+        // count down in 10 ms steps
+        100.. => {
+            context.arm_timer(10, event - 100);
+        }
+        1.. => context.publish_event(event - 1),
+        0 => (),
+    }
+    ProcessingResult::Handled
+}
+
+state_machine! {
+    #[derive(Debug, PartialEq)]
+    enum CounterState;
+
+    const COUNTER_STATES: [State<Counter, u8>] = [
+        Counting { exit: counter_exit, dispatch: counter_dispatch },
+    ];
+}
+
+fn counter(trace: &Trace) -> Box<dyn StateMachine<u8> + Send> {
+    Box::new(FiniteStateMachine::new(&COUNTER_STATES, Counter { trace: trace.clone() }))
+}
+
+#[tokio::test]
+async fn stop_drains_events_in_flight_and_exits_states() {
+    let trace = Trace::default();
+    let mut context = TokioContext::new();
+    context.add(counter(&trace));
+    let runtime = context.spawn();
+    runtime.inject(20).await.unwrap();
+    runtime.shutdown_handle().stop_with_timeout(Duration::from_millis(1000));
+    assert_eq!(Ok(()), runtime.join().await);
+    let trace = trace.lock().unwrap();
+    let expected: Vec<String> = (0..=20).rev().map(|n: u8| n.to_string()).chain(["exit".into()]).collect();
+    assert_eq!(expected, *trace);
+}
+
+#[tokio::test]
+async fn stop_reports_panicked_state_machines() {
+    let trace = Trace::default();
+    let mut context = TokioContext::new();
+    context.add(counter(&trace));
+    let failing = context.add_subscribed(counter(&trace), |_| false);
+    let runtime = context.spawn();
+    runtime.inject_to(failing, 99).await.unwrap();
+    runtime.shutdown_handle().stop_with_timeout(Duration::from_millis(10));
+    let error = runtime.join().await.unwrap_err();
+    assert_eq!(1, error.panicked.len());
+    assert_eq!("sm-1", error.panicked[0].task);
+    assert_eq!("boom", error.panicked[0].message);
+}
+
+#[tokio::test]
+async fn panicked_state_machine_does_not_delay_stop() {
+    let trace = Trace::default();
+    let mut context = TokioContext::new();
+    let failing = context.add(counter(&trace));
+    let runtime = context.spawn();
+    runtime.inject_to(failing, 99).await.unwrap();
+    let start = std::time::Instant::now();
+    runtime.shutdown_handle().stop_with_timeout(Duration::from_secs(10));
+    assert!(runtime.join().await.is_err());
+    // the event the state machine panicked on is not in flight anymore
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn injected_events_respect_subscriptions() {
+    let all = Trace::default();
+    let even = Trace::default();
+    let mut context = TokioContext::new();
+    context.add(counter(&all));
+    context.add_subscribed(counter(&even), |e| e.is_multiple_of(2));
+    let runtime = context.spawn();
+    runtime.inject(1).await.unwrap(); // the first state machine publishes 0 then
+    runtime.stop();
+    assert_eq!(Ok(()), runtime.join().await);
+    assert_eq!(vec!["1", "0", "exit"], *all.lock().unwrap());
+    assert_eq!(vec!["0", "exit"], *even.lock().unwrap());
+}
+
+#[tokio::test(start_paused = true)]
+async fn timers_send_to_the_arming_state_machine() {
+    let trace = Trace::default();
+    let other = Trace::default();
+    let mut context = TokioContext::new();
+    let id = context.add_subscribed(counter(&trace), |_| false);
+    context.add(counter(&other));
+    let runtime = context.spawn();
+    let start = Instant::now();
+    runtime.inject_to(id, 102).await.unwrap(); // arms a timer for 2
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    runtime.stop();
+    assert_eq!(Ok(()), runtime.join().await);
+    assert_eq!(vec!["102", "2", "exit"], *trace.lock().unwrap());
+    // the counter publishes its count down, only the other one subscribes
+    assert_eq!(vec!["1", "0", "exit"], *other.lock().unwrap());
+    assert!(start.elapsed() >= Duration::from_millis(10));
+}
+
+#[tokio::test]
+async fn injector_drives_state_machines_from_other_threads() {
+    let trace = Trace::default();
+    let mut context = TokioContext::new();
+    let id = context.add_subscribed(counter(&trace), |_| false); // ignores its count down
+    let injector = context.injector().to(id);
+    injector.try_inject(1).unwrap(); // processed after start
+    let runtime = context.spawn();
+    tokio::task::spawn_blocking(move || injector.blocking_inject(3)).await.unwrap().unwrap();
+    runtime.stop();
+    assert_eq!(Ok(()), runtime.join().await);
+    assert_eq!(vec!["1", "3", "exit"], *trace.lock().unwrap());
+}
@@ -0,0 +1,134 @@
+//! Timers on top of `tokio::time`
+//!
+//! - every armed timer is a task sleeping until it is due
+//! - a fired timer sends its item into the fan-in channel
+//! - periodic timers send a copy of their item on every tick
+//! - cancelling a timer aborts its task; a timer cancelled while it is
+//!   firing does not send its item
+//!
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc,
+    task::AbortHandle,
+    time::{self, Instant},
+};
+
+/// Tasks of the pending timers by timer id
+struct Armed {
+    next: AtomicU64,
+    tasks: Mutex<HashMap<u64, AbortHandle>>,
+}
+
+impl Armed {
+    fn lock(&self) -> MutexGuard<'_, HashMap<u64, AbortHandle>> {
+        // a panicking task cannot leave the map inconsistent
+        self.tasks.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn is_armed(&self, id: u64) -> bool {
+        self.lock().contains_key(&id)
+    }
+
+    fn disarm(&self, id: u64) -> Option<AbortHandle> {
+        self.lock().remove(&id)
+    }
+}
+
+/// Cloneable handle to arm and cancel timers sending into a channel
+pub(crate) struct Timers<T> {
+    queue: mpsc::Sender<T>,
+    armed: Arc<Armed>,
+}
+
+impl<T> Clone for Timers<T> {
+    fn clone(&self) -> Self {
+        Timers {
+            queue: self.queue.clone(),
+            armed: self.armed.clone(),
+        }
+    }
+}
+
+impl<T: Send + 'static> Timers<T> {
+    pub(crate) fn new(queue: mpsc::Sender<T>) -> Self {
+        Timers {
+            queue,
+            armed: Arc::new(Armed {
+                next: AtomicU64::new(0),
+                tasks: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Spawn the task of a timer; it is registered before it can fire
+    fn arm<F>(&self, task: impl FnOnce(u64, mpsc::Sender<T>, Arc<Armed>) -> F) -> u64
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        let id = self.armed.next.fetch_add(1, Ordering::Relaxed);
+        let mut tasks = self.armed.lock();
+        let handle = tokio::spawn(task(id, self.queue.clone(), self.armed.clone()));
+        tasks.insert(id, handle.abort_handle());
+        id
+    }
+
+    /// Send `item` into the channel after `delay`
+    ///
+    /// Returns the id to cancel the timer.
+    pub(crate) fn schedule(&self, delay: Duration, item: T) -> u64 {
+        self.arm(move |id, queue, armed| async move {
+            time::sleep(delay).await;
+            if armed.disarm(id).is_some() {
+                let _ = queue.send(item).await;
+            }
+        })
+    }
+
+    /// Send a copy of `item` into the channel every `period`
+    ///
+    /// Returns the id to cancel the timer.
+    pub(crate) fn schedule_periodic(&self, period: Duration, item: T) -> u64
+    where
+        T: Clone,
+    {
+        let period = period.max(Duration::from_millis(1));
+        self.arm(move |id, queue, armed| async move {
+            let mut ticks = time::interval_at(Instant::now() + period, period);
+            loop {
+                ticks.tick().await;
+                if !armed.is_armed(id) || queue.send(item.clone()).await.is_err() {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Cancel a pending timer; returns `false` if the timer already fired
+    /// or is unknown
+    pub(crate) fn cancel(&self, id: u64) -> bool {
+        match self.armed.disarm(id) {
+            Some(task) => {
+                task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cancel all pending timers
+    pub(crate) fn cancel_all(&self) {
+        for (_, task) in self.armed.lock().drain() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
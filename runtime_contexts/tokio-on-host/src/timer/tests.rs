@@ -0,0 +1,62 @@
+use super::*;
+
+fn timers() -> (Timers<u8>, mpsc::Receiver<u8>) {
+    let (tx, rx) = mpsc::channel(10);
+    (Timers::new(tx), rx)
+}
+
+#[tokio::test(start_paused = true)]
+async fn timers_fire_in_due_order() {
+    let (timers, mut rx) = timers();
+    let start = Instant::now();
+    timers.schedule(Duration::from_millis(60), 3);
+    timers.schedule(Duration::from_millis(20), 1);
+    timers.schedule(Duration::from_millis(40), 2);
+    assert_eq!(Some(1), rx.recv().await);
+    assert_eq!(Duration::from_millis(20), start.elapsed());
+    assert_eq!(Some(2), rx.recv().await);
+    assert_eq!(Some(3), rx.recv().await);
+    assert_eq!(Duration::from_millis(60), start.elapsed());
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancel_pending_timer() {
+    let (timers, mut rx) = timers();
+    let cancelled = timers.schedule(Duration::from_millis(10), 1);
+    timers.schedule(Duration::from_millis(20), 2);
+    assert!(timers.cancel(cancelled));
+    assert_eq!(Some(2), rx.recv().await);
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancel_fired_timer_fails() {
+    let (timers, mut rx) = timers();
+    let fired = timers.schedule(Duration::ZERO, 1);
+    assert_eq!(Some(1), rx.recv().await);
+    assert!(!timers.cancel(fired));
+    assert!(!timers.cancel(42));
+}
+
+#[tokio::test(start_paused = true)]
+async fn periodic_timer_fires_until_cancelled() {
+    let (timers, mut rx) = timers();
+    let start = Instant::now();
+    let periodic = timers.schedule_periodic(Duration::from_millis(10), 7);
+    for _ in 0..3 {
+        assert_eq!(Some(7), rx.recv().await);
+    }
+    assert_eq!(Duration::from_millis(30), start.elapsed());
+    assert!(timers.cancel(periodic));
+    timers.schedule(Duration::from_millis(50), 8);
+    assert_eq!(Some(8), rx.recv().await);
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancel_all_discards_pending_timers() {
+    let (timers, mut rx) = timers();
+    timers.schedule(Duration::from_millis(10), 1);
+    timers.schedule_periodic(Duration::from_millis(10), 2);
+    timers.cancel_all();
+    time::sleep(Duration::from_millis(100)).await;
+    assert!(rx.try_recv().is_err());
+}